@@ -7,23 +7,20 @@ fn main() {
     println!("======================================================");
 
     let mut cfg = OffsetCfg::default();
-    cfg.svg_orig = false;
     let poly_orig = pline_01()[0].clone();
     let poly = polyline_translate(&poly_orig, point(250.0, 100.0));
 
+    let offs: Vec<f64> = (0..100).map(|i| (i as f64) / 5.0).collect();
+
     let start = Instant::now();
 
     for _ in 0..100 {
         // Forward direction
-        for i in 0..100 {
-            offset_polyline_to_polyline(&poly, (i as f64) / 5.0, &mut cfg);
-        }
+        offset_polyline_multiple(&poly, &offs, &mut cfg);
 
         // Reverse direction
         let poly = polyline_reverse(&poly);
-        for i in 0..100 {
-            offset_polyline_to_polyline(&poly, (i as f64) / 5.0, &mut cfg);
-        }
+        offset_polyline_multiple(&poly, &offs, &mut cfg);
     }
 
     let total_time = start.elapsed();
@@ -7,7 +7,6 @@ fn main() {
     println!("======================================================");
 
     let mut cfg = OffsetCfg::default();
-    cfg.svg_orig = false;
     let arc_orig = arcline200();
 
     let start = Instant::now();
@@ -1,12 +1,12 @@
 use togo::{poly::arcline200, prelude::*};
-use offroad::{offset::offset_arcline_to_arcline, prelude::OffsetCfg};
+use offroad::{offset::offset_arcline_to_arcline, prelude::{OffsetCfg, SvgObserver}};
 
 fn main() {
     let mut cfg = OffsetCfg::default();
-    let mut svg = SVG::new(600.0, 600.0, Some("/tmp/debug_multi200.svg"));
-    cfg.svg = Some(&mut svg);
-    cfg.svg_orig = true;
-    cfg.svg_final = true;
+    let mut svg = SvgObserver::new(SVG::new(600.0, 600.0, Some("/tmp/debug_multi200.svg")));
+    svg.orig = true;
+    svg.output = true;
+    cfg.observer = Some(&mut svg);
 
     let poly_orig = arcline200();
     println!("Original arcline200 has {} arcs", poly_orig.len());
@@ -31,9 +31,7 @@ fn main() {
     println!("\n=== FINAL RESULTS ===");
     println!("Total arclines: {}", total_offsets.len());
     
-    if let Some(svg) = cfg.svg.as_mut() {
-        svg.write_stroke_width(0.1);
-    }
+    svg.svg.write_stroke_width(0.1);
     
     println!("\nExpected: 228 total arclines");
     println!("Got: {} total arclines", total_offsets.len());
@@ -1,15 +1,15 @@
 use togo::prelude::*;
-use offroad::{offset::offset_arcline_to_arcline, prelude::OffsetCfg};
+use offroad::{offset::offset_arcline_to_arcline, prelude::{OffsetCfg, SvgObserver}};
 
 fn main() {
     // Configuration for offsetting
     let mut cfg = OffsetCfg::default();
-    let mut svg = SVG::new(300.0, 300.0, Some("/tmp/arcline.svg"));
-    cfg.svg = Some(&mut svg);
+    let mut svg = SvgObserver::new(SVG::new(300.0, 300.0, Some("/tmp/arcline.svg")));
     // Show original arcline in SVG output
-    cfg.svg_orig = true;
+    svg.orig = true;
     // Show final offset arclines in SVG output
-    cfg.svg_final = true;
+    svg.output = true;
+    cfg.observer = Some(&mut svg);
 
     let arc0 = arc_from_bulge(point(40.0, 100.0), point(140.0, 200.0), 0.0);
     let arc1 = arc_from_bulge(point(140.0, 200.0), point(240.0, 100.0), 0.5);
@@ -29,10 +29,8 @@ fn main() {
         println!("Arcline {}: {} vertices", i, arcline.len());
     }
 
-    if let Some(svg) = cfg.svg.as_mut() {
-        // Write svg to file
-        svg.write_stroke_width(0.1);
-    }
+    // Write svg to file
+    svg.svg.write_stroke_width(0.1);
     assert!(
         offset_arclines.len() == 1,
         "Wrong number of offset arclines generated"
@@ -3,18 +3,16 @@ use offroad::prelude::*;
 
 fn main() {
     let mut cfg = OffsetCfg::default();
-    let mut svg = SVG::new(800.0, 800.0, Some("/tmp/arcline200.svg"));
-    cfg.svg = Some(&mut svg);
-    cfg.svg_orig = true;
-    cfg.svg_final = true;
+    let mut svg = SvgObserver::new(SVG::new(800.0, 800.0, Some("/tmp/arcline200.svg")));
+    svg.orig = true;
+    svg.output = true;
+    cfg.observer = Some(&mut svg);
 
     let poly = arcline200();
     let offset_polylines = offset_arcline_to_arcline(&poly, 5.0, &mut cfg);
 
-    if let Some(svg) = cfg.svg.as_mut(){
-        // Write svg to file
-        svg.write_stroke_width(0.1);
-    }
+    // Write svg to file
+    svg.svg.write_stroke_width(0.1);
 
     assert_eq!(offset_polylines.len(), 1, "Expected exactly 1 offset polyline");
     assert_eq!(offset_polylines[0].len(), 337);
@@ -1,13 +1,13 @@
 use togo::prelude::*;
-use offroad::prelude::{OffsetCfg, offset_polyline_to_polyline, pline_01};
+use offroad::prelude::{OffsetCfg, SvgObserver, offset_polyline_to_polyline, pline_01};
 
 fn main() {
     let mut cfg = OffsetCfg::default();
     // This will create an SVG file at /tmp/pline1.svg
-    let mut svg = SVG::new(600.0, 600.0, Some("/tmp/multi.svg"));
-    cfg.svg = Some(&mut svg);
-    cfg.svg_orig = true;
-    cfg.svg_final = true;
+    let mut svg = SvgObserver::new(SVG::new(600.0, 600.0, Some("/tmp/multi.svg")));
+    svg.orig = true;
+    svg.output = true;
+    cfg.observer = Some(&mut svg);
 
     let poly_orig = pline_01()[0].clone();
     // Translate to fit in the SVG viewport
@@ -27,10 +27,8 @@ fn main() {
         offset_internal.extend(offset);
     }
 
-    if let Some(svg) = cfg.svg.as_mut(){
-        // Write svg to file
-        svg.write_stroke_width(0.1);
-    }
+    // Write svg to file
+    svg.svg.write_stroke_width(0.1);
 
     assert!(
         offset_external.len() == 99,
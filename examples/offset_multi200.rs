@@ -1,13 +1,13 @@
 use togo::{poly::arcline200, prelude::*};
-use offroad::{offset::offset_arcline_to_arcline, prelude::{offset_polyline_to_polyline, pline_01, OffsetCfg}};
+use offroad::{offset::offset_arcline_to_arcline, prelude::{offset_polyline_to_polyline, pline_01, OffsetCfg, SvgObserver}};
 
 fn main() {
     let mut cfg = OffsetCfg::default();
     // This will create an SVG file at /tmp/pline1.svg
-    let mut svg = SVG::new(800.0, 800.0, Some("/tmp/multi200.svg"));
-    cfg.svg = Some(&mut svg);
-    cfg.svg_orig = true;
-    cfg.svg_final = true;
+    let mut svg = SvgObserver::new(SVG::new(800.0, 800.0, Some("/tmp/multi200.svg")));
+    svg.orig = true;
+    svg.output = true;
+    cfg.observer = Some(&mut svg);
 
     let arcs_orig = arcline200();
     // Translate to fit in the SVG viewport
@@ -27,10 +27,8 @@ fn main() {
         offset_internal.extend(offset);
     }
 
-    if let Some(svg) = cfg.svg.as_mut(){
-        // Write svg to file
-        svg.write_stroke_width(0.1);
-    }
+    // Write svg to file
+    svg.svg.write_stroke_width(0.1);
 }
 
 
@@ -1,15 +1,15 @@
 use togo::prelude::*;
-use offroad::prelude::{OffsetCfg, offset_polyline_to_polyline};
+use offroad::prelude::{OffsetCfg, SvgObserver, offset_polyline_to_polyline};
 
 fn main() {
     // Configuration for offsetting
     let mut cfg = OffsetCfg::default();
-    let mut svg = SVG::new(300.0, 300.0, Some("/tmp/polyline.svg"));
-    cfg.svg = Some(&mut svg);
+    let mut svg = SvgObserver::new(SVG::new(300.0, 300.0, Some("/tmp/polyline.svg")));
     // Show original polyline in SVG output
-    cfg.svg_orig = true;
+    svg.orig = true;
     // Show final offset polylines in SVG output
-    cfg.svg_final = true;
+    svg.output = true;
+    cfg.observer = Some(&mut svg);
 
     let poly_orig = vec![
         pvertex(point(0.0, 0.0), 0.0),
@@ -29,9 +29,7 @@ fn main() {
         println!("Polyline {}: {} vertices", i, polyline.len());
     }
 
-    if let Some(svg) = cfg.svg.as_mut() {
-        // Write svg to file
-        svg.write_stroke_width(0.1);
-    }
+    // Write svg to file
+    svg.svg.write_stroke_width(0.1);
     assert!(offset_polylines.len() == 1);
 }
@@ -0,0 +1,327 @@
+//! Signed area and point containment of closed Polylines and Arclines.
+//!
+//! The sign of the area gives the orientation of the shape: positive for
+//! counter-clockwise, negative for clockwise (in a coordinate system with the Y axis up).
+//! It is used to find the inside of a shape independently of its vertex order.
+
+use togo::prelude::*;
+
+use crate::tolerances::EPS_VERTEX;
+
+/// Orientation of a closed loop, the direction it is cut in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    /// Counter-clockwise, positive signed area
+    Ccw,
+    /// Clockwise, negative signed area
+    Cw,
+}
+
+/// Computes the signed area of a closed Polyline, including the bulge segments.
+///
+/// # Arguments
+///
+/// * `poly` - The input polyline, expected to be a closed shape.
+///
+/// # Returns
+///
+/// Positive area for counter-clockwise polylines, negative for clockwise polylines.
+/// Degenerate segments are ignored.
+///
+/// # Examples
+///
+/// ```rust
+/// use togo::prelude::*;
+/// use offroad::prelude::*;
+///
+/// let poly = vec![
+///     pvertex(point(0.0, 0.0), 0.0),
+///     pvertex(point(10.0, 0.0), 0.0),
+///     pvertex(point(10.0, 10.0), 0.0),
+///     pvertex(point(0.0, 10.0), 0.0),
+/// ];
+/// assert_eq!(polyline_signed_area(&poly), 100.0);
+/// assert_eq!(polyline_signed_area(&polyline_reverse(&poly)), -100.0);
+/// ```
+pub fn polyline_signed_area(poly: &Polyline) -> f64 {
+    let n = poly.len();
+    let mut forward = Vec::with_capacity(n);
+    let mut reversed = Vec::new();
+    for i in 0..n {
+        let v0 = poly[i];
+        let v1 = poly[(i + 1) % n];
+        if v0.p == v1.p {
+            continue;
+        }
+        // Arcs are CCW, negative bulge arc is traversed from b to a
+        let seg = arc_from_bulge(v0.p, v1.p, v0.b);
+        if v0.b < 0.0 {
+            reversed.push(seg);
+        } else {
+            forward.push(seg);
+        }
+    }
+    arcline_area(&forward) - arcline_area(&reversed)
+}
+
+/// Computes the signed area of a closed Arcline.
+///
+/// The traversal direction of each arc is found from its connection to the next arc,
+/// so the arcs do not need to be oriented a to b along the Arcline.
+/// Arc ends closer than `Tolerances::default().vertex` are connected,
+/// see `arcline_signed_area_with_tolerance` for input of other sizes.
+///
+/// # Arguments
+///
+/// * `arcs` - The input arcline, expected to be a closed shape.
+///
+/// # Returns
+///
+/// Positive area for counter-clockwise arclines, negative for clockwise arclines.
+pub fn arcline_signed_area(arcs: &Arcline) -> f64 {
+    arcline_signed_area_with_tolerance(arcs, EPS_VERTEX)
+}
+
+/// Same as `arcline_signed_area`, arc ends closer than `tolerance` are connected.
+///
+/// # Examples
+///
+/// ```rust
+/// use togo::prelude::*;
+/// use offroad::prelude::*;
+///
+/// // Clockwise half disc 100 km wide, the arc is cut from b to a,
+/// // and its ends are 1e-6 from the ends of the lines
+/// let arcs = vec![
+///     arc(point(-5e4, 0.0), point(5e4, 0.0), point(0.0, 0.0), 5e4),
+///     arcseg(point(0.0, 1e-6), point(-5e4, 1e-6)),
+///     arcseg(point(0.0, 1e-6), point(5e4, 1e-6)),
+/// ];
+/// let tol = Tolerances::default().scaled_to(1e5);
+/// assert!(arcline_signed_area_with_tolerance(&arcs, tol.vertex) < 0.0);
+/// ```
+pub fn arcline_signed_area_with_tolerance(arcs: &Arcline, tolerance: f64) -> f64 {
+    traversal_area(arcs, &arcline_forward(arcs, true, tolerance))
+}
+
+// Signed area of the closed Arcline with the given traversal directions
+fn traversal_area(arcs: &Arcline, directions: &[bool]) -> f64 {
+    let mut forward = Vec::with_capacity(arcs.len());
+    let mut reversed = Vec::new();
+    for (seg, is_forward) in arcs.iter().zip(directions) {
+        if *is_forward {
+            forward.push(*seg);
+        } else {
+            reversed.push(*seg);
+        }
+    }
+    arcline_area(&forward) - arcline_area(&reversed)
+}
+
+// Traversal direction of every arc of the Arcline, true when the arc goes from a to b.
+// The direction of the arcs of a closed Arcline is found from the connection to the next arc.
+// Open Arclines continue from the end of the previous arc, only the first arc
+// is found from the connection to the next arc.
+// Arc ends closer than the tolerance are connected.
+//
+// Both arcs of a closed loop of two arcs connect at both ends, such loops are
+// read counter-clockwise, see arcline_oriented.
+pub(crate) fn arcline_forward(arcs: &Arcline, closed: bool, tolerance: f64) -> Vec<bool> {
+    let n = arcs.len();
+    let mut forward: Vec<bool> = Vec::with_capacity(n);
+    for i in 0..n {
+        let seg = arcs[i];
+        if !closed && i > 0 {
+            let prev_end = if forward[i - 1] { arcs[i - 1].b } else { arcs[i - 1].a };
+            forward.push(!prev_end.close_enough(seg.b, tolerance) || prev_end.close_enough(seg.a, tolerance));
+            continue;
+        }
+        let next = arcs[(i + 1) % n];
+        let connects = |p: Point| p.close_enough(next.a, tolerance) || p.close_enough(next.b, tolerance);
+        forward.push(connects(seg.b) || !connects(seg.a));
+    }
+    forward
+}
+
+// Arcs of the closed Arcline in the order they are cut with the given orientation,
+// and the traversal direction of every arc (see arcline_forward).
+// The cut starts at the start of the first arc in the connection order.
+pub(crate) fn arcline_oriented(
+    arcs: &Arcline,
+    orientation: Orientation,
+    tolerance: f64,
+) -> (Arcline, Vec<bool>) {
+    let forward = arcline_forward(arcs, true, tolerance);
+    let ccw = traversal_area(arcs, &forward) > 0.0;
+    if ccw == (orientation == Orientation::Ccw) {
+        (arcs.clone(), forward)
+    } else {
+        (
+            arcs.iter().rev().copied().collect(),
+            forward.iter().rev().map(|f| !f).collect(),
+        )
+    }
+}
+
+/// Checks if the point is inside of a closed Polyline, using the even-odd rule.
+///
+/// Points on the boundary can be reported either inside or outside.
+pub(crate) fn polyline_contains_point(poly: &Polyline, p: Point) -> bool {
+    let n = poly.len();
+    let mut inside = false;
+    for i in 0..n {
+        let a = poly[i].p;
+        let b = poly[(i + 1) % n].p;
+        // Chord crossing with the ray from p to +x
+        if (a.y > p.y) != (b.y > p.y) {
+            let x = a.x + (p.y - a.y) * (b.x - a.x) / (b.y - a.y);
+            if x > p.x {
+                inside = !inside;
+            }
+        }
+        let bulge = poly[i].b;
+        if bulge != 0.0 && a != b {
+            // The arc and the chord enclose a circular segment,
+            // the ray crosses the arc once more (or less) than the chord when p is inside it.
+            // Positive bulge arcs are on the right side of the chord.
+            let arc = arc_from_bulge(a, b, bulge);
+            let side = (b - a).perp(p - a);
+            let arc_side = if bulge > 0.0 { side < 0.0 } else { side > 0.0 };
+            if arc_side && (p - arc.c).norm() < arc.r {
+                inside = !inside;
+            }
+        }
+    }
+    inside
+}
+
+#[cfg(test)]
+mod test_area {
+    use super::*;
+    use crate::tolerances::Tolerances;
+
+    fn square() -> Polyline {
+        vec![
+            pvertex(point(0.0, 0.0), 0.0),
+            pvertex(point(10.0, 0.0), 0.0),
+            pvertex(point(10.0, 10.0), 0.0),
+            pvertex(point(0.0, 10.0), 0.0),
+        ]
+    }
+
+    #[test]
+    fn test_polyline_signed_area_square() {
+        assert_eq!(polyline_signed_area(&square()), 100.0);
+        assert_eq!(polyline_signed_area(&polyline_reverse(&square())), -100.0);
+    }
+
+    #[test]
+    fn test_polyline_signed_area_bulge() {
+        // Circle from two half circles, radius 5
+        let circle = vec![pvertex(point(0.0, 0.0), 1.0), pvertex(point(10.0, 0.0), 1.0)];
+        let expected = std::f64::consts::PI * 25.0;
+        assert!((polyline_signed_area(&circle) - expected).abs() < 1e-10);
+        let circle_cw = vec![pvertex(point(0.0, 0.0), -1.0), pvertex(point(10.0, 0.0), -1.0)];
+        assert!((polyline_signed_area(&circle_cw) + expected).abs() < 1e-10);
+
+        // Square with one side bulging out by a half circle
+        let mut poly = square();
+        poly[0].b = 1.0;
+        let expected = 100.0 + std::f64::consts::PI * 25.0 / 2.0;
+        assert!((polyline_signed_area(&poly) - expected).abs() < 1e-10);
+        let expected = -expected;
+        assert!((polyline_signed_area(&polyline_reverse(&poly)) - expected).abs() < 1e-10);
+    }
+
+    #[test]
+    fn test_polyline_signed_area_pline_01() {
+        let poly = crate::poly::pline_01()[0].clone();
+        let area = polyline_signed_area(&poly);
+        assert!(area != 0.0);
+        let reversed = polyline_signed_area(&polyline_reverse(&poly));
+        assert!((area + reversed).abs() < 1e-6 * area.abs());
+    }
+
+    #[test]
+    fn test_arcline_signed_area() {
+        // Square with one reversed segment and one arc bulging out
+        let arcs = vec![
+            arc_from_bulge(point(0.0, 0.0), point(10.0, 0.0), 1.0),
+            arcseg(point(10.0, 10.0), point(10.0, 0.0)),
+            arcseg(point(10.0, 10.0), point(0.0, 10.0)),
+            arcseg(point(0.0, 10.0), point(0.0, 0.0)),
+        ];
+        let expected = 100.0 + std::f64::consts::PI * 25.0 / 2.0;
+        assert!((arcline_signed_area(&arcs) - expected).abs() < 1e-10);
+
+        // Clockwise, the arc is traversed from b to a
+        let arcs = vec![
+            arcseg(point(0.0, 0.0), point(0.0, 10.0)),
+            arcseg(point(0.0, 10.0), point(10.0, 10.0)),
+            arcseg(point(10.0, 10.0), point(10.0, 0.0)),
+            arc_from_bulge(point(0.0, 0.0), point(10.0, 0.0), 1.0),
+        ];
+        assert!((arcline_signed_area(&arcs) + expected).abs() < 1e-10);
+    }
+
+    #[test]
+    fn test_arcline_signed_area_scaled() {
+        // Clockwise half disc 100 km wide, the ends of the arc are 1e-6 from the lines
+        let arcs = vec![
+            arc(point(-5e4, 0.0), point(5e4, 0.0), point(0.0, 0.0), 5e4),
+            arcseg(point(0.0, 1e-6), point(-5e4, 1e-6)),
+            arcseg(point(0.0, 1e-6), point(5e4, 1e-6)),
+        ];
+        let expected = -std::f64::consts::PI * 5e4 * 5e4 / 2.0;
+        // The default tolerance does not connect the arc, it is read counter-clockwise
+        assert!(arcline_signed_area(&arcs) > 0.0);
+        let tol = Tolerances::default().scaled_to(1e5);
+        assert!(arcline_forward(&arcs, true, tol.vertex) == vec![false, false, true]);
+        let area = arcline_signed_area_with_tolerance(&arcs, tol.vertex);
+        assert!((area - expected).abs() < 1e-6 * expected.abs());
+    }
+
+    #[test]
+    fn test_arcline_oriented() {
+        // Circle of two arcs, both arcs connect at both ends
+        let circle = vec![
+            arc(point(5.0, 0.0), point(-5.0, 0.0), point(0.0, 0.0), 5.0),
+            arc(point(-5.0, 0.0), point(5.0, 0.0), point(0.0, 0.0), 5.0),
+        ];
+        let eps = Tolerances::default().vertex;
+        assert_eq!(arcline_forward(&circle, true, eps), vec![true, true]);
+        let (arcs, forward) = arcline_oriented(&circle, Orientation::Ccw, eps);
+        assert_eq!((arcs, forward), (circle.clone(), vec![true, true]));
+        // Clockwise from the same start point
+        let (arcs, forward) = arcline_oriented(&circle, Orientation::Cw, eps);
+        assert_eq!(arcs, vec![circle[1], circle[0]]);
+        assert_eq!(forward, vec![false, false]);
+        assert!(traversal_area(&arcs, &forward) < 0.0);
+        assert_eq!(arcs[0].b, circle[0].a);
+
+        // Open path around the circle continues from the first arc
+        let path = vec![arcseg(point(9.0, 0.0), point(5.0, 0.0)), circle[1], circle[0]];
+        assert_eq!(arcline_forward(&path, false, eps), vec![true, false, false]);
+    }
+
+    #[test]
+    fn test_polyline_contains_point() {
+        let poly = square();
+        assert!(polyline_contains_point(&poly, point(5.0, 5.0)));
+        assert!(!polyline_contains_point(&poly, point(15.0, 5.0)));
+        assert!(!polyline_contains_point(&poly, point(-5.0, 5.0)));
+        assert!(polyline_contains_point(&polyline_reverse(&poly), point(5.0, 5.0)));
+
+        // Bottom side bulging out, the half circle is inside
+        let mut poly = square();
+        poly[0].b = 1.0;
+        assert!(polyline_contains_point(&poly, point(5.0, -4.0)));
+        assert!(!polyline_contains_point(&poly, point(5.0, -6.0)));
+        assert!(!polyline_contains_point(&poly, point(0.5, -4.0)));
+        // Bottom side bulging in, the half circle is outside
+        poly[0].b = -1.0;
+        assert!(!polyline_contains_point(&poly, point(5.0, 4.0)));
+        assert!(polyline_contains_point(&poly, point(5.0, 6.0)));
+        assert!(polyline_contains_point(&poly, point(0.5, 4.0)));
+    }
+}
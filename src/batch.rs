@@ -0,0 +1,163 @@
+//! Parallel offsetting of many Polylines, or of one Polyline at many distances.
+//!
+//! The offsets run on `std::thread::scope` workers, one per available CPU, all with
+//! the same `OffsetOptions`. The budget is shared: its cancel flag stops the whole batch.
+//! Observers are not used, as the workers can not share them.
+
+use std::{
+    panic,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+use togo::prelude::*;
+
+use crate::{
+    error::OffsetError,
+    offset::{OffsetCfg, OffsetOptions, try_offset_polyline_to_polyline},
+};
+
+/// Computes the offsets of many closed Polylines in parallel.
+///
+/// Every polyline is offset separately, as `try_offset_polyline_to_polyline` does,
+/// the results are in the order of the input.
+///
+/// # Examples
+///
+/// ```rust
+/// use togo::prelude::*;
+/// use offroad::prelude::*;
+///
+/// let options = OffsetOptions::default();
+/// let square = |size: f64| vec![
+///     pvertex(point(0.0, 0.0), 0.0),
+///     pvertex(point(0.0, size), 0.0),
+///     pvertex(point(size, size), 0.0),
+///     pvertex(point(size, 0.0), 0.0),
+/// ];
+/// let polys: Vec<Polyline> = (1..=8).map(|i| square(i as f64 * 10.0)).collect();
+///
+/// let res = offset_polylines_parallel(&polys, 1.0, &options);
+/// assert_eq!(res.len(), 8);
+/// assert_eq!(res[7].as_ref().unwrap()[0].len(), 4);
+/// ```
+pub fn offset_polylines_parallel(
+    polys: &[Polyline],
+    off: f64,
+    options: &OffsetOptions,
+) -> Vec<Result<Vec<Polyline>, OffsetError>> {
+    run_parallel(polys.len(), |i| {
+        let mut cfg = OffsetCfg::from(*options);
+        try_offset_polyline_to_polyline(&polys[i], off, &mut cfg)
+    })
+}
+
+/// Computes the offsets of a closed Polyline at many distances in parallel.
+///
+/// The result of every distance is the result of `try_offset_polyline_to_polyline`,
+/// in the order of `offs`.
+pub fn offset_distances_parallel(
+    poly: &Polyline,
+    offs: &[f64],
+    options: &OffsetOptions,
+) -> Vec<Result<Vec<Polyline>, OffsetError>> {
+    run_parallel(offs.len(), |i| {
+        let mut cfg = OffsetCfg::from(*options);
+        try_offset_polyline_to_polyline(poly, offs[i], &mut cfg)
+    })
+}
+
+// Runs f for the indices 0..n on the available CPUs, the results are in index order
+fn run_parallel<R: Send>(n: usize, f: impl Fn(usize) -> R + Sync) -> Vec<R> {
+    let workers = thread::available_parallelism().map_or(1, |cpus| cpus.get()).min(n);
+    if workers <= 1 {
+        return (0..n).map(f).collect();
+    }
+    // Workers take the next index when they are done, long offsets do not hold up the others
+    let next = AtomicUsize::new(0);
+    let mut done: Vec<(usize, R)> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        if i >= n {
+                            return done;
+                        }
+                        done.push((i, f(i)));
+                    }
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap_or_else(|err| panic::resume_unwind(err)))
+            .collect()
+    });
+    done.sort_by_key(|(i, _)| *i);
+    done.into_iter().map(|(_, res)| res).collect()
+}
+
+#[cfg(test)]
+mod test_batch {
+    use super::*;
+    use crate::budget::Budget;
+    use std::sync::atomic::AtomicBool;
+
+    fn square(size: f64) -> Polyline {
+        vec![
+            pvertex(point(0.0, 0.0), 0.0),
+            pvertex(point(0.0, size), 0.0),
+            pvertex(point(size, size), 0.0),
+            pvertex(point(size, 0.0), 0.0),
+        ]
+    }
+
+    #[test]
+    fn test_options_shared() {
+        fn shared<T: Clone + Send + Sync>() {}
+        shared::<OffsetOptions>();
+    }
+
+    #[test]
+    fn test_polylines_parallel_in_order() {
+        let options = OffsetOptions::default();
+        // The third square collapses, the others keep their order
+        let polys: Vec<Polyline> = [10.0, 20.0, 1.0, 30.0, 40.0, 50.0, 60.0, 70.0, 80.0]
+            .iter()
+            .map(|&size| square(size))
+            .collect();
+        let res = offset_polylines_parallel(&polys, 2.0, &options);
+        assert_eq!(res.len(), polys.len());
+        for (poly, res) in polys.iter().zip(res.iter()) {
+            let mut cfg = OffsetCfg::default();
+            assert_eq!(*res, try_offset_polyline_to_polyline(poly, 2.0, &mut cfg));
+        }
+        assert_eq!(res[2], Err(OffsetError::Collapsed));
+        assert!(offset_polylines_parallel(&[], 2.0, &options).is_empty());
+    }
+
+    #[test]
+    fn test_distances_parallel() {
+        let poly = crate::poly::pline_01()[0].clone();
+        let offs: Vec<f64> = (1..=12).map(|i| i as f64 * 1.5).collect();
+        let options = OffsetOptions::default();
+        let res = offset_distances_parallel(&poly, &offs, &options);
+        for (&off, res) in offs.iter().zip(res.iter()) {
+            let mut cfg = OffsetCfg::default();
+            assert_eq!(*res, try_offset_polyline_to_polyline(&poly, off, &mut cfg));
+        }
+    }
+
+    #[test]
+    fn test_parallel_cancelled() {
+        let cancel = AtomicBool::new(true);
+        let options = OffsetOptions {
+            budget: Budget::default().with_cancel(&cancel),
+            ..OffsetOptions::default()
+        };
+        let res = offset_distances_parallel(&square(10.0), &[1.0, 2.0, 3.0], &options);
+        assert!(res.iter().all(|r| *r == Err(OffsetError::Cancelled)));
+    }
+}
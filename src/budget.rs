@@ -0,0 +1,106 @@
+//! Work budget and cancellation of the offsetting stages.
+//!
+//! The split, prune and cycle-finding stages check the budget while they run,
+//! and stop with an `OffsetError` when it is exhausted or the offset is cancelled.
+
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use crate::{error::OffsetError, offset_split_arcs::SPLIT_STEPS_LIMIT};
+
+/// Limits of the work spent on one offset.
+#[derive(Debug, Clone, Copy)]
+pub struct Budget<'a> {
+    /// Maximum number of split steps, see `OffsetError::SplitIterationLimit`
+    pub split_steps: usize,
+    /// Time after which the offset is stopped with `OffsetError::DeadlineExceeded`
+    pub deadline: Option<Instant>,
+    /// Flag that stops the offset with `OffsetError::Cancelled` when set,
+    /// for example from another thread
+    pub cancel: Option<&'a AtomicBool>,
+}
+
+impl Default for Budget<'_> {
+    fn default() -> Self {
+        Budget {
+            split_steps: SPLIT_STEPS_LIMIT,
+            deadline: None,
+            cancel: None,
+        }
+    }
+}
+
+impl<'a> Budget<'a> {
+    /// Returns the budget with the deadline set to `timeout` from now.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::time::Duration;
+    /// use offroad::prelude::*;
+    ///
+    /// let mut cfg = OffsetCfg::default();
+    /// cfg.options.budget = Budget::default().with_timeout(Duration::from_millis(500));
+    /// assert!(cfg.options.budget.check().is_ok());
+    /// ```
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Budget {
+            deadline: Instant::now().checked_add(timeout),
+            ..self
+        }
+    }
+
+    /// Returns the budget stopped by setting `cancel`.
+    pub fn with_cancel(self, cancel: &'a AtomicBool) -> Self {
+        Budget {
+            cancel: Some(cancel),
+            ..self
+        }
+    }
+
+    /// Checks that the offset is not cancelled and the deadline is not reached.
+    ///
+    /// # Errors
+    ///
+    /// `OffsetError::Cancelled` when the cancel flag is set,
+    /// `OffsetError::DeadlineExceeded` when the deadline has passed.
+    pub fn check(&self) -> Result<(), OffsetError> {
+        if let Some(cancel) = self.cancel
+            && cancel.load(Ordering::Relaxed)
+        {
+            return Err(OffsetError::Cancelled);
+        }
+        if let Some(deadline) = self.deadline
+            && Instant::now() >= deadline
+        {
+            return Err(OffsetError::DeadlineExceeded);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_budget {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        assert!(Budget::default().check().is_ok());
+
+        let cancel = AtomicBool::new(false);
+        let budget = Budget::default().with_cancel(&cancel);
+        assert!(budget.check().is_ok());
+        cancel.store(true, Ordering::Relaxed);
+        assert_eq!(budget.check(), Err(OffsetError::Cancelled));
+
+        let budget = Budget {
+            deadline: Some(Instant::now()),
+            ..Budget::default()
+        };
+        assert_eq!(budget.check(), Err(OffsetError::DeadlineExceeded));
+        let budget = Budget::default().with_timeout(Duration::from_secs(3600));
+        assert!(budget.check().is_ok());
+    }
+}
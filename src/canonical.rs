@@ -0,0 +1,128 @@
+//! Canonical form of the offset loops.
+//!
+//! The offset of the same input with the same configuration is the same bit for bit.
+//! The order of the loops and the vertex each loop starts at come from the internal stages,
+//! and can change when the stages change. The canonical form does not depend on them,
+//! so it can be compared across versions, or used as a cache key.
+
+use togo::prelude::*;
+
+use crate::contour_tree::ContourTree;
+
+/// Returns the loops in canonical form.
+///
+/// - Outer boundaries are counter-clockwise and holes are clockwise
+/// - Every loop starts at its vertex with the smallest x, then the smallest y
+/// - The loops are sorted by the bounding box of their vertices: by the smallest x,
+///   then the smallest y, the largest x and the largest y
+///
+/// # Examples
+///
+/// ```rust
+/// use togo::prelude::*;
+/// use offroad::prelude::*;
+///
+/// let square = |x: f64| vec![
+///     pvertex(point(x + 10.0, 10.0), 0.0),
+///     pvertex(point(x + 10.0, 0.0), 0.0),
+///     pvertex(point(x, 0.0), 0.0),
+///     pvertex(point(x, 10.0), 0.0),
+/// ];
+/// let res = canonical_polylines(vec![square(20.0), square(0.0)]);
+///
+/// assert_eq!(res[0][0].p, point(0.0, 0.0));
+/// assert_eq!(res[0][1].p, point(10.0, 0.0));
+/// assert_eq!(res[1][0].p, point(20.0, 0.0));
+/// ```
+pub fn canonical_polylines(polys: Vec<Polyline>) -> Vec<Polyline> {
+    let tree = ContourTree::new(polys);
+    let mut res: Vec<Polyline> = tree
+        .nodes
+        .into_iter()
+        .map(|node| {
+            let poly = if node.ccw == node.is_hole() {
+                polyline_reverse(&node.poly)
+            } else {
+                node.poly
+            };
+            rotate_to_start(poly)
+        })
+        .collect();
+    res.sort_by(|p0, p1| {
+        let (b0, b1) = (bounds(p0), bounds(p1));
+        b0.iter()
+            .zip(b1.iter())
+            .map(|(x0, x1)| x0.total_cmp(x1))
+            .find(|ord| ord.is_ne())
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    res
+}
+
+// Rotates the loop to start at the vertex with the smallest x, then the smallest y
+fn rotate_to_start(mut poly: Polyline) -> Polyline {
+    let start = (0..poly.len()).min_by(|&i, &j| {
+        let (p, q) = (poly[i].p, poly[j].p);
+        p.x.total_cmp(&q.x).then(p.y.total_cmp(&q.y))
+    });
+    if let Some(start) = start {
+        poly.rotate_left(start);
+    }
+    poly
+}
+
+// Bounding box of the vertices, min x, min y, max x, max y
+fn bounds(poly: &Polyline) -> [f64; 4] {
+    poly.iter().fold(
+        [f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY],
+        |b, v| [b[0].min(v.p.x), b[1].min(v.p.y), b[2].max(v.p.x), b[3].max(v.p.y)],
+    )
+}
+
+#[cfg(test)]
+mod test_canonical {
+    use super::*;
+    use crate::area::polyline_signed_area;
+
+    fn rect(x0: f64, y0: f64, x1: f64, y1: f64) -> Polyline {
+        vec![
+            pvertex(point(x0, y0), 0.0),
+            pvertex(point(x1, y0), 0.0),
+            pvertex(point(x1, y1), 0.0),
+            pvertex(point(x0, y1), 0.0),
+        ]
+    }
+
+    #[test]
+    fn test_canonical_orientation_and_order() {
+        let mut hole = polyline_reverse(&rect(2.0, 2.0, 8.0, 8.0));
+        hole.rotate_left(2);
+        // Hole given counter-clockwise, outer clockwise, island first
+        let polys = vec![
+            rect(20.0, 0.0, 25.0, 5.0),
+            polyline_reverse(&hole),
+            polyline_reverse(&rect(0.0, 0.0, 10.0, 10.0)),
+        ];
+        let res = canonical_polylines(polys);
+        assert_eq!(res.len(), 3);
+        assert!(polyline_signed_area(&res[0]) > 0.0);
+        assert!(polyline_signed_area(&res[1]) < 0.0);
+        assert!(polyline_signed_area(&res[2]) > 0.0);
+        assert_eq!(res[0][0].p, point(0.0, 0.0));
+        assert_eq!(res[1][0].p, point(2.0, 2.0));
+        assert_eq!(res[1][1].p, point(2.0, 8.0));
+        assert_eq!(res[2][0].p, point(20.0, 0.0));
+    }
+
+    #[test]
+    fn test_canonical_keeps_bulges() {
+        // Counter-clockwise half disc, the arc from (1, 0) to (-1, 0)
+        let poly = vec![
+            pvertex(point(1.0, 0.0), 1.0),
+            pvertex(point(-1.0, 0.0), 0.0),
+        ];
+        let res = canonical_polylines(vec![poly.clone()]);
+        assert_eq!(res[0], vec![poly[1], poly[0]]);
+        assert_eq!(canonical_polylines(res.clone()), res);
+    }
+}
@@ -0,0 +1,197 @@
+//! Containment tree of the loops of an offset.
+//!
+//! Offsets that split into islands, and offsets of regions, give several loops.
+//! `ContourTree` tells which loops are outer boundaries and which are holes,
+//! and which loop contains which.
+
+use togo::prelude::*;
+
+use crate::{
+    area::{polyline_contains_point, polyline_signed_area},
+    region::Region,
+};
+
+/// One loop of a `ContourTree`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContourNode {
+    /// The loop, with the orientation it was given
+    pub poly: Polyline,
+    /// Signed area of the loop, positive for counter-clockwise loops
+    pub signed_area: f64,
+    /// True when the loop is counter-clockwise
+    pub ccw: bool,
+    /// Number of loops containing this loop, even for outer boundaries and odd for holes
+    pub depth: usize,
+    /// Index of the smallest loop containing this loop
+    pub parent: Option<usize>,
+    /// Indices of the loops directly inside of this loop, larger loops first
+    pub children: Vec<usize>,
+}
+
+impl ContourNode {
+    /// Returns true when the loop is a hole, inside of an odd number of loops.
+    pub fn is_hole(&self) -> bool {
+        !self.depth.is_multiple_of(2)
+    }
+
+    // The loop in counter-clockwise (ccw = true) or clockwise orientation
+    fn oriented(&self, ccw: bool) -> Polyline {
+        if self.ccw == ccw {
+            self.poly.clone()
+        } else {
+            polyline_reverse(&self.poly)
+        }
+    }
+}
+
+/// Containment tree of non-intersecting closed loops.
+///
+/// # Examples
+///
+/// ```rust
+/// use togo::prelude::*;
+/// use offroad::prelude::*;
+///
+/// let square = |x0: f64, x1: f64| vec![
+///     pvertex(point(x0, x0), 0.0),
+///     pvertex(point(x1, x0), 0.0),
+///     pvertex(point(x1, x1), 0.0),
+///     pvertex(point(x0, x1), 0.0),
+/// ];
+/// let tree = ContourTree::new(vec![square(2.0, 8.0), square(0.0, 10.0), square(4.0, 6.0)]);
+///
+/// assert_eq!(tree.roots, vec![1]);
+/// assert_eq!(tree.nodes[1].children, vec![0]);
+/// assert!(tree.nodes[0].is_hole());
+/// assert_eq!(tree.nodes[2].parent, Some(0));
+/// assert_eq!(tree.nodes[2].depth, 2);
+/// assert_eq!(tree.regions().len(), 2);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ContourTree {
+    /// The loops, in the order they were given
+    pub nodes: Vec<ContourNode>,
+    /// Indices of the loops not contained in another loop, larger loops first
+    pub roots: Vec<usize>,
+}
+
+impl ContourTree {
+    /// Builds the containment tree of the loops.
+    ///
+    /// The loops are expected to be closed and not to intersect each other,
+    /// as the loops of an offset are.
+    pub fn new(loops: Vec<Polyline>) -> Self {
+        let areas: Vec<f64> = loops.iter().map(polyline_signed_area).collect();
+        // Larger loops first, so parents are placed before their children
+        let mut order: Vec<usize> = (0..loops.len()).collect();
+        order.sort_by(|&i, &j| areas[j].abs().total_cmp(&areas[i].abs()));
+
+        let mut nodes: Vec<ContourNode> = loops
+            .into_iter()
+            .zip(areas.iter())
+            .map(|(poly, &signed_area)| ContourNode {
+                poly,
+                signed_area,
+                ccw: signed_area > 0.0,
+                depth: 0,
+                parent: None,
+                children: Vec::new(),
+            })
+            .collect();
+        let mut roots = Vec::new();
+        for (k, &i) in order.iter().enumerate() {
+            let parent = nodes[i].poly.first().and_then(|v| {
+                // The smallest loop containing this loop is its parent
+                order[..k]
+                    .iter()
+                    .rev()
+                    .copied()
+                    .find(|&j| polyline_contains_point(&nodes[j].poly, v.p))
+            });
+            match parent {
+                Some(j) => {
+                    nodes[i].parent = Some(j);
+                    nodes[i].depth = nodes[j].depth + 1;
+                    nodes[j].children.push(i);
+                }
+                None => roots.push(i),
+            }
+        }
+        ContourTree { nodes, roots }
+    }
+
+    /// Groups the loops into regions, every outer boundary with the holes directly inside of it.
+    ///
+    /// Outer boundaries are counter-clockwise and holes are clockwise,
+    /// the regions are ordered by decreasing area of the outer boundary.
+    pub fn regions(&self) -> Vec<Region> {
+        let mut outers: Vec<usize> = (0..self.nodes.len())
+            .filter(|&i| !self.nodes[i].is_hole())
+            .collect();
+        outers.sort_by(|&i, &j| {
+            self.nodes[j].signed_area.abs().total_cmp(&self.nodes[i].signed_area.abs())
+        });
+        outers
+            .iter()
+            .map(|&i| {
+                let node = &self.nodes[i];
+                let holes = node
+                    .children
+                    .iter()
+                    .map(|&c| self.nodes[c].oriented(false))
+                    .collect();
+                Region::new(node.oriented(true), holes)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test_contour_tree {
+    use super::*;
+
+    fn rect(x0: f64, y0: f64, x1: f64, y1: f64) -> Polyline {
+        vec![
+            pvertex(point(x0, y0), 0.0),
+            pvertex(point(x1, y0), 0.0),
+            pvertex(point(x1, y1), 0.0),
+            pvertex(point(x0, y1), 0.0),
+        ]
+    }
+
+    #[test]
+    fn test_tree_nesting() {
+        // Two islands, the first one with a hole that contains another island
+        let loops = vec![
+            rect(0.0, 0.0, 10.0, 10.0),
+            polyline_reverse(&rect(2.0, 2.0, 8.0, 8.0)),
+            rect(4.0, 4.0, 6.0, 6.0),
+            rect(20.0, 0.0, 25.0, 5.0),
+        ];
+        let tree = ContourTree::new(loops);
+        assert_eq!(tree.roots, vec![0, 3]);
+        assert_eq!(tree.nodes[0].children, vec![1]);
+        assert_eq!(tree.nodes[1].children, vec![2]);
+        assert_eq!(tree.nodes[2].parent, Some(1));
+        assert_eq!(
+            tree.nodes.iter().map(|n| n.depth).collect::<Vec<_>>(),
+            vec![0, 1, 2, 0]
+        );
+        assert!(tree.nodes[0].ccw && !tree.nodes[1].ccw);
+        assert!((tree.nodes[1].signed_area + 36.0).abs() < 1e-9);
+        assert!(tree.nodes[1].is_hole() && !tree.nodes[2].is_hole());
+
+        let regions = tree.regions();
+        assert_eq!(regions.len(), 3);
+        assert_eq!(regions[0].holes.len(), 1);
+        assert!(polyline_signed_area(&regions[0].holes[0]) < 0.0);
+        assert!(regions[1].holes.is_empty() && regions[2].holes.is_empty());
+    }
+
+    #[test]
+    fn test_tree_empty() {
+        let tree = ContourTree::new(Vec::new());
+        assert!(tree.nodes.is_empty() && tree.roots.is_empty());
+        assert!(tree.regions().is_empty());
+    }
+}
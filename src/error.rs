@@ -0,0 +1,84 @@
+//! Error type returned by the fallible (`try_*`) offsetting functions.
+
+use std::fmt;
+
+/// Describes why an offset operation did not produce a result.
+///
+/// The infallible entry points (`offset_polyline_to_polyline`, `offset_arcline_to_arcline`)
+/// return an empty vector in all of these cases. The `try_*` variants report them separately,
+/// so that "the shape collapsed" can be told apart from "the library failed".
+#[derive(Debug, Clone, PartialEq)]
+pub enum OffsetError {
+    /// The input can not be offset, for example it has less than 2 vertices,
+    /// all its segments are degenerate, or the offset distance is not finite.
+    InvalidInput(String),
+    /// The input contains a NaN or infinite coordinate (or bulge).
+    /// Contains the index of the offending vertex (or arc).
+    NonFiniteCoordinate(usize),
+    /// Splitting the offset segments at their intersections did not finish
+    /// within the allowed number of split steps.
+    /// Contains the step limit that was reached.
+    SplitIterationLimit(usize),
+    /// Offset segments remained after pruning, but they could not be
+    /// reconnected into closed loops.
+    UnclosedResult,
+    /// Nothing is left after the offset, the shape collapsed.
+    Collapsed,
+    /// The offset was stopped by the cancel flag of `Budget`.
+    Cancelled,
+    /// The offset did not finish before the deadline of `Budget`.
+    DeadlineExceeded,
+    /// A lead-in or lead-out move comes closer to the input than the offset distance,
+    /// or crosses a contour.
+    LeadCollision,
+    /// A link between two loops of a toolpath comes closer to the walls than its ends.
+    LinkCollision,
+}
+
+impl fmt::Display for OffsetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OffsetError::InvalidInput(reason) => write!(f, "invalid input: {}", reason),
+            OffsetError::NonFiniteCoordinate(index) => {
+                write!(f, "non-finite coordinate at index {}", index)
+            }
+            OffsetError::SplitIterationLimit(steps) => {
+                write!(f, "split stage reached the limit of {} steps", steps)
+            }
+            OffsetError::UnclosedResult => write!(f, "offset segments do not form closed loops"),
+            OffsetError::Collapsed => write!(f, "offset collapsed, nothing is left"),
+            OffsetError::Cancelled => write!(f, "offset was cancelled"),
+            OffsetError::DeadlineExceeded => write!(f, "offset did not finish before the deadline"),
+            OffsetError::LeadCollision => write!(f, "lead move collides with the input or a contour"),
+            OffsetError::LinkCollision => {
+                write!(f, "link between loops comes closer to the walls than its ends")
+            }
+        }
+    }
+}
+
+impl std::error::Error for OffsetError {}
+
+#[cfg(test)]
+mod test_offset_error {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            OffsetError::NonFiniteCoordinate(3).to_string(),
+            "non-finite coordinate at index 3"
+        );
+        assert_eq!(
+            OffsetError::SplitIterationLimit(10).to_string(),
+            "split stage reached the limit of 10 steps"
+        );
+        assert_eq!(OffsetError::Collapsed.to_string(), "offset collapsed, nothing is left");
+    }
+
+    #[test]
+    fn test_is_std_error() {
+        let err: Box<dyn std::error::Error> = Box::new(OffsetError::UnclosedResult);
+        assert_eq!(err.to_string(), "offset segments do not form closed loops");
+    }
+}
@@ -0,0 +1,465 @@
+//! G-code output of offset Arclines and Polylines.
+//!
+//! Segments are written as G1 moves and arcs as G2 (clockwise) and G3 (counter-clockwise)
+//! moves, after a G0 rapid move to the start of the path.
+//! The arcs of Arclines are always counter-clockwise, an arc that is cut clockwise goes
+//! from `b` to `a` (see `arcs_to_polylines`) and is written as a G2 move.
+//! The arcs of an open path are cut in the direction that continues from the previous arc.
+//! Closed loops are cut in the given orientation, as the connections do not tell
+//! the direction of a loop of two arcs, such as the offsets of a circle.
+
+use std::f64::consts::{PI, TAU};
+
+use togo::prelude::*;
+
+use crate::{
+    area::{Orientation, arcline_forward, arcline_oriented},
+    tolerances::Tolerances,
+};
+
+/// Format of the arc moves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ArcFormat {
+    /// Center of the arc relative to its start point, `I` and `J` words
+    #[default]
+    IJ,
+    /// Radius of the arc, `R` word. Arcs are split to at most half circles,
+    /// as the radius does not tell the longer arc from the shorter one.
+    R,
+}
+
+/// Coordinates of the moves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GcodeMode {
+    /// Absolute coordinates, G90
+    #[default]
+    Absolute,
+    /// Coordinates relative to the previous point, G91.
+    /// The rapid move to the start of the path is absolute.
+    Incremental,
+}
+
+/// Configuration of the G-code output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GcodeCfg {
+    /// Format of the arc moves
+    pub arc_format: ArcFormat,
+    /// Absolute or incremental coordinates
+    pub mode: GcodeMode,
+    /// Number of decimals of the coordinates
+    pub precision: usize,
+    /// Largest sweep angle of an arc move in radians, longer arcs are split
+    pub max_sweep: f64,
+    /// Tolerances of the path, arc ends closer than `tolerances.vertex` are connected
+    /// when finding the direction of the arcs of an Arcline
+    pub tolerances: Tolerances,
+}
+
+impl Default for GcodeCfg {
+    fn default() -> Self {
+        GcodeCfg {
+            arc_format: ArcFormat::IJ,
+            mode: GcodeMode::Absolute,
+            precision: 4,
+            max_sweep: PI,
+            tolerances: Tolerances::default(),
+        }
+    }
+}
+
+/// Writes an Arcline as G-code.
+///
+/// `orientation` is the cutting direction of a closed loop, as the loops of
+/// `offset_arcline_to_arcline`, from the start of its first arc. It is `None` for an open
+/// path, as the result of `link_loops` and `add_leads`, that is cut from the first arc on,
+/// every arc in the direction that continues from the previous arc.
+/// Arc ends closer than `cfg.tolerances.vertex` are connected, set `cfg.tolerances`
+/// to the tolerances the path was computed with (see `Tolerances::scaled_to`).
+///
+/// # Examples
+///
+/// ```rust
+/// use togo::prelude::*;
+/// use offroad::prelude::*;
+///
+/// // Line and a clockwise half circle, cut from b to a
+/// let path = vec![
+///     arcseg(point(0.0, 0.0), point(10.0, 0.0)),
+///     arc(point(20.0, 0.0), point(10.0, 0.0), point(15.0, 0.0), 5.0),
+/// ];
+/// let gcode = arcline_to_gcode(&path, None, &GcodeCfg::default());
+/// assert_eq!(
+///     gcode,
+///     "G90\nG0 X0.0000 Y0.0000\nG1 X10.0000 Y0.0000\nG2 X20.0000 Y0.0000 I5.0000 J0.0000\n"
+/// );
+///
+/// // Circle of two arcs, cut clockwise
+/// let circle = vec![
+///     arc(point(5.0, 0.0), point(-5.0, 0.0), point(0.0, 0.0), 5.0),
+///     arc(point(-5.0, 0.0), point(5.0, 0.0), point(0.0, 0.0), 5.0),
+/// ];
+/// let gcode = arcline_to_gcode(&circle, Some(Orientation::Cw), &GcodeCfg::default());
+/// assert_eq!(
+///     gcode,
+///     "G90\nG0 X5.0000 Y0.0000\nG2 X-5.0000 Y0.0000 I-5.0000 J0.0000\nG2 X5.0000 Y0.0000 I5.0000 J0.0000\n"
+/// );
+/// ```
+pub fn arcline_to_gcode(arcs: &Arcline, orientation: Option<Orientation>, cfg: &GcodeCfg) -> String {
+    let eps = cfg.tolerances.vertex;
+    let (arcs, forward) = match orientation {
+        Some(orientation) => arcline_oriented(arcs, orientation, eps),
+        None => (arcs.clone(), arcline_forward(arcs, false, eps)),
+    };
+    let moves: Vec<(Arc, bool)> = arcs.into_iter().zip(forward).collect();
+    moves_to_gcode(&moves, cfg)
+}
+
+/// Writes a Polyline as G-code.
+///
+/// Positive bulges are counter-clockwise arcs, written as G3 moves, and negative bulges
+/// are clockwise arcs, written as G2 moves. `closed` adds the segment from the last vertex
+/// to the first one.
+///
+/// # Examples
+///
+/// ```rust
+/// use togo::prelude::*;
+/// use offroad::prelude::*;
+///
+/// let cfg = GcodeCfg {
+///     mode: GcodeMode::Incremental,
+///     precision: 2,
+///     ..GcodeCfg::default()
+/// };
+/// let poly = vec![
+///     pvertex(point(1.0, 1.0), 0.0),
+///     pvertex(point(4.0, 1.0), 0.0),
+///     pvertex(point(4.0, 3.0), 0.0),
+/// ];
+/// let gcode = polyline_to_gcode(&poly, true, &cfg);
+/// assert_eq!(
+///     gcode,
+///     "G90\nG0 X1.00 Y1.00\nG91\nG1 X3.00 Y0.00\nG1 X0.00 Y2.00\nG1 X-3.00 Y-2.00\nG90\n"
+/// );
+/// ```
+pub fn polyline_to_gcode(poly: &Polyline, closed: bool, cfg: &GcodeCfg) -> String {
+    let n = poly.len();
+    let count = if closed { n } else { n.saturating_sub(1) };
+    let moves: Vec<(Arc, bool)> = (0..count)
+        .map(|i| {
+            let (a, b) = (poly[i].p, poly[(i + 1) % n].p);
+            // Negative bulges give counter-clockwise arcs from b to a
+            (arc_from_bulge(a, b, poly[i].b), poly[i].b >= 0.0)
+        })
+        .collect();
+    moves_to_gcode(&moves, cfg)
+}
+
+// Writes the moves, every arc with its direction (true when it is cut from a to b)
+fn moves_to_gcode(moves: &[(Arc, bool)], cfg: &GcodeCfg) -> String {
+    let mut writer = GcodeWriter::new(cfg);
+    let start = match moves.first() {
+        Some((arc, true)) => arc.a,
+        Some((arc, false)) => arc.b,
+        None => return String::new(),
+    };
+    writer.rapid(start);
+    for (arc, forward) in moves.iter() {
+        if arc.is_seg() {
+            writer.line(if *forward { arc.b } else { arc.a });
+        } else {
+            let parts = split_arc(arc, max_sweep(cfg));
+            if *forward {
+                for (_, b) in parts {
+                    writer.arc(b, arc.c, arc.r, true);
+                }
+            } else {
+                // Clockwise, the parts from b to a
+                for (a, _) in parts.into_iter().rev() {
+                    writer.arc(a, arc.c, arc.r, false);
+                }
+            }
+        }
+    }
+    writer.finish()
+}
+
+// Largest sweep of the arc moves, R arcs are at most half circles
+fn max_sweep(cfg: &GcodeCfg) -> f64 {
+    let max_sweep = if cfg.max_sweep > 0.0 {
+        cfg.max_sweep
+    } else {
+        PI
+    };
+    match cfg.arc_format {
+        ArcFormat::IJ => max_sweep,
+        ArcFormat::R => max_sweep.min(PI),
+    }
+}
+
+// Parts of the counter-clockwise arc from a to b, with at most max_sweep each
+fn split_arc(arc: &Arc, max_sweep: f64) -> Vec<(Point, Point)> {
+    let angle = |p: Point| (p.y - arc.c.y).atan2(p.x - arc.c.x);
+    let start = angle(arc.a);
+    let mut sweep = (angle(arc.b) - start).rem_euclid(TAU);
+    if sweep == 0.0 {
+        sweep = TAU;
+    }
+    let parts = (sweep / max_sweep).ceil().max(1.0) as usize;
+    let point_at = |j: usize| {
+        if j == 0 {
+            return arc.a;
+        }
+        if j == parts {
+            return arc.b;
+        }
+        let theta = start + sweep * j as f64 / parts as f64;
+        arc.c + point(theta.cos(), theta.sin()) * arc.r
+    };
+    (0..parts).map(|j| (point_at(j), point_at(j + 1))).collect()
+}
+
+// Formats the moves, tracking the current point for incremental coordinates
+struct GcodeWriter<'a> {
+    cfg: &'a GcodeCfg,
+    out: String,
+    // Current point, rounded to the precision
+    current: Point,
+}
+
+impl<'a> GcodeWriter<'a> {
+    fn new(cfg: &'a GcodeCfg) -> Self {
+        GcodeWriter {
+            cfg,
+            out: String::from("G90\n"),
+            current: point(0.0, 0.0),
+        }
+    }
+
+    fn rapid(&mut self, p: Point) {
+        self.current = self.round(p);
+        let (x, y) = (self.num(self.current.x), self.num(self.current.y));
+        self.out.push_str(&format!("G0 X{} Y{}\n", x, y));
+        if self.cfg.mode == GcodeMode::Incremental {
+            self.out.push_str("G91\n");
+        }
+    }
+
+    fn line(&mut self, p: Point) {
+        let xy = self.xy(p);
+        self.out.push_str(&format!("G1 {}\n", xy));
+    }
+
+    fn arc(&mut self, p: Point, c: Point, r: f64, ccw: bool) {
+        // Center relative to the start point, in both modes
+        let (i, j) = (
+            self.num(c.x - self.current.x),
+            self.num(c.y - self.current.y),
+        );
+        let code = if ccw { "G3" } else { "G2" };
+        let xy = self.xy(p);
+        let center = match self.cfg.arc_format {
+            ArcFormat::IJ => format!("I{} J{}", i, j),
+            ArcFormat::R => format!("R{}", self.num(r)),
+        };
+        self.out.push_str(&format!("{} {} {}\n", code, xy, center));
+    }
+
+    // Coordinates of the end point, and moves the current point to it
+    fn xy(&mut self, p: Point) -> String {
+        let p = self.round(p);
+        let xy = match self.cfg.mode {
+            GcodeMode::Absolute => p,
+            // Differences of the rounded points, the increments add up to the rounded points
+            GcodeMode::Incremental => p - self.current,
+        };
+        self.current = p;
+        format!("X{} Y{}", self.num(xy.x), self.num(xy.y))
+    }
+
+    fn round(&self, p: Point) -> Point {
+        let scale = 10f64.powi(self.cfg.precision as i32);
+        point((p.x * scale).round() / scale, (p.y * scale).round() / scale)
+    }
+
+    fn num(&self, v: f64) -> String {
+        let s = format!("{:.*}", self.cfg.precision, v);
+        // No negative zero
+        if s.trim_start_matches('-')
+            .chars()
+            .all(|c| c == '0' || c == '.')
+        {
+            s.trim_start_matches('-').to_string()
+        } else {
+            s
+        }
+    }
+
+    fn finish(mut self) -> String {
+        if self.cfg.mode == GcodeMode::Incremental {
+            self.out.push_str("G90\n");
+        }
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod test_gcode {
+    use super::*;
+
+    fn lines(gcode: &str) -> Vec<&str> {
+        gcode.lines().collect()
+    }
+
+    #[test]
+    fn test_polyline_arc_directions() {
+        // Counter-clockwise and clockwise half circles
+        let poly = vec![
+            pvertex(point(0.0, 0.0), 1.0),
+            pvertex(point(10.0, 0.0), -1.0),
+            pvertex(point(20.0, 0.0), 0.0),
+        ];
+        let gcode = polyline_to_gcode(&poly, false, &GcodeCfg::default());
+        assert_eq!(
+            lines(&gcode),
+            vec![
+                "G90",
+                "G0 X0.0000 Y0.0000",
+                "G3 X10.0000 Y0.0000 I5.0000 J0.0000",
+                "G2 X20.0000 Y0.0000 I5.0000 J0.0000",
+            ]
+        );
+        // Cut backward, the directions flip
+        let gcode = polyline_to_gcode(&polyline_reverse(&poly), false, &GcodeCfg::default());
+        assert_eq!(
+            lines(&gcode)[2..],
+            [
+                "G3 X10.0000 Y0.0000 I-5.0000 J0.0000",
+                "G2 X0.0000 Y0.0000 I-5.0000 J0.0000",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_offset_loop_directions() {
+        // Offset loops of a counter-clockwise rounded square, both orientations
+        let mut cfg = crate::offset::OffsetCfg::default();
+        let square = crate::observer::polyline_arcs(
+            &vec![
+                pvertex(point(0.0, 0.0), 0.0),
+                pvertex(point(10.0, 0.0), 0.0),
+                pvertex(point(10.0, 10.0), 0.0),
+                pvertex(point(0.0, 10.0), 0.0),
+            ],
+            true,
+        );
+        // Outward offsets have arcs at the corners, one for every orientation
+        let mut outward = 0;
+        for arcs in [square.clone(), arcline_reverse(&square)] {
+            for off in [1.0, -1.0] {
+                let res = crate::offset::offset_arcline_to_arcline(&arcs, off, &mut cfg);
+                let polys = crate::offset::arcs_to_polylines(
+                    &res,
+                    &crate::tolerances::Tolerances::default(),
+                );
+                let ccw = crate::area::arcline_signed_area(&res[0]) > 0.0;
+                let orientation = if ccw { Orientation::Ccw } else { Orientation::Cw };
+                let from_arcs = arcline_to_gcode(&res[0], Some(orientation), &GcodeCfg::default());
+                // The Polyline of the same loop gives the same moves
+                let from_poly = polyline_to_gcode(&polys[0], true, &GcodeCfg::default());
+                assert_eq!(from_arcs, from_poly);
+                // All corners turn the same way as the loop
+                let arcs: Vec<&str> = lines(&from_arcs)
+                    .into_iter()
+                    .filter(|line| line.starts_with("G2") || line.starts_with("G3"))
+                    .collect();
+                assert!(arcs.iter().all(|line| line.starts_with("G3") == ccw));
+                if !arcs.is_empty() {
+                    assert_eq!(arcs.len(), 4);
+                    outward += 1;
+                }
+            }
+        }
+        assert_eq!(outward, 2);
+    }
+
+    #[test]
+    fn test_circle_loop_directions() {
+        // Offsets of a counter-clockwise circle are loops of two arcs
+        let mut cfg = crate::offset::OffsetCfg::default();
+        let circle = vec![
+            pvertex(point(0.0, 0.0), 1.0),
+            pvertex(point(10.0, 0.0), 1.0),
+        ];
+        for off in [2.0, -2.0] {
+            let arcs = crate::offset::offset_arcline_to_arcline(
+                &crate::observer::polyline_arcs(&circle, true),
+                off,
+                &mut cfg,
+            );
+            assert_eq!(arcs.len(), 1);
+            assert_eq!(arcs[0].len(), 2);
+            for (orientation, code) in [(Orientation::Ccw, "G3"), (Orientation::Cw, "G2")] {
+                let gcode = arcline_to_gcode(&arcs[0], Some(orientation), &GcodeCfg::default());
+                let moves = lines(&gcode);
+                assert_eq!(moves.len(), 4);
+                assert!(moves[2..].iter().all(|line| line.starts_with(code)), "{:?}", moves);
+                // The cut ends where it starts
+                let end = moves[3].split(' ').skip(1).take(2).collect::<Vec<_>>();
+                assert_eq!(moves[1].split(' ').skip(1).collect::<Vec<_>>(), end);
+            }
+        }
+    }
+
+    #[test]
+    fn test_split_and_r_format() {
+        // Full turn of three quarters, counter-clockwise from (1, 0) to (0, -1)
+        let path = vec![arc(point(1.0, 0.0), point(0.0, -1.0), point(0.0, 0.0), 1.0)];
+        let cfg = GcodeCfg {
+            arc_format: ArcFormat::R,
+            max_sweep: TAU,
+            precision: 3,
+            ..GcodeCfg::default()
+        };
+        let res = arcline_to_gcode(&path, None, &cfg);
+        // R arcs are at most half circles
+        assert_eq!(
+            lines(&res),
+            vec![
+                "G90",
+                "G0 X1.000 Y0.000",
+                "G3 X-0.707 Y0.707 R1.000",
+                "G3 X0.000 Y-1.000 R1.000",
+            ]
+        );
+        let cfg = GcodeCfg {
+            max_sweep: PI / 2.0,
+            ..GcodeCfg::default()
+        };
+        let res = arcline_to_gcode(&path, None, &cfg);
+        assert_eq!(lines(&res).len(), 2 + 3);
+        assert_eq!(lines(&res)[2], "G3 X0.0000 Y1.0000 I-1.0000 J0.0000");
+        assert_eq!(lines(&res)[3], "G3 X-1.0000 Y0.0000 I0.0000 J-1.0000");
+    }
+
+    #[test]
+    fn test_incremental_rounding() {
+        let cfg = GcodeCfg {
+            mode: GcodeMode::Incremental,
+            precision: 1,
+            ..GcodeCfg::default()
+        };
+        // Increments of the rounded points do not drift
+        let poly: Polyline = (0..=10)
+            .map(|i| pvertex(point(i as f64 * 0.33, 0.0), 0.0))
+            .collect();
+        let res = polyline_to_gcode(&poly, false, &cfg);
+        let sum: f64 = lines(&res)
+            .iter()
+            .filter_map(|line| line.strip_prefix("G1 X"))
+            .map(|line| line.split(' ').next().unwrap().parse::<f64>().unwrap())
+            .sum();
+        assert!((sum - 3.3).abs() < 1e-9);
+        assert!(arcline_to_gcode(&Vec::new(), Some(Orientation::Ccw), &cfg).is_empty());
+    }
+}
@@ -0,0 +1,241 @@
+//! Find open chains in an undirected graph composed of geometric arcs.
+//!
+//! Used for offsets of open polylines, where the offset segments do not form cycles.
+//! - Vertices are endpoints of arcs in 2D space
+//! - Chains start and end in vertices with degree other than 2
+//!   (open ends or intersections), so they are trimmed at intersections
+//! - Isolated loops (all vertices with degree 2) are returned as closed chains
+
+use togo::prelude::*;
+use aabb::HilbertRTree;
+
+use crate::tolerances::EPS_VERTEX;
+
+
+/// Sequence of connected arcs, in traversal order.
+///
+/// Arcs are always CCW, so the traversal direction of each arc
+/// follows from its connection to the previous one, starting at `start`.
+#[derive(Debug, Clone)]
+pub struct ArcChain {
+    /// Arcs of the chain in traversal order
+    pub arcs: Vec<Arc>,
+    /// Start point of the chain
+    pub start: Point,
+    /// End point of the chain
+    pub end: Point,
+    /// True if the chain is an isolated loop (end is the same as start)
+    pub closed: bool,
+}
+
+impl ArcChain {
+    /// Reverses the traversal direction of the chain
+    pub fn reverse(&mut self) {
+        self.arcs.reverse();
+        std::mem::swap(&mut self.start, &mut self.end);
+    }
+}
+
+/// Finds chains of connected arcs.
+///
+/// # Arguments
+/// * `arcs` - Input arcs, with endpoints already merged (see `merge_close_endpoints`)
+///
+/// # Returns
+/// Vector of chains, each arc is used in exactly one chain.
+/// Arc ends closer than `Tolerances::default().vertex` are the same vertex.
+pub fn find_chains(arcs: &[Arc]) -> Vec<ArcChain> {
+    find_chains_with_tolerance(arcs, EPS_VERTEX)
+}
+
+/// Same as `find_chains`, arc ends closer than `tolerance` are the same vertex
+pub fn find_chains_with_tolerance(arcs: &[Arc], tolerance: f64) -> Vec<ArcChain> {
+    let mut chains = Vec::new();
+    if arcs.is_empty() {
+        return chains;
+    }
+
+    // Endpoint 2 * i is arc.a, endpoint 2 * i + 1 is arc.b
+    let vertex_of = merge_vertices(arcs, tolerance);
+    let vertex_count = vertex_of.iter().max().map_or(0, |v| v + 1);
+    let mut adjacency: Vec<Vec<usize>> = vec![Vec::new(); vertex_count];
+    for i in 0..arcs.len() {
+        adjacency[vertex_of[2 * i]].push(i);
+        adjacency[vertex_of[2 * i + 1]].push(i);
+    }
+
+    let mut used = vec![false; arcs.len()];
+
+    // Chains between open ends and intersections
+    for vertex in 0..vertex_count {
+        if adjacency[vertex].len() == 2 {
+            continue;
+        }
+        for k in 0..adjacency[vertex].len() {
+            let edge = adjacency[vertex][k];
+            if used[edge] {
+                continue;
+            }
+            chains.push(walk_chain(arcs, &vertex_of, &adjacency, &mut used, edge, vertex));
+        }
+    }
+
+    // Remaining arcs form isolated loops
+    for edge in 0..arcs.len() {
+        if used[edge] {
+            continue;
+        }
+        let vertex = vertex_of[2 * edge];
+        let mut chain = walk_chain(arcs, &vertex_of, &adjacency, &mut used, edge, vertex);
+        chain.closed = true;
+        chains.push(chain);
+    }
+
+    chains
+}
+
+// Follows the arcs from the vertex through the degree 2 vertices.
+fn walk_chain(
+    arcs: &[Arc],
+    vertex_of: &[usize],
+    adjacency: &[Vec<usize>],
+    used: &mut [bool],
+    first_edge: usize,
+    first_vertex: usize,
+) -> ArcChain {
+    let start = endpoint(arcs, vertex_of, first_edge, first_vertex);
+    let mut chain = ArcChain {
+        arcs: Vec::new(),
+        start,
+        end: start,
+        closed: false,
+    };
+    let mut edge = first_edge;
+    let mut vertex = first_vertex;
+    loop {
+        used[edge] = true;
+        chain.arcs.push(arcs[edge]);
+        // Move to the other end of the arc
+        vertex = if vertex_of[2 * edge] == vertex {
+            vertex_of[2 * edge + 1]
+        } else {
+            vertex_of[2 * edge]
+        };
+        chain.end = endpoint(arcs, vertex_of, edge, vertex);
+        if adjacency[vertex].len() != 2 {
+            break;
+        }
+        match adjacency[vertex].iter().find(|&&e| !used[e]) {
+            Some(&next) => edge = next,
+            None => break,
+        }
+    }
+    chain
+}
+
+// Position of the arc endpoint that belongs to the vertex
+fn endpoint(arcs: &[Arc], vertex_of: &[usize], edge: usize, vertex: usize) -> Point {
+    if vertex_of[2 * edge] == vertex {
+        arcs[edge].a
+    } else {
+        arcs[edge].b
+    }
+}
+
+// Maps each arc endpoint to a vertex id, close endpoints share the vertex
+fn merge_vertices(arcs: &[Arc], tolerance: f64) -> Vec<usize> {
+    let mut spatial_index = HilbertRTree::with_capacity(2 * arcs.len());
+    for arc in arcs {
+        spatial_index.add_point(arc.a.x, arc.a.y);
+        spatial_index.add_point(arc.b.x, arc.b.y);
+    }
+    spatial_index.build();
+
+    let mut vertex_of = vec![usize::MAX; 2 * arcs.len()];
+    let mut vertex_count = 0;
+    let mut nearby_indices = Vec::new();
+    for i in 0..vertex_of.len() {
+        if vertex_of[i] != usize::MAX {
+            continue;
+        }
+        let p = if i % 2 == 0 { arcs[i / 2].a } else { arcs[i / 2].b };
+        nearby_indices.clear();
+        spatial_index.query_circle(p.x, p.y, tolerance, &mut nearby_indices);
+        vertex_of[i] = vertex_count;
+        for &j in &nearby_indices {
+            if vertex_of[j] == usize::MAX {
+                vertex_of[j] = vertex_count;
+            }
+        }
+        vertex_count += 1;
+    }
+    vertex_of
+}
+
+#[cfg(test)]
+mod test_find_chains {
+    use super::*;
+
+    #[test]
+    fn test_empty_input() {
+        assert!(find_chains(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_single_chain() {
+        // Arcs are not in order and the middle one is reversed
+        let arcs = vec![
+            arcseg(point(2.0, 0.0), point(3.0, 0.0)),
+            arcseg(point(0.0, 0.0), point(1.0, 0.0)),
+            arcseg(point(2.0, 0.0), point(1.0, 0.0)),
+        ];
+        let chains = find_chains(&arcs);
+        assert_eq!(chains.len(), 1);
+        assert_eq!(chains[0].arcs.len(), 3);
+        assert!(!chains[0].closed);
+        let mut ends = [chains[0].start, chains[0].end];
+        ends.sort_by(|a, b| a.x.total_cmp(&b.x));
+        assert_eq!(ends, [point(0.0, 0.0), point(3.0, 0.0)]);
+    }
+
+    #[test]
+    fn test_split_at_intersection() {
+        // Cross, 4 chains meet in the middle
+        let arcs = vec![
+            arcseg(point(-1.0, 0.0), point(0.0, 0.0)),
+            arcseg(point(0.0, 0.0), point(1.0, 0.0)),
+            arcseg(point(0.0, -1.0), point(0.0, 0.0)),
+            arcseg(point(0.0, 0.0), point(0.0, 1.0)),
+        ];
+        let chains = find_chains(&arcs);
+        assert_eq!(chains.len(), 4);
+        assert!(chains.iter().all(|c| c.arcs.len() == 1));
+    }
+
+    #[test]
+    fn test_closed_loop() {
+        let arcs = vec![
+            arcseg(point(0.0, 0.0), point(1.0, 0.0)),
+            arcseg(point(1.0, 0.0), point(0.0, 1.0)),
+            arcseg(point(0.0, 1.0), point(0.0, 0.0)),
+        ];
+        let chains = find_chains(&arcs);
+        assert_eq!(chains.len(), 1);
+        assert!(chains[0].closed);
+        assert_eq!(chains[0].arcs.len(), 3);
+        assert_eq!(chains[0].start, chains[0].end);
+    }
+
+    #[test]
+    fn test_reverse() {
+        let arcs = vec![
+            arcseg(point(0.0, 0.0), point(1.0, 0.0)),
+            arcseg(point(1.0, 0.0), point(2.0, 0.0)),
+        ];
+        let mut chain = find_chains(&arcs).remove(0);
+        let (start, end) = (chain.start, chain.end);
+        chain.reverse();
+        assert_eq!(chain.start, end);
+        assert_eq!(chain.end, start);
+    }
+}
@@ -14,11 +14,10 @@
 //! 4. Return non-intersecting cycles as separate arc sequences
 
 use togo::prelude::*;
-use std::collections::HashMap;
 use aabb::HilbertRTree;
 
-/// Tolerance for considering vertices as the same point
-const VERTEX_TOLERANCE: f64 = 1e-8;
+use crate::{budget::Budget, error::OffsetError, tolerances::EPS_VERTEX};
+
 
 /// Vertex identifier - represents a unique point in 2D space
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -42,23 +41,26 @@ struct GraphEdge {
 struct CycleGraph {
     /// Vertex positions in 2D space
     vertices: Vec<Point>,
-    /// Adjacency list: vertex -> list of connected edges
-    adjacency: HashMap<VertexId, Vec<usize>>,
+    /// Adjacency list: vertex -> list of connected edges, in the order the edges were added
+    adjacency: Vec<Vec<usize>>,
     /// All edges in the graph
     edges: Vec<GraphEdge>,
     /// Spatial index for fast vertex lookup
     #[allow(dead_code)]
     vertex_spatial_index: Option<HilbertRTree>,
+    /// Tolerance for considering vertices as the same point
+    tolerance: f64,
 }
 
 impl CycleGraph {
     /// Create a new empty graph
-    fn new() -> Self {
+    fn new(tolerance: f64) -> Self {
         Self {
             vertices: Vec::new(),
-            adjacency: HashMap::new(),
+            adjacency: Vec::new(),
             edges: Vec::new(),
             vertex_spatial_index: None,
+            tolerance,
         }
     }
     
@@ -68,7 +70,7 @@ impl CycleGraph {
         // Always add, no filtering - merging done post-build
         let vertex_id = VertexId(self.vertices.len());
         self.vertices.push(point);
-        self.adjacency.insert(vertex_id, Vec::new());
+        self.adjacency.push(Vec::new());
         vertex_id
     }
     
@@ -85,15 +87,15 @@ impl CycleGraph {
         };
         
         // Add to adjacency lists
-        self.adjacency.get_mut(&from).unwrap().push(edge.id);
-        self.adjacency.get_mut(&to).unwrap().push(edge.id);
+        self.adjacency[from.0].push(edge.id);
+        self.adjacency[to.0].push(edge.id);
         
         self.edges.push(edge);
     }
     
     /// Get all edge IDs connected to a vertex
     fn get_adjacent_edges(&self, vertex: VertexId) -> &[usize] {
-        self.adjacency.get(&vertex).map(|v| v.as_slice()).unwrap_or(&[])
+        self.adjacency.get(vertex.0).map(|v| v.as_slice()).unwrap_or(&[])
     }
     
     /// Get vertex position
@@ -107,8 +109,8 @@ impl CycleGraph {
 /// Merging is done here to handle cases where find_non_intersecting_cycles is called
 /// without prior merge_close_endpoints, but the overhead is minimal when vertices are
 /// already merged (most vertices map to themselves).
-fn build_graph(arcs: &[Arc]) -> CycleGraph {
-    let mut graph = CycleGraph::new();
+fn build_graph(arcs: &[Arc], tolerance: f64) -> CycleGraph {
+    let mut graph = CycleGraph::new(tolerance);
     
     // Pass 1: Add all arcs and collect vertices
     for arc in arcs {
@@ -127,7 +129,7 @@ fn build_graph(arcs: &[Arc]) -> CycleGraph {
     spatial_index.build();
     
     // Pass 3: Find and merge close vertices using spatial queries
-    let mut vertex_mapping: HashMap<usize, usize> = HashMap::new(); // old_id -> new_id
+    let mut vertex_mapping: Vec<usize> = (0..graph.vertices.len()).collect(); // old_id -> new_id
     let mut merged_vertices: Vec<Point> = Vec::with_capacity(graph.vertices.len());
     let mut used = vec![false; graph.vertices.len()];
     let mut nearby_indices: Vec<usize> = Vec::with_capacity(graph.vertices.len() / 8);  // Preallocate reusable buffer
@@ -141,32 +143,30 @@ fn build_graph(arcs: &[Arc]) -> CycleGraph {
         
         // Find all nearby vertices using spatial index
         nearby_indices.clear();
-        spatial_index.query_circle(point_i.x, point_i.y, VERTEX_TOLERANCE, &mut nearby_indices);
+        spatial_index.query_circle(point_i.x, point_i.y, tolerance, &mut nearby_indices);
         
         // Keep the first one, merge others into it
         let new_vertex_id = merged_vertices.len();
         merged_vertices.push(point_i);
-        vertex_mapping.insert(i, new_vertex_id);
+        vertex_mapping[i] = new_vertex_id;
         used[i] = true;
         
         // Merge nearby vertices (already filtered by query_circle, no need to check distance again)
         for &nearby_idx in &nearby_indices {
             if nearby_idx != i && !used[nearby_idx] {
-                vertex_mapping.insert(nearby_idx, new_vertex_id);
+                vertex_mapping[nearby_idx] = new_vertex_id;
                 used[nearby_idx] = true;
             }
         }
     }
     
     // Pass 4: Rebuild graph with merged vertices
-    let mut new_graph = CycleGraph::new();
+    let mut new_graph = CycleGraph::new(tolerance);
     new_graph.vertices = merged_vertices;
     new_graph.edges.reserve(graph.edges.len());
     
     // Initialize adjacency lists for new vertices
-    for i in 0..new_graph.vertices.len() {
-        new_graph.adjacency.insert(VertexId(i), Vec::new());
-    }
+    new_graph.adjacency = vec![Vec::new(); new_graph.vertices.len()];
     
     // Remap edges to use new vertex IDs
     for edge in &graph.edges {
@@ -174,8 +174,8 @@ fn build_graph(arcs: &[Arc]) -> CycleGraph {
         let old_to = edge.to.0;
         
         // Look up merged vertex IDs - most vertices map to themselves if not merged
-        let new_from_id = *vertex_mapping.get(&old_from).unwrap_or(&old_from);
-        let new_to_id = *vertex_mapping.get(&old_to).unwrap_or(&old_to);
+        let new_from_id = vertex_mapping[old_from];
+        let new_to_id = vertex_mapping[old_to];
         let new_from = VertexId(new_from_id);
         let new_to = VertexId(new_to_id);
         
@@ -186,8 +186,8 @@ fn build_graph(arcs: &[Arc]) -> CycleGraph {
             id: new_graph.edges.len(),
         };
         
-        new_graph.adjacency.get_mut(&new_from).unwrap().push(remapped_edge.id);
-        new_graph.adjacency.get_mut(&new_to).unwrap().push(remapped_edge.id);
+        new_graph.adjacency[new_from.0].push(remapped_edge.id);
+        new_graph.adjacency[new_to.0].push(remapped_edge.id);
         new_graph.edges.push(remapped_edge);
     }
     
@@ -243,7 +243,7 @@ fn choose_rightmost_edge(
     let incoming_edge = &graph.edges[incoming_edge_id];
     
     // Calculate incoming direction using proper tangent calculation
-    let incoming_direction = get_arc_direction_at_vertex(&incoming_edge.arc, vertex_pos, true);
+    let incoming_direction = get_arc_direction_at_vertex(&incoming_edge.arc, vertex_pos, true, graph.tolerance);
     
     // Calculate angles for all available outgoing edges
     let mut edge_angles: Vec<(usize, f64)> = Vec::with_capacity(available_edges.len());
@@ -252,7 +252,7 @@ fn choose_rightmost_edge(
         let edge = &graph.edges[edge_id];
         
         // Calculate outgoing direction using proper tangent calculation
-        let outgoing_direction = get_arc_direction_at_vertex(&edge.arc, vertex_pos, false);
+        let outgoing_direction = get_arc_direction_at_vertex(&edge.arc, vertex_pos, false, graph.tolerance);
         
         // Calculate the angle between incoming and outgoing directions
         // Using atan2 to get signed angle (-π to π)
@@ -265,7 +265,8 @@ fn choose_rightmost_edge(
     
     // Sort by angle and choose the rightmost (smallest positive angle, or largest negative)
     // "Most close on the right" means the edge that makes the smallest right turn
-    edge_angles.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+    // total_cmp() keeps the order defined even for NaN angles from degenerate arcs
+    edge_angles.sort_by(|a, b| a.1.total_cmp(&b.1));
     
     // Find the edge with the smallest positive angle (closest to straight ahead on the right)
     // If no positive angles, take the largest negative angle (least left turn)
@@ -279,10 +280,9 @@ fn choose_rightmost_edge(
 
 /// Get the proper direction vector at a vertex for an arc using togo's tangent calculation
 /// For both arcs and line segments, this uses the tangent method to get correct directions
-fn get_arc_direction_at_vertex(arc: &Arc, vertex_pos: Point, incoming: bool) -> Point {
+fn get_arc_direction_at_vertex(arc: &Arc, vertex_pos: Point, incoming: bool, tolerance: f64) -> Point {
     let tangents = arc.tangents();
-    let tolerance = 1e-10;
-    
+
     // Determine if vertex is at start (a) or end (b) of the arc
     let is_at_start = (vertex_pos - arc.a).norm() < tolerance;
     let is_at_end = (vertex_pos - arc.b).norm() < tolerance;
@@ -381,26 +381,45 @@ fn find_cycle_from_edge(
     }
 }
 
-/// Main function to find non-intersecting cycles from input arcs
+/// Main function to find non-intersecting cycles from input arcs.
+/// Arc ends closer than `Tolerances::default().vertex` are the same vertex.
 pub fn find_non_intersecting_cycles(arcs: &[Arc]) -> Vec<Vec<Arc>> {
+    find_non_intersecting_cycles_with_tolerance(arcs, EPS_VERTEX)
+}
+
+/// Same as `find_non_intersecting_cycles`, arc ends closer than `tolerance` are the same vertex
+pub fn find_non_intersecting_cycles_with_tolerance(arcs: &[Arc], tolerance: f64) -> Vec<Vec<Arc>> {
+    // The default budget has no deadline and no cancel flag, it never stops the search
+    find_non_intersecting_cycles_with_budget(arcs, tolerance, &Budget::default()).unwrap_or_default()
+}
+
+/// Same as `find_non_intersecting_cycles_with_tolerance`, the budget is checked
+/// before every cycle search and stops it with `OffsetError::Cancelled`
+/// or `OffsetError::DeadlineExceeded`
+pub fn find_non_intersecting_cycles_with_budget(
+    arcs: &[Arc],
+    tolerance: f64,
+    budget: &Budget,
+) -> Result<Vec<Vec<Arc>>, OffsetError> {
     if arcs.is_empty() {
-        return Vec::new();
+        return Ok(Vec::new());
     }
     
     // Build graph representation
-    let graph = build_graph(arcs);
+    let graph = build_graph(arcs, tolerance);
     
     let mut cycles = Vec::new();
     let mut used_edges = vec![false; graph.edges.len()];  // Use Vec<bool> instead of HashSet
     
     // Try to find cycles starting from each edge
     for edge_id in 0..graph.edges.len() {
+        budget.check()?;
         if let Some(cycle) = find_cycle_from_edge(&graph, edge_id, &mut used_edges) {
             cycles.push(cycle);
         }
     }
     
-    cycles
+    Ok(cycles)
 }
 
 #[cfg(test)]
@@ -458,7 +477,7 @@ pub fn find_non_intersecting_cycles(arcs: &[Arc]) -> Vec<Vec<Arc>> {
             arcseg(point(1.0, 0.0), point(1.0, 1.0)),
         ];
         
-        let graph = build_graph(&arcs);
+        let graph = build_graph(&arcs, EPS_VERTEX);
         assert_eq!(graph.vertices.len(), 3); // Three unique vertices
         assert_eq!(graph.edges.len(), 2);
     }
@@ -470,7 +489,7 @@ pub fn find_non_intersecting_cycles(arcs: &[Arc]) -> Vec<Vec<Arc>> {
             arcseg(point(1.0, 1e-10), point(2.0, 0.0)), // Close to (1.0, 0.0)
         ];
         
-        let graph = build_graph(&arcs);
+        let graph = build_graph(&arcs, EPS_VERTEX);
         // Should merge close vertices
         assert_eq!(graph.vertices.len(), 3);
     }
@@ -591,7 +610,7 @@ pub fn find_non_intersecting_cycles(arcs: &[Arc]) -> Vec<Vec<Arc>> {
             arcseg(point(1.0, 1.0), point(0.0, 2.0)),  // Up-left (135°)
         ];
         
-        let graph = build_graph(&arcs);
+        let graph = build_graph(&arcs, EPS_VERTEX);
         let vertex_id = VertexId(1); // Vertex at (1,1)
         let incoming_edge = 0;
         let available_edges = vec![1, 2, 3];
@@ -3,10 +3,12 @@
 //! This module provides algorithms for:
 //! - Merging close endpoints of arcs to handle numerical precision issues
 //! - Finding non-intersecting cycles in graphs of connected arcs
+//! - Finding open chains of connected arcs
 //! - Handling geometric constraints for tool path generation
 
 pub mod merge_ends;
 pub mod find_cycles;
+pub mod find_chains;
 
 #[cfg(test)]
 mod find_cycles_tangent_tests_simple;
@@ -16,4 +18,8 @@ mod cycle_detection_comprehensive_tests;
 
 // Re-export main functions
 pub use merge_ends::merge_close_endpoints;
-pub use find_cycles::find_non_intersecting_cycles;
\ No newline at end of file
+pub use find_cycles::{
+    find_non_intersecting_cycles, find_non_intersecting_cycles_with_budget,
+    find_non_intersecting_cycles_with_tolerance,
+};
+pub use find_chains::{find_chains, find_chains_with_tolerance};
\ No newline at end of file
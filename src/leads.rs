@@ -0,0 +1,414 @@
+//! Lead-in and lead-out moves of closed offset contours.
+//!
+//! Profiling and cutting enter a contour with a lead-in move, that ends tangent to the
+//! contour (or perpendicular to it), and leave it with a lead-out move from the same point.
+//! The moves are on the side of the contour away from the input,
+//! and they are checked not to come closer to the input than the contour does.
+
+use togo::prelude::*;
+
+use crate::{
+    area::{Orientation, arcline_oriented},
+    error::OffsetError,
+    offset::check_polyline,
+    offset_arcs_raw::poly_to_raws,
+    offset_prune_invalid::{PolyArcs, dist_point_element, distance_element_element},
+    spiral::{closest_point, loop_from},
+    tolerances::Tolerances,
+};
+
+/// Shape of the lead-in and lead-out moves.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LeadStyle {
+    /// Quarter circle tangent to the contour
+    Arc {
+        /// Radius of the arc
+        radius: f64,
+    },
+    /// Line perpendicular to the contour
+    Line {
+        /// Length of the line
+        length: f64,
+    },
+    /// Line followed by a quarter circle tangent to the contour,
+    /// the line is tangent to the arc
+    LineArc {
+        /// Length of the line
+        length: f64,
+        /// Radius of the arc
+        radius: f64,
+    },
+}
+
+/// Adds lead-in and lead-out moves to a closed contour, see `try_add_leads`.
+///
+/// Invalid input and colliding moves produce an empty path
+/// (use `try_add_leads` to find out why).
+pub fn add_leads(
+    contour: &Arcline,
+    orientation: Orientation,
+    start: Point,
+    style: LeadStyle,
+    source: &Polyline,
+    others: &[Arcline],
+    tol: &Tolerances,
+) -> Arcline {
+    try_add_leads(contour, orientation, start, style, source, others, tol).unwrap_or_default()
+}
+
+/// Adds lead-in and lead-out moves to a closed contour, reporting failures as `OffsetError`.
+///
+/// The contour is an offset of the closed `source` Polyline, as the loops of
+/// `offset_arcline_to_arcline`. It is cut from its point closest to `start`,
+/// all the way around with the given orientation, and the lead-in move ends at that point
+/// tangent to the cut and the lead-out move starts at it. The moves are on the side of
+/// the contour away from the source.
+///
+/// The result is an open path of the lead-in move, the contour split at the start point and
+/// the lead-out move (see `arcline_to_gcode`). As in the loops of `offset_arcline_to_arcline`,
+/// the arcs are counter-clockwise and an arc that is cut clockwise goes from `b` to `a`.
+///
+/// The moves gouge the source when they come closer to it than the start point of
+/// the contour, by more than `tol.prune`. They must not touch the `others` contours,
+/// and the contour itself away from the start point.
+///
+/// # Errors
+///
+/// - `OffsetError::InvalidInput` when the contour is empty, the lead lengths are not positive
+///   and finite, or the start point is on the source
+/// - `OffsetError::LeadCollision` when a move gouges the source or touches a contour
+/// - otherwise see `try_offset_polyline_to_polyline` for the errors of the source
+///
+/// # Examples
+///
+/// ```rust
+/// use togo::prelude::*;
+/// use offroad::prelude::*;
+///
+/// let mut cfg = OffsetCfg::default();
+/// // Counter-clockwise square, offset to the right is outward
+/// let source = vec![
+///     pvertex(point(0.0, 0.0), 0.0),
+///     pvertex(point(10.0, 0.0), 0.0),
+///     pvertex(point(10.0, 10.0), 0.0),
+///     pvertex(point(0.0, 10.0), 0.0),
+/// ];
+/// let square: Arcline = vec![
+///     arcseg(point(0.0, 0.0), point(10.0, 0.0)),
+///     arcseg(point(10.0, 0.0), point(10.0, 10.0)),
+///     arcseg(point(10.0, 10.0), point(0.0, 10.0)),
+///     arcseg(point(0.0, 10.0), point(0.0, 0.0)),
+/// ];
+/// let contour = offset_arcline_to_arcline(&square, 1.0, &mut cfg).remove(0);
+///
+/// let style = LeadStyle::Line { length: 2.0 };
+/// let tol = Tolerances::default();
+/// let path = try_add_leads(&contour, Orientation::Ccw, point(5.0, -5.0), style, &source, &[], &tol)
+///     .unwrap();
+/// // Perpendicular line from below to the bottom side of the contour, and back
+/// assert_eq!(path[0], arcseg(point(5.0, -3.0), point(5.0, -1.0)));
+/// assert_eq!(path[path.len() - 1], arcseg(point(5.0, -1.0), point(5.0, -3.0)));
+/// ```
+pub fn try_add_leads(
+    contour: &Arcline,
+    orientation: Orientation,
+    start: Point,
+    style: LeadStyle,
+    source: &Polyline,
+    others: &[Arcline],
+    tol: &Tolerances,
+) -> Result<Arcline, OffsetError> {
+    if contour.is_empty() {
+        return Err(OffsetError::InvalidInput("contour is empty".to_string()));
+    }
+    check_style(style)?;
+    check_polyline(source, 0.0)?;
+    let source_arcs = PolyArcs::new(&poly_to_raws(&vec![source.clone()], tol), tol.prune);
+
+    // Start point on the contour, the direction of the contour and the side away from the source
+    let (contour, forward) = arcline_oriented(contour, orientation, tol.vertex);
+    let (i, s) = closest_point(&contour, start);
+    let t = tangent(&contour[i], forward[i], s);
+    let Some((dist, q)) = source_arcs
+        .closest_point(s)
+        .filter(|(dist, _)| *dist > tol.prune)
+    else {
+        return Err(OffsetError::InvalidInput(format!(
+            "start point {:?} is on the source",
+            s
+        )));
+    };
+    let mut n = point(-t.y, t.x);
+    if n.dot(s - q) < 0.0 {
+        n = -n;
+    }
+
+    let (lead_in, lead_out) = lead_moves(s, t, n, style);
+    for lead in lead_in.iter().chain(lead_out.iter()) {
+        if source_arcs.closer_than(lead, dist - tol.prune) {
+            return Err(OffsetError::LeadCollision);
+        }
+        // The contour is only touched at the start point
+        let touches = |arc: &Arc| distance_element_element(arc, lead) < tol.prune;
+        let own = contour
+            .iter()
+            .filter(|arc| dist_point_element(arc, s).0 >= tol.prune)
+            .any(touches);
+        if own || others.iter().flatten().any(touches) {
+            return Err(OffsetError::LeadCollision);
+        }
+    }
+
+    let mut path = lead_in;
+    path.extend(loop_from(&contour, &forward, i, s, tol.vertex));
+    path.extend(lead_out);
+    Ok(path)
+}
+
+fn check_style(style: LeadStyle) -> Result<(), OffsetError> {
+    let lengths = match style {
+        LeadStyle::Arc { radius } => [radius, radius],
+        LeadStyle::Line { length } => [length, length],
+        LeadStyle::LineArc { length, radius } => [length, radius],
+    };
+    if lengths.iter().all(|len| len.is_finite() && *len > 0.0) {
+        Ok(())
+    } else {
+        Err(OffsetError::InvalidInput(format!(
+            "lead {:?} has a length that is not positive",
+            style
+        )))
+    }
+}
+
+// Unit direction of the contour at the point of the arc
+pub(crate) fn tangent(arc: &Arc, forward: bool, p: Point) -> Point {
+    let dir = if arc.is_seg() {
+        arc.b - arc.a
+    } else {
+        // Arcs are counter-clockwise from a to b
+        point(arc.c.y - p.y, p.x - arc.c.x)
+    };
+    let (dir, _) = dir.normalize(false);
+    if forward { dir } else { -dir }
+}
+
+// Lead-in and lead-out moves at the point s of the contour with the direction t,
+// on the side n of the contour
+fn lead_moves(s: Point, t: Point, n: Point, style: LeadStyle) -> (Arcline, Arcline) {
+    match style {
+        LeadStyle::Line { length } => {
+            let p = s + n * length;
+            (vec![arcseg(p, s)], vec![arcseg(s, p)])
+        }
+        LeadStyle::Arc { radius } => (
+            vec![lead_arc(s, t, n, radius, true)],
+            vec![lead_arc(s, t, n, radius, false)],
+        ),
+        LeadStyle::LineArc { length, radius } => {
+            // The arcs start and end going along n
+            let p_in = s + (n - t) * radius;
+            let p_out = s + (n + t) * radius;
+            (
+                vec![
+                    arcseg(p_in + n * length, p_in),
+                    lead_arc(s, t, n, radius, true),
+                ],
+                vec![
+                    lead_arc(s, t, n, radius, false),
+                    arcseg(p_out, p_out + n * length),
+                ],
+            )
+        }
+    }
+}
+
+// Quarter circle on the side n, tangent to the contour at s,
+// to s for the lead-in and from s for the lead-out
+fn lead_arc(s: Point, t: Point, n: Point, radius: f64, lead_in: bool) -> Arc {
+    let c = s + n * radius;
+    let p = if lead_in {
+        c - t * radius
+    } else {
+        c + t * radius
+    };
+    // The arcs are cut counter-clockwise when the contour turns toward n
+    let ccw = (s - c).perp(t) > 0.0;
+    if ccw == lead_in {
+        arc(p, s, c, radius)
+    } else {
+        arc(s, p, c, radius)
+    }
+}
+
+#[cfg(test)]
+mod test_leads {
+    use super::*;
+    use crate::area::arcline_forward;
+    use crate::{
+        observer::polyline_arcs,
+        offset::{OffsetCfg, offset_arcline_to_arcline},
+    };
+
+    fn rect(x0: f64, y0: f64, x1: f64, y1: f64) -> Polyline {
+        vec![
+            pvertex(point(x0, y0), 0.0),
+            pvertex(point(x1, y0), 0.0),
+            pvertex(point(x1, y1), 0.0),
+            pvertex(point(x0, y1), 0.0),
+        ]
+    }
+
+    // Start and end points of the path arcs, in the cutting order
+    fn path_points(path: &Arcline) -> Vec<(Point, Point)> {
+        path.iter()
+            .zip(arcline_forward(path, false, Tolerances::default().vertex))
+            .map(|(arc, forward)| {
+                if forward {
+                    (arc.a, arc.b)
+                } else {
+                    (arc.b, arc.a)
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_leads_styles() {
+        let mut cfg = OffsetCfg::default();
+        let tol = Tolerances::default();
+        let source = rect(0.0, 0.0, 10.0, 10.0);
+        let contour =
+            offset_arcline_to_arcline(&polyline_arcs(&source, true), 1.0, &mut cfg).remove(0);
+        let styles = [
+            LeadStyle::Arc { radius: 1.5 },
+            LeadStyle::Line { length: 2.0 },
+            LeadStyle::LineArc {
+                length: 2.0,
+                radius: 1.5,
+            },
+        ];
+        for style in styles {
+            let path = try_add_leads(&contour, Orientation::Ccw, point(-5.0, 4.0), style, &source, &[], &tol)
+                .unwrap();
+            let points = path_points(&path);
+            for pair in points.windows(2) {
+                assert!(
+                    pair[0].1.close_enough(pair[1].0, 1e-9),
+                    "{:?} {:?}",
+                    style,
+                    pair
+                );
+            }
+            // The moves are on the left of the contour, at x < -1
+            let s = point(-1.0, 4.0);
+            let n_in = match style {
+                LeadStyle::LineArc { .. } => 2,
+                _ => 1,
+            };
+            assert!(points[n_in - 1].1.close_enough(s, 1e-9));
+            assert!(points[points.len() - n_in].0.close_enough(s, 1e-9));
+            for (a, b) in points[..n_in]
+                .iter()
+                .chain(points[points.len() - n_in..].iter())
+            {
+                assert!(a.x <= -1.0 + 1e-9 && b.x <= -1.0 + 1e-9, "{:?}", style);
+            }
+            assert_eq!(path.len(), contour.len() + 1 + 2 * n_in);
+        }
+    }
+
+    #[test]
+    fn test_leads_circle() {
+        let mut cfg = OffsetCfg::default();
+        let tol = Tolerances::default();
+        // Counter-clockwise circle, the outward offset is a loop of two arcs
+        let source = vec![
+            pvertex(point(0.0, 0.0), 1.0),
+            pvertex(point(10.0, 0.0), 1.0),
+        ];
+        let c = point(5.0, 0.0);
+        let contour =
+            offset_arcline_to_arcline(&polyline_arcs(&source, true), 1.0, &mut cfg).remove(0);
+        assert_eq!(contour.len(), 2);
+        let style = LeadStyle::Arc { radius: 1.5 };
+        // Start inside of an arc and at the end of an arc
+        let starts = [point(5.0, 10.0), c + (contour[0].a - c) * 2.0];
+        for orientation in [Orientation::Ccw, Orientation::Cw] {
+            for start in starts {
+                let path =
+                    try_add_leads(&contour, orientation, start, style, &source, &[], &tol).unwrap();
+                let points = path_points(&path);
+                for pair in points.windows(2) {
+                    assert!(pair[0].1.close_enough(pair[1].0, 1e-9), "{:?}", pair);
+                }
+                // The contour is cut in the orientation, the lead-in arrives along the cut
+                let n = path.len();
+                let forward = arcline_forward(&path, false, tol.vertex);
+                assert!(forward[1..n - 1].iter().all(|f| *f == (orientation == Orientation::Ccw)));
+                let s = points[0].1;
+                let t_in = tangent(&path[0], forward[0], s);
+                let t_cut = tangent(&path[1], forward[1], s);
+                assert!(t_in.close_enough(t_cut, 1e-9), "{:?} {:?}", t_in, t_cut);
+                let t_out = tangent(&path[n - 1], forward[n - 1], s);
+                let t_end = tangent(&path[n - 2], forward[n - 2], s);
+                assert!(t_out.close_enough(t_end, 1e-9), "{:?} {:?}", t_out, t_end);
+            }
+        }
+    }
+
+    #[test]
+    fn test_leads_gouge() {
+        let mut cfg = OffsetCfg::default();
+        let tol = Tolerances::default();
+        // Narrow slot, the contour is inside of it
+        let source = rect(0.0, 0.0, 10.0, 4.0);
+        let contour =
+            offset_arcline_to_arcline(&polyline_arcs(&source, true), -1.0, &mut cfg).remove(0);
+        let start = point(5.0, 0.0);
+        let style = LeadStyle::Line { length: 2.5 };
+        let res = try_add_leads(&contour, Orientation::Ccw, start, style, &source, &[], &tol);
+        assert_eq!(res, Err(OffsetError::LeadCollision));
+        assert!(add_leads(&contour, Orientation::Ccw, start, style, &source, &[], &tol).is_empty());
+        let style = LeadStyle::Arc { radius: 0.5 };
+        assert!(try_add_leads(&contour, Orientation::Ccw, start, style, &source, &[], &tol).is_ok());
+
+        // An other contour in the way
+        let other = polyline_arcs(&rect(4.0, 1.5, 6.0, 2.5), true);
+        let style = LeadStyle::Line { length: 0.8 };
+        assert!(try_add_leads(&contour, Orientation::Ccw, start, style, &source, &[], &tol).is_ok());
+        let res = try_add_leads(&contour, Orientation::Ccw, start, style, &source, &[other], &tol);
+        assert_eq!(res, Err(OffsetError::LeadCollision));
+    }
+
+    #[test]
+    fn test_leads_invalid() {
+        let tol = Tolerances::default();
+        let source = rect(0.0, 0.0, 10.0, 10.0);
+        let style = LeadStyle::Arc { radius: -1.0 };
+        let contour = polyline_arcs(&rect(-1.0, -1.0, 11.0, 11.0), true);
+        let res = try_add_leads(&contour, Orientation::Ccw, point(0.0, 0.0), style, &source, &[], &tol);
+        assert!(matches!(res, Err(OffsetError::InvalidInput(_))));
+        let res = try_add_leads(
+            &Vec::new(),
+            Orientation::Ccw,
+            point(0.0, 0.0),
+            LeadStyle::Line { length: 1.0 },
+            &source,
+            &[],
+            &tol,
+        );
+        assert!(matches!(res, Err(OffsetError::InvalidInput(_))));
+        // The contour touches the source
+        let res = try_add_leads(
+            &polyline_arcs(&source, true),
+            Orientation::Ccw,
+            point(5.0, 0.0),
+            LeadStyle::Line { length: 1.0 },
+            &source,
+            &[],
+            &tol,
+        );
+        assert!(matches!(res, Err(OffsetError::InvalidInput(_))));
+    }
+}
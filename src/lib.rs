@@ -31,7 +31,45 @@
 
 // Offsetting algorithm components
 pub mod offset;
-#[doc(hidden)]
+// Error type of the fallible offsetting API
+pub mod error;
+// Input validation and repair
+pub mod validate;
+// Offsetting of open polylines and arclines
+pub mod offset_open;
+// Two-sided buffer of open polylines and arclines
+pub mod offset_buffer;
+// Signed area and orientation of closed shapes
+pub mod area;
+// Offsetting of regions with holes and of sets of shapes
+pub mod region;
+// Containment tree of the loops of an offset
+pub mod contour_tree;
+// Canonical form of the offset loops
+pub mod canonical;
+// Pocketing toolpaths of regions
+pub mod pocket;
+// Linking of nested loops into one toolpath
+pub mod spiral;
+// Lead-in and lead-out moves of offset contours
+pub mod leads;
+// G-code output of Arclines and Polylines
+pub mod gcode;
+// Offsetting with a separate distance for every segment
+pub mod offset_variable;
+// Numeric tolerances of the offsetting stages
+pub mod tolerances;
+// Work budget and cancellation of the offsetting stages
+pub mod budget;
+// Observation of the offsetting stages, SVG output
+pub mod observer;
+// Parallel offsetting of batches
+pub mod batch;
+// Stage-by-stage offsetting
+pub mod pipeline;
+// Provenance of the offset arcs
+pub mod origin;
+// Input segments and raw offsets of the offsetting stages
 pub mod offsetraw;
 #[doc(hidden)]
 // raw offsetting components (lines, arcs)
@@ -60,7 +98,36 @@ pub mod poly;
 // Re-export main offsetting functions
 // For public API
 pub mod prelude {
-    pub use crate::offset::{offset_polyline_to_polyline, offset_arcline_to_arcline, OffsetCfg};
+    pub use crate::offset::{offset_polyline_to_polyline, offset_arcline_to_arcline, OffsetCfg, OffsetOptions, JoinStyle};
+    pub use crate::offset::{try_offset_polyline_to_polyline, try_offset_arcline_to_arcline};
+    pub use crate::offset::{try_offset_polyline_with_origins, try_offset_polyline_tree};
+    pub use crate::offset::{offset_polyline_multiple, try_offset_polyline_multiple};
+    pub use crate::offset::{offset_outward, offset_inward, try_offset_outward, try_offset_inward};
+    pub use crate::offset::{offset_arcline_outward, offset_arcline_inward, try_offset_arcline_outward, try_offset_arcline_inward};
+    pub use crate::area::{polyline_signed_area, arcline_signed_area, arcline_signed_area_with_tolerance, Orientation};
+    pub use crate::region::{offset_region, try_offset_region, Region};
+    pub use crate::region::{offset_polylines, try_offset_polylines};
+    pub use crate::region::try_offset_region_tree;
+    pub use crate::contour_tree::{ContourNode, ContourTree};
+    pub use crate::canonical::canonical_polylines;
+    pub use crate::pocket::{pocket_region, try_pocket_region, Pocket, PocketNode};
+    pub use crate::spiral::{link_loops, try_link_loops, LinkStyle};
+    pub use crate::leads::{add_leads, try_add_leads, LeadStyle};
+    pub use crate::gcode::{arcline_to_gcode, polyline_to_gcode, ArcFormat, GcodeCfg, GcodeMode};
+    pub use crate::offset_variable::{offset_polyline_variable, try_offset_polyline_variable};
+    pub use crate::offset_open::{offset_open_polyline, offset_open_arcline};
+    pub use crate::offset_open::{try_offset_open_polyline, try_offset_open_arcline};
+    pub use crate::offset_buffer::{buffer_polyline, buffer_arcline, try_buffer_polyline, try_buffer_arcline, EndCap};
+    pub use crate::tolerances::Tolerances;
+    pub use crate::budget::Budget;
+    pub use crate::observer::{OffsetObserver, SvgObserver};
+    pub use crate::batch::{offset_polylines_parallel, offset_distances_parallel};
+    pub use crate::pipeline::OffsetPipeline;
+    pub use crate::origin::{ArcOrigin, ArcOrigins, OriginKind};
+    pub use crate::offsetraw::OffsetRaw;
+    pub use crate::error::OffsetError;
+    pub use crate::validate::{validate_polyline, validate_arcline, repair_polyline, repair_arcline, ValidationIssue};
+    pub use crate::validate::{validate_polyline_with_tolerance, validate_arcline_with_tolerance};
     pub use crate::poly::{pline_01, pline_02, pline_03, pline_04, pline_500};
 }
 // For internal use
@@ -0,0 +1,251 @@
+//! Observation of the intermediate results of the offsetting stages.
+//!
+//! Set `OffsetCfg::observer` to receive the result of every stage, for debugging,
+//! logging or visualization. `SvgObserver` draws the stages in an SVG file.
+
+use togo::prelude::*;
+
+use crate::offsetraw::OffsetRaw;
+
+/// Callbacks with the results of the offsetting stages, in the order they are called.
+///
+/// All callbacks do nothing by default, implement the ones of interest.
+/// Functions that offset several shapes call them once for every shape.
+///
+/// # Examples
+///
+/// ```rust
+/// use togo::prelude::*;
+/// use offroad::prelude::*;
+///
+/// // Counts the arcs left after pruning
+/// #[derive(Default)]
+/// struct PruneCount(usize);
+///
+/// impl OffsetObserver for PruneCount {
+///     fn pruned(&mut self, arcs: &[Arc]) {
+///         self.0 += arcs.len();
+///     }
+/// }
+///
+/// let mut count = PruneCount::default();
+/// let mut cfg = OffsetCfg::default();
+/// cfg.observer = Some(&mut count);
+/// let poly = vec![
+///     pvertex(point(0.0, 0.0), 0.0),
+///     pvertex(point(10.0, 0.0), 0.0),
+///     pvertex(point(10.0, 10.0), 0.0),
+/// ];
+/// let res = offset_polyline_to_polyline(&poly, 1.0, &mut cfg);
+/// assert_eq!(res.len(), 1);
+/// assert!(count.0 > 0);
+/// ```
+pub trait OffsetObserver {
+    /// The input shapes, polylines are converted to arcs
+    fn input(&mut self, _arcs: &[Arcline]) {}
+    /// Raw offsets of the input segments, before they are connected
+    fn raw_offsets(&mut self, _raws: &[Vec<OffsetRaw>]) {}
+    /// Arcs and lines connecting the raw offsets at the corners
+    fn connect(&mut self, _arcs: &[Arcline]) {}
+    /// Raw offsets and connections split at their intersections
+    fn split(&mut self, _arcs: &[Arc]) {}
+    /// Split arcs left after removing the ones too close to the input
+    fn pruned(&mut self, _arcs: &[Arc]) {}
+    /// Pruned arcs reconnected into closed cycles, or into chains for open input
+    fn reconnected(&mut self, _arcs: &[Arcline]) {}
+    /// The result, before it is converted to polylines
+    fn output(&mut self, _arcs: &[Arcline]) {}
+}
+
+/// Observer that draws the offsetting stages in an SVG file.
+///
+/// The input is drawn in red, the raw offsets in blue, the other stages in violet.
+/// Only the stages with their flag set are drawn.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use togo::prelude::*;
+/// use offroad::prelude::*;
+///
+/// let mut svg = SvgObserver::new(SVG::new(300.0, 300.0, Some("/tmp/offset.svg")));
+/// svg.orig = true;
+/// svg.output = true;
+/// let mut cfg = OffsetCfg::default();
+/// cfg.observer = Some(&mut svg);
+/// let _ = offset_polyline_to_polyline(&pline_01()[0], 5.0, &mut cfg);
+/// svg.svg.write_stroke_width(0.1);
+/// ```
+pub struct SvgObserver {
+    /// The SVG context the stages are drawn in
+    pub svg: SVG,
+    /// Flag to draw the input
+    pub orig: bool,
+    /// Flag to draw the raw offsets
+    pub raw: bool,
+    /// Flag to draw the corner connections
+    pub connect: bool,
+    /// Flag to draw the split offsets
+    pub split: bool,
+    /// Flag to draw the pruned offsets
+    pub prune: bool,
+    /// Flag to draw the reconnected cycles and chains
+    pub reconnect: bool,
+    /// Flag to draw the result
+    pub output: bool,
+}
+
+impl SvgObserver {
+    /// Creates an observer drawing in `svg`, with all flags unset.
+    pub fn new(svg: SVG) -> Self {
+        SvgObserver {
+            svg,
+            orig: false,
+            raw: false,
+            connect: false,
+            split: false,
+            prune: false,
+            reconnect: false,
+            output: false,
+        }
+    }
+
+    fn draw<'b>(&mut self, arcs: impl IntoIterator<Item = &'b Arc>, color: &str) {
+        for arc in arcs {
+            self.svg.arcsegment(arc, color);
+        }
+    }
+}
+
+impl OffsetObserver for SvgObserver {
+    fn input(&mut self, arcs: &[Arcline]) {
+        if self.orig {
+            self.draw(arcs.iter().flatten(), "red");
+        }
+    }
+
+    fn raw_offsets(&mut self, raws: &[Vec<OffsetRaw>]) {
+        if self.raw {
+            self.draw(raws.iter().flatten().map(|raw| &raw.arc), "blue");
+        }
+    }
+
+    fn connect(&mut self, arcs: &[Arcline]) {
+        if self.connect {
+            self.draw(arcs.iter().flatten(), "violet");
+        }
+    }
+
+    fn split(&mut self, arcs: &[Arc]) {
+        if self.split {
+            self.draw(arcs, "violet");
+        }
+    }
+
+    fn pruned(&mut self, arcs: &[Arc]) {
+        if self.prune {
+            self.draw(arcs, "violet");
+        }
+    }
+
+    fn reconnected(&mut self, arcs: &[Arcline]) {
+        if self.reconnect {
+            self.draw(arcs.iter().flatten(), "violet");
+        }
+    }
+
+    fn output(&mut self, arcs: &[Arcline]) {
+        if self.output {
+            self.draw(arcs.iter().flatten(), "violet");
+        }
+    }
+}
+
+// Arcs of the polyline segments, with the closing segment when closed
+pub(crate) fn polyline_arcs(poly: &Polyline, closed: bool) -> Arcline {
+    let mut arcs: Arcline = poly
+        .windows(2)
+        .map(|pair| arc_from_bulge(pair[0].p, pair[1].p, pair[0].b))
+        .collect();
+    if closed
+        && let (Some(first), Some(last)) = (poly.first(), poly.last())
+    {
+        arcs.push(arc_from_bulge(last.p, first.p, last.b));
+    }
+    arcs
+}
+
+#[cfg(test)]
+mod test_observer {
+    use super::*;
+    use crate::offset::{OffsetCfg, offset_polyline_to_polyline};
+
+    // Records the number of arcs passed to every stage
+    #[derive(Default)]
+    struct Counts {
+        stages: Vec<(&'static str, usize)>,
+    }
+
+    impl OffsetObserver for Counts {
+        fn input(&mut self, arcs: &[Arcline]) {
+            self.stages.push(("input", arcs.iter().map(|a| a.len()).sum()));
+        }
+        fn raw_offsets(&mut self, raws: &[Vec<OffsetRaw>]) {
+            self.stages.push(("raw", raws.iter().map(|r| r.len()).sum()));
+        }
+        fn connect(&mut self, arcs: &[Arcline]) {
+            self.stages.push(("connect", arcs.iter().map(|a| a.len()).sum()));
+        }
+        fn split(&mut self, arcs: &[Arc]) {
+            self.stages.push(("split", arcs.len()));
+        }
+        fn pruned(&mut self, arcs: &[Arc]) {
+            self.stages.push(("pruned", arcs.len()));
+        }
+        fn reconnected(&mut self, arcs: &[Arcline]) {
+            self.stages.push(("reconnected", arcs.len()));
+        }
+        fn output(&mut self, arcs: &[Arcline]) {
+            self.stages.push(("output", arcs.len()));
+        }
+    }
+
+    #[test]
+    fn test_observer_stages() {
+        // Clockwise square, offset inward
+        let poly = vec![
+            pvertex(point(0.0, 0.0), 0.0),
+            pvertex(point(0.0, 10.0), 0.0),
+            pvertex(point(10.0, 10.0), 0.0),
+            pvertex(point(10.0, 0.0), 0.0),
+        ];
+        let mut counts = Counts::default();
+        let mut cfg = OffsetCfg {
+            observer: Some(&mut counts),
+            ..OffsetCfg::default()
+        };
+        let res = offset_polyline_to_polyline(&poly, 1.0, &mut cfg);
+        assert_eq!(res.len(), 1);
+        let names: Vec<&str> = counts.stages.iter().map(|s| s.0).collect();
+        assert_eq!(
+            names,
+            ["input", "raw", "connect", "split", "pruned", "reconnected", "output"]
+        );
+        assert_eq!(counts.stages[0].1, 4);
+        assert_eq!(counts.stages[1].1, 4);
+        assert_eq!(counts.stages[6].1, 1);
+    }
+
+    #[test]
+    fn test_polyline_arcs() {
+        let poly = vec![
+            pvertex(point(0.0, 0.0), 0.0),
+            pvertex(point(1.0, 0.0), 0.0),
+            pvertex(point(1.0, 1.0), 0.0),
+        ];
+        assert_eq!(polyline_arcs(&poly, false).len(), 2);
+        let closed = polyline_arcs(&poly, true);
+        assert_eq!(closed.len(), 3);
+        assert_eq!(closed[2].b, point(0.0, 0.0));
+    }
+}
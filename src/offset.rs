@@ -4,45 +4,119 @@
 use togo::prelude::*;
 
 use crate::{
-    offset_connect_raw::offset_connect_raw,
-    offset_arcs_raw::{self, arcs_to_raws, poly_to_raws},
-    offset_prune_invalid::offset_prune_invalid,
+    area::{arcline_signed_area_with_tolerance, polyline_signed_area},
+    budget::Budget,
+    contour_tree::ContourTree,
+    error::OffsetError,
+    observer::OffsetObserver,
+    pipeline::OffsetPipeline,
     offsetraw::OffsetRaw,
-    offset_reconnect_arcs::{offset_reconnect_arcs},
-    offset_split_arcs::offset_split_arcs
+    origin::ArcOrigin,
+    tolerances::{Tolerances, points_size},
+    validate::{REPAIR_TOLERANCE, repair_arcline, repair_polyline},
 };
 
-/// Configuration options for offsetting operations.
-pub struct OffsetCfg<'a> {
-    /// Optional SVG context for rendering
-    pub svg: Option<&'a mut SVG>, 
-    /// Flag to indicate if reconnecting arcs is needed
+/// Shape of the offset at convex corners.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum JoinStyle {
+    /// Circular arc of radius `off` around the corner
+    #[default]
+    Round,
+    /// Offset segments are extended along their tangents to the intersection point.
+    /// When the distance of the intersection point from the corner is more than
+    /// `limit` times the offset distance, the corner is beveled.
+    Miter {
+        /// Maximum ratio of the miter length to the offset distance
+        limit: f64,
+    },
+    /// Straight line between the ends of the offset segments
+    Bevel,
+    /// Offsets are extended until they meet, lines along their direction and arcs
+    /// along their circle, as the CAD offset command does.
+    /// When the extended offsets do not meet, or an arc would have to be extended
+    /// nearly around its whole circle, the corner is round.
+    Extend,
+}
+
+/// Options controlling the offsetting behavior.
+///
+/// The options are `Clone`, `Send` and `Sync`, the same options can be used
+/// by many threads, see `offset_polylines_parallel`.
+#[derive(Debug, Clone, Copy)]
+pub struct OffsetOptions<'a> {
+    /// Flag to reconnect the offset arcs into loops, used by the Arcline functions
+    /// (Polylines are always reconnected, see `OffsetPipeline::into_arcs` for the unconnected arcs)
     pub reconnect: bool,
-    /// Flag to enable writing in svg original polyline
-    pub svg_orig: bool,
-    /// Flag to enable writing in svg raw offsets
-    pub svg_raw: bool,
-    /// Flag to enable writing in svg connect offsets
-    pub svg_connect: bool,
-    /// Flag to enable writing in svg split offsets
-    pub svg_split: bool,
-    /// Flag to enable writing in svg pruned offsets
-    pub svg_prune: bool,
-    /// Flag to enable writing in svg final offsets
-    pub svg_final: bool,
+    /// Flag to repair the input before offsetting (see `repair_polyline` and `repair_arcline`)
+    pub repair: bool,
+    /// Shape of the offset at convex corners
+    pub join: JoinStyle,
+    /// Numeric tolerances of the offsetting stages
+    pub tolerances: Tolerances,
+    /// Flag to scale the tolerances from `REFERENCE_SIZE` to the size of the input
+    /// (the largest side of its bounding box, or the offset distance when larger)
+    pub auto_tolerances: bool,
+    /// Limits of the work spent on the offset, and the cancel flag
+    pub budget: Budget<'a>,
+    /// Flag to return the loops in canonical form, see `canonical_polylines`.
+    /// Used by the Polyline functions, the result of the other functions has a fixed order
+    pub canonical: bool,
 }
 
-impl<'a> Default for OffsetCfg<'a> {
+impl Default for OffsetOptions<'_> {
     fn default() -> Self {
-        OffsetCfg {
-            svg: None,
+        OffsetOptions {
             reconnect: true,
-            svg_orig: false,
-            svg_raw: false,
-            svg_connect: false,
-            svg_split: false,
-            svg_prune: false,
-            svg_final: false,
+            repair: false,
+            join: JoinStyle::Round,
+            tolerances: Tolerances::default(),
+            auto_tolerances: false,
+            budget: Budget::default(),
+            canonical: false,
+        }
+    }
+}
+
+impl OffsetOptions<'_> {
+    // Tolerances for input with the given size, see auto_tolerances
+    pub(crate) fn tolerances_for(&self, size: f64, off: f64) -> Tolerances {
+        if self.auto_tolerances {
+            self.tolerances.scaled_to(size.max(off.abs()))
+        } else {
+            self.tolerances
+        }
+    }
+}
+
+/// Configuration of offsetting operations: the options and the observer of the stages.
+///
+/// # Examples
+///
+/// ```rust
+/// use offroad::prelude::*;
+///
+/// let mut cfg = OffsetCfg::default();
+/// cfg.options.join = JoinStyle::Bevel;
+///
+/// // Options without the observer can be copied to other threads
+/// let options: OffsetOptions = cfg.options;
+/// assert_eq!(options.join, JoinStyle::Bevel);
+/// let cfg = OffsetCfg::from(options);
+/// assert!(cfg.observer.is_none());
+/// ```
+#[derive(Default)]
+pub struct OffsetCfg<'a> {
+    /// Optional observer of the offsetting stages, see `SvgObserver` for SVG output
+    pub observer: Option<&'a mut dyn OffsetObserver>,
+    /// Options controlling the offsetting behavior
+    pub options: OffsetOptions<'a>,
+}
+
+impl<'a> From<OffsetOptions<'a>> for OffsetCfg<'a> {
+    fn from(options: OffsetOptions<'a>) -> Self {
+        OffsetCfg {
+            observer: None,
+            options,
         }
     }
 }
@@ -56,8 +130,9 @@ impl<'a> Default for OffsetCfg<'a> {
 /// # Arguments
 ///
 /// * `poly` - The input polyline to offset. Should be a sequence of connected PVertex-es.
-/// * `off` - The offset distance. Only positive values offset to the "right" side of the polyline.
-/// * `cfg` - Configuration options controlling the offsetting behavior and writing to svg file.
+/// * `off` - The offset distance. Positive values offset to the "right" side of the polyline,
+///   negative values to the "left" side.
+/// * `cfg` - Configuration options controlling the offsetting behavior and observing the stages.
 ///
 /// # Returns
 ///
@@ -67,6 +142,7 @@ impl<'a> Default for OffsetCfg<'a> {
 /// - Simple cases may produce a single offset polyline
 /// - Complex geometries or self-intersecting offsets may produce multiple polylines
 /// - Invalid or degenerate cases may produce an empty vector
+///   (use `try_offset_polyline_to_polyline` to find out why)
 ///
 /// # Examples
 ///
@@ -105,32 +181,230 @@ pub fn offset_polyline_to_polyline(
     off: f64,
     cfg: &mut OffsetCfg,
 ) -> Vec<Polyline> {
-    if let Some(svg) = cfg.svg.as_mut()
-        && cfg.svg_orig
-    {
-        svg.polyline(poly, "red");
-    }
-    let offset_arcs = offset_polyline_to_polyline_impl(poly, off, cfg);    
-
-    // Always reconnect arcs
-    let reconnect_arcs = offset_reconnect_arcs(offset_arcs);
-    // println!(
-    //     "DEBUG: offset_reconnect_arcs returned {} components",
-    //     reconnect_arcs.len()
-    // );
-    // for (i, component) in reconnect_arcs.iter().enumerate() {
-    //     println!("DEBUG: Component {}: {} arcs", i, component.len());
-    // }
+    try_offset_polyline_to_polyline(poly, off, cfg).unwrap_or_default()
+}
+
+/// Computes the offset of a Polyline, reporting failures as `OffsetError`.
+///
+/// Same as `offset_polyline_to_polyline`, but instead of an empty vector it returns:
+/// - `OffsetError::InvalidInput` when the polyline has less than 2 vertices,
+///   only degenerate segments, or the offset distance is not finite
+/// - `OffsetError::NonFiniteCoordinate` when a vertex has a NaN or infinite coordinate or bulge
+/// - `OffsetError::SplitIterationLimit` when the split stage does not finish
+///   within `cfg.options.budget.split_steps`
+/// - `OffsetError::Cancelled` and `OffsetError::DeadlineExceeded` when `cfg.options.budget`
+///   stops the offset
+/// - `OffsetError::Collapsed` when nothing is left after the offset
+/// - `OffsetError::UnclosedResult` when the remaining offset segments do not form closed loops
+///
+/// # Errors
+///
+/// See the list above.
+///
+/// # Examples
+///
+/// ```rust
+/// use togo::prelude::*;
+/// use offroad::prelude::*;
+///
+/// let mut cfg = OffsetCfg::default();
+/// // Clockwise square, offset to the right is inward
+/// let poly = vec![
+///     pvertex(point(0.0, 0.0), 0.0),
+///     pvertex(point(0.0, 10.0), 0.0),
+///     pvertex(point(10.0, 10.0), 0.0),
+///     pvertex(point(10.0, 0.0), 0.0),
+/// ];
+///
+/// assert!(try_offset_polyline_to_polyline(&poly, 2.0, &mut cfg).is_ok());
+/// assert_eq!(
+///     try_offset_polyline_to_polyline(&poly, 6.0, &mut cfg),
+///     Err(OffsetError::Collapsed)
+/// );
+/// ```
+pub fn try_offset_polyline_to_polyline(
+    poly: &Polyline,
+    off: f64,
+    cfg: &mut OffsetCfg,
+) -> Result<Vec<Polyline>, OffsetError> {
+    let repaired;
+    let poly = if cfg.options.repair {
+        repaired = repair_polyline(poly, REPAIR_TOLERANCE);
+        &repaired
+    } else {
+        poly
+    };
+    // Always reconnect arcs, use OffsetPipeline::into_arcs for the unconnected arcs
+    OffsetPipeline::polyline(poly, off, cfg)?.into_polylines()
+}
 
-    let final_poly = arcs_to_polylines(&reconnect_arcs);
+/// Computes the offset of a closed Polyline and returns the containment tree of the result.
+///
+/// Inward offsets can split into several islands, and outward offsets can enclose holes,
+/// the tree tells the outer boundaries from the holes.
+///
+/// # Errors
+///
+/// See `try_offset_polyline_to_polyline`.
+///
+/// # Examples
+///
+/// ```rust
+/// use togo::prelude::*;
+/// use offroad::prelude::*;
+///
+/// let mut cfg = OffsetCfg::default();
+/// // Clockwise dumbbell, two squares joined by a narrow bridge
+/// let poly = vec![
+///     pvertex(point(0.0, 0.0), 0.0),
+///     pvertex(point(0.0, 10.0), 0.0),
+///     pvertex(point(10.0, 10.0), 0.0),
+///     pvertex(point(10.0, 6.0), 0.0),
+///     pvertex(point(20.0, 6.0), 0.0),
+///     pvertex(point(20.0, 10.0), 0.0),
+///     pvertex(point(30.0, 10.0), 0.0),
+///     pvertex(point(30.0, 0.0), 0.0),
+///     pvertex(point(20.0, 0.0), 0.0),
+///     pvertex(point(20.0, 4.0), 0.0),
+///     pvertex(point(10.0, 4.0), 0.0),
+///     pvertex(point(10.0, 0.0), 0.0),
+/// ];
+///
+/// // The bridge closes, two islands are left
+/// let tree = try_offset_polyline_tree(&poly, 2.0, &mut cfg).unwrap();
+/// assert_eq!(tree.roots.len(), 2);
+/// assert!(tree.nodes.iter().all(|node| !node.is_hole()));
+/// ```
+pub fn try_offset_polyline_tree(
+    poly: &Polyline,
+    off: f64,
+    cfg: &mut OffsetCfg,
+) -> Result<ContourTree, OffsetError> {
+    Ok(ContourTree::new(try_offset_polyline_to_polyline(poly, off, cfg)?))
+}
 
-    if let Some(svg) = cfg.svg.as_mut() {
-        if cfg.svg_final {
-            svg.polylines(&final_poly, "violet");
-        }
-    }
+/// Computes the offset of a closed Polyline with the origin of every offset segment.
+///
+/// Same as `try_offset_polyline_to_polyline`, and every result polyline comes with
+/// the origins of its segments: the origin of the segment from vertex `i` to vertex `i + 1`
+/// is at index `i`. `cfg.options.repair` is not used, as it can change the input segments,
+/// and `cfg.options.canonical` is not used, as it changes the start vertices of the loops.
+///
+/// # Errors
+///
+/// See `try_offset_polyline_to_polyline`.
+///
+/// # Examples
+///
+/// ```rust
+/// use togo::prelude::*;
+/// use offroad::prelude::*;
+///
+/// let mut cfg = OffsetCfg::default();
+/// // Clockwise square, offset to the right is inward
+/// let poly = vec![
+///     pvertex(point(0.0, 0.0), 0.0),
+///     pvertex(point(0.0, 10.0), 0.0),
+///     pvertex(point(10.0, 10.0), 0.0),
+///     pvertex(point(10.0, 0.0), 0.0),
+/// ];
+/// // Feed rate of every input segment
+/// let feeds = [100.0, 200.0, 300.0, 400.0];
+///
+/// let res = try_offset_polyline_with_origins(&poly, 1.0, &mut cfg).unwrap();
+/// let (offset, origins) = &res[0];
+/// assert_eq!(offset.len(), origins.len());
+/// let offset_feeds: Vec<f64> = origins.iter().map(|o| feeds[o.segment]).collect();
+/// assert!(offset_feeds.contains(&100.0) && offset_feeds.contains(&400.0));
+/// ```
+pub fn try_offset_polyline_with_origins(
+    poly: &Polyline,
+    off: f64,
+    cfg: &mut OffsetCfg,
+) -> Result<Vec<(Polyline, Vec<ArcOrigin>)>, OffsetError> {
+    let pipeline = OffsetPipeline::polyline(poly, off, cfg)?;
+    let origins = pipeline.origins().clone();
+    let tol = *pipeline.tolerances();
+    let loops = pipeline.reconnect()?;
+    let res = loops
+        .iter()
+        .map(|arcs| {
+            let arc_origins = arcs.iter().filter_map(|arc| origins.get(arc)).collect();
+            (arcs_to_polylines_single(arcs, &tol), arc_origins)
+        })
+        .collect();
+    Ok(res)
+}
+
+/// Computes the offsets of a closed Polyline at many distances.
+///
+/// Same as `offset_polyline_to_polyline` for every distance in `offs`, the offset at
+/// `offs[i]` is at index `i`, empty when the offset fails.
+/// The work that does not depend on the distance, the input segments and the spatial index
+/// of the prune stage, is done once for all distances.
+/// With `OffsetOptions::auto_tolerances`, the tolerances of all distances are those of the
+/// largest finite distance.
+///
+/// # Examples
+///
+/// ```rust
+/// use togo::prelude::*;
+/// use offroad::prelude::*;
+///
+/// let mut cfg = OffsetCfg::default();
+/// // Clockwise square, offset to the right is inward
+/// let poly = vec![
+///     pvertex(point(0.0, 0.0), 0.0),
+///     pvertex(point(0.0, 10.0), 0.0),
+///     pvertex(point(10.0, 10.0), 0.0),
+///     pvertex(point(10.0, 0.0), 0.0),
+/// ];
+/// // Distances are given, not accumulated from a step
+/// let offs: Vec<f64> = (1..=6).map(|i| i as f64 * 0.9).collect();
+///
+/// let res = offset_polyline_multiple(&poly, &offs, &mut cfg);
+/// assert_eq!(res.len(), 6);
+/// assert_eq!(res[4], offset_polyline_to_polyline(&poly, 4.5, &mut cfg));
+/// // The square collapses before 5.4
+/// assert!(res[5].is_empty());
+/// ```
+pub fn offset_polyline_multiple(
+    poly: &Polyline,
+    offs: &[f64],
+    cfg: &mut OffsetCfg,
+) -> Vec<Vec<Polyline>> {
+    try_offset_polyline_multiple(poly, offs, cfg)
+        .into_iter()
+        .map(|res| res.unwrap_or_default())
+        .collect()
+}
 
-    final_poly
+/// Computes the offsets of a closed Polyline at many distances, reporting failures as `OffsetError`.
+///
+/// Same as `offset_polyline_multiple`, with the result of `try_offset_polyline_to_polyline`
+/// for every distance.
+///
+/// # Errors
+///
+/// The result of every distance has its own error, see `try_offset_polyline_to_polyline`.
+pub fn try_offset_polyline_multiple(
+    poly: &Polyline,
+    offs: &[f64],
+    cfg: &mut OffsetCfg,
+) -> Vec<Result<Vec<Polyline>, OffsetError>> {
+    let repaired;
+    let poly = if cfg.options.repair {
+        repaired = repair_polyline(poly, REPAIR_TOLERANCE);
+        &repaired
+    } else {
+        poly
+    };
+    let max_off = offs.iter().map(|off| off.abs()).filter(|off| off.is_finite()).fold(0.0, f64::max);
+    let tol = cfg.options.tolerances_for(points_size(poly.iter().map(|v| v.p)), max_off);
+    let mut shared = None;
+    offs.iter()
+        .map(|&off| OffsetPipeline::polyline_shared(poly, off, tol, cfg, &mut shared)?.into_polylines())
+        .collect()
 }
 
 /// Computes the offset of an Arcline and returns result as multiple Arcline-s.
@@ -142,9 +416,9 @@ pub fn offset_polyline_to_polyline(
 /// # Arguments
 ///
 /// * `arcs` - The input arcline (sequence of arcs) to offset.
-/// * `off` - The offset distance. Positive values only, to the "right" side of the Arcline
-///   direction.
-/// * `cfg` - Configuration options controlling the offsetting behavior and writing to svg file.
+/// * `off` - The offset distance. Positive values offset to the "right" side of the Arcline
+///   direction, negative values to the "left" side.
+/// * `cfg` - Configuration options controlling the offsetting behavior and observing the stages.
 ///
 /// # Returns
 ///
@@ -152,6 +426,7 @@ pub fn offset_polyline_to_polyline(
 /// - Simple cases may produce a single offset arcline
 /// - Complex geometries or self-intersecting offsets may produce multiple arclines
 /// - Invalid or degenerate cases may produce an empty vector
+///   (use `try_offset_arcline_to_arcline` to find out why)
 ///
 /// # Examples
 ///
@@ -185,52 +460,800 @@ pub fn offset_polyline_to_polyline(
 /// 5. Reconnect valid segments into continuous arc-paths
 ///
 pub fn offset_arcline_to_arcline(arcs: &Arcline, off: f64, cfg: &mut OffsetCfg) -> Vec<Arcline> {
-    if let Some(svg) = cfg.svg.as_mut()
-        && cfg.svg_orig
-    {
-        svg.arcline(arcs, "red");
+    try_offset_arcline_to_arcline(arcs, off, cfg).unwrap_or_default()
+}
+
+/// Computes the offset of an Arcline, reporting failures as `OffsetError`.
+///
+/// Same as `offset_arcline_to_arcline`, but returns an `OffsetError` instead of an
+/// empty vector, see `try_offset_polyline_to_polyline` for the list of errors.
+/// `OffsetError::UnclosedResult` is only reported when `cfg.options.reconnect` is set.
+///
+/// # Errors
+///
+/// See `try_offset_polyline_to_polyline`.
+pub fn try_offset_arcline_to_arcline(
+    arcs: &Arcline,
+    off: f64,
+    cfg: &mut OffsetCfg,
+) -> Result<Vec<Arcline>, OffsetError> {
+    let repaired;
+    let arcs = if cfg.options.repair {
+        repaired = repair_arcline(arcs, REPAIR_TOLERANCE);
+        &repaired
+    } else {
+        arcs
+    };
+    let reconnect = cfg.options.reconnect;
+    let pipeline = OffsetPipeline::arcline(arcs, off, cfg)?.prune()?;
+    if reconnect {
+        return pipeline.reconnect();
+    }
+    let final_arcs = vec![pipeline.into_arcs()?];
+    if let Some(observer) = cfg.observer.as_mut() {
+        observer.output(&final_arcs);
     }
+    Ok(final_arcs)
+}
+
+/// Offsets a closed Polyline outward, away from the enclosed area.
+///
+/// The side is found from the sign of the polyline area, so the result does not depend
+/// on the vertex order (clockwise or counter-clockwise) or on the Y-axis convention
+/// (Y up or Y down) of the input.
+/// Negative `off` offsets inward.
+///
+/// # Examples
+///
+/// ```rust
+/// use togo::prelude::*;
+/// use offroad::prelude::*;
+///
+/// let mut cfg = OffsetCfg::default();
+/// let poly = vec![
+///     pvertex(point(0.0, 0.0), 0.0),
+///     pvertex(point(10.0, 0.0), 0.0),
+///     pvertex(point(10.0, 10.0), 0.0),
+///     pvertex(point(0.0, 10.0), 0.0),
+/// ];
+/// // Same result for both orientations
+/// let ccw = offset_outward(&poly, 1.0, &mut cfg);
+/// let cw = offset_outward(&polyline_reverse(&poly), 1.0, &mut cfg);
+/// assert_eq!(ccw.len(), 1);
+/// assert_eq!(cw.len(), 1);
+/// ```
+pub fn offset_outward(poly: &Polyline, off: f64, cfg: &mut OffsetCfg) -> Vec<Polyline> {
+    try_offset_outward(poly, off, cfg).unwrap_or_default()
+}
+
+/// Offsets a closed Polyline inward, toward the enclosed area.
+///
+/// See `offset_outward`, negative `off` offsets outward.
+pub fn offset_inward(poly: &Polyline, off: f64, cfg: &mut OffsetCfg) -> Vec<Polyline> {
+    try_offset_inward(poly, off, cfg).unwrap_or_default()
+}
+
+/// Offsets a closed Polyline outward, reporting failures as `OffsetError`.
+///
+/// # Errors
+///
+/// `OffsetError::InvalidInput` when the polyline encloses no area,
+/// otherwise see `try_offset_polyline_to_polyline`.
+pub fn try_offset_outward(
+    poly: &Polyline,
+    off: f64,
+    cfg: &mut OffsetCfg,
+) -> Result<Vec<Polyline>, OffsetError> {
+    let side = polyline_side(poly)?;
+    try_offset_polyline_to_polyline(poly, side * off, cfg)
+}
+
+/// Offsets a closed Polyline inward, reporting failures as `OffsetError`.
+///
+/// # Errors
+///
+/// See `try_offset_outward`.
+pub fn try_offset_inward(
+    poly: &Polyline,
+    off: f64,
+    cfg: &mut OffsetCfg,
+) -> Result<Vec<Polyline>, OffsetError> {
+    let side = polyline_side(poly)?;
+    try_offset_polyline_to_polyline(poly, -side * off, cfg)
+}
+
+/// Offsets a closed Arcline outward, away from the enclosed area.
+///
+/// Same as `offset_outward`, for Arclines.
+pub fn offset_arcline_outward(arcs: &Arcline, off: f64, cfg: &mut OffsetCfg) -> Vec<Arcline> {
+    try_offset_arcline_outward(arcs, off, cfg).unwrap_or_default()
+}
+
+/// Offsets a closed Arcline inward, toward the enclosed area.
+///
+/// Same as `offset_inward`, for Arclines.
+pub fn offset_arcline_inward(arcs: &Arcline, off: f64, cfg: &mut OffsetCfg) -> Vec<Arcline> {
+    try_offset_arcline_inward(arcs, off, cfg).unwrap_or_default()
+}
+
+/// Offsets a closed Arcline outward, reporting failures as `OffsetError`.
+///
+/// # Errors
+///
+/// `OffsetError::InvalidInput` when the arcline encloses no area,
+/// otherwise see `try_offset_arcline_to_arcline`.
+pub fn try_offset_arcline_outward(
+    arcs: &Arcline,
+    off: f64,
+    cfg: &mut OffsetCfg,
+) -> Result<Vec<Arcline>, OffsetError> {
+    let side = arcline_side(arcs, off, &cfg.options)?;
+    try_offset_arcline_to_arcline(arcs, side * off, cfg)
+}
 
-    let offset_arcs = offset_arcline_to_arcline_impl(arcs, off, cfg);
+/// Offsets a closed Arcline inward, reporting failures as `OffsetError`.
+///
+/// # Errors
+///
+/// See `try_offset_arcline_outward`.
+pub fn try_offset_arcline_inward(
+    arcs: &Arcline,
+    off: f64,
+    cfg: &mut OffsetCfg,
+) -> Result<Vec<Arcline>, OffsetError> {
+    let side = arcline_side(arcs, off, &cfg.options)?;
+    try_offset_arcline_to_arcline(arcs, -side * off, cfg)
+}
 
-    let mut final_arcs = Vec::new();
-    if cfg.reconnect {
-        final_arcs = offset_reconnect_arcs(offset_arcs);
+// Sign of the offset that goes outward: the right side is outside of counter-clockwise shapes
+fn area_side(area: f64) -> Result<f64, OffsetError> {
+    if area > 0.0 {
+        Ok(1.0)
+    } else if area < 0.0 {
+        Ok(-1.0)
     } else {
-        final_arcs.push(offset_arcs);
+        Err(OffsetError::InvalidInput(format!(
+            "shape area is {}, outside is undefined",
+            area
+        )))
+    }
+}
+
+fn polyline_side(poly: &Polyline) -> Result<f64, OffsetError> {
+    area_side(polyline_signed_area(poly))
+}
+
+fn arcline_side(arcs: &Arcline, off: f64, options: &OffsetOptions) -> Result<f64, OffsetError> {
+    let tol = options.tolerances_for(points_size(arcs.iter().flat_map(|a| [a.a, a.b])), off);
+    area_side(arcline_signed_area_with_tolerance(arcs, tol.vertex))
+}
+
+pub(crate) fn check_offset(off: f64) -> Result<(), OffsetError> {
+    if !off.is_finite() {
+        return Err(OffsetError::InvalidInput(format!(
+            "offset distance {} is not finite",
+            off
+        )));
     }
+    Ok(())
+}
 
-    if let Some(svg) = cfg.svg.as_mut() {
-        if cfg.svg_final {
-            svg.arclines(&final_arcs, "violet");
+pub(crate) fn check_polyline(poly: &Polyline, off: f64) -> Result<(), OffsetError> {
+    check_offset(off)?;
+    if poly.len() < 2 {
+        return Err(OffsetError::InvalidInput(format!(
+            "polyline has {} vertices, at least 2 are needed",
+            poly.len()
+        )));
+    }
+    for (i, v) in poly.iter().enumerate() {
+        if !v.p.x.is_finite() || !v.p.y.is_finite() || !v.b.is_finite() {
+            return Err(OffsetError::NonFiniteCoordinate(i));
         }
     }
+    Ok(())
+}
 
-    final_arcs
+pub(crate) fn check_arcline(arcs: &Arcline, off: f64) -> Result<(), OffsetError> {
+    check_offset(off)?;
+    if arcs.is_empty() {
+        return Err(OffsetError::InvalidInput("arcline is empty".to_string()));
+    }
+    for (i, arc) in arcs.iter().enumerate() {
+        let finite = arc.a.x.is_finite()
+            && arc.a.y.is_finite()
+            && arc.b.x.is_finite()
+            && arc.b.y.is_finite();
+        // Line segments have infinite center and radius
+        let finite_arc = arc.is_seg() || (arc.c.x.is_finite() && arc.c.y.is_finite());
+        if !finite || !finite_arc {
+            return Err(OffsetError::NonFiniteCoordinate(i));
+        }
+    }
+    Ok(())
 }
 
-fn offset_polyline_to_polyline_impl(poly: &Polyline, off: f64, cfg: &mut OffsetCfg) -> Vec<Arc> {
-    let mut plines = Vec::new();
-    plines.push(poly.clone());
-    let poly_raws = poly_to_raws(&plines);
-    let offset_arcs = offset_single(&poly_raws, off, cfg);
-    offset_arcs
+#[cfg(test)]
+mod test_try_offset {
+    use super::*;
+
+    fn square_cw() -> Polyline {
+        vec![
+            pvertex(point(0.0, 0.0), 0.0),
+            pvertex(point(0.0, 10.0), 0.0),
+            pvertex(point(10.0, 10.0), 0.0),
+            pvertex(point(10.0, 0.0), 0.0),
+        ]
+    }
+
+    #[test]
+    fn test_try_offset_ok_matches_infallible() {
+        let mut cfg = OffsetCfg::default();
+        let res = try_offset_polyline_to_polyline(&square_cw(), 2.0, &mut cfg).unwrap();
+        let expected = offset_polyline_to_polyline(&square_cw(), 2.0, &mut cfg);
+        assert_eq!(res, expected);
+        assert_eq!(res.len(), 1);
+    }
+
+    #[test]
+    fn test_try_offset_too_few_vertices() {
+        let mut cfg = OffsetCfg::default();
+        let poly = vec![pvertex(point(0.0, 0.0), 0.0)];
+        let res = try_offset_polyline_to_polyline(&poly, 2.0, &mut cfg);
+        assert!(matches!(res, Err(OffsetError::InvalidInput(_))));
+        assert!(offset_polyline_to_polyline(&poly, 2.0, &mut cfg).is_empty());
+    }
+
+    #[test]
+    fn test_try_offset_non_finite_coordinate() {
+        let mut cfg = OffsetCfg::default();
+        let mut poly = square_cw();
+        poly[2].p.y = f64::NAN;
+        let res = try_offset_polyline_to_polyline(&poly, 2.0, &mut cfg);
+        assert_eq!(res, Err(OffsetError::NonFiniteCoordinate(2)));
+
+        let mut poly = square_cw();
+        poly[1].b = f64::INFINITY;
+        let res = try_offset_polyline_to_polyline(&poly, 2.0, &mut cfg);
+        assert_eq!(res, Err(OffsetError::NonFiniteCoordinate(1)));
+    }
+
+    #[test]
+    fn test_try_offset_non_finite_distance() {
+        let mut cfg = OffsetCfg::default();
+        let res = try_offset_polyline_to_polyline(&square_cw(), f64::NAN, &mut cfg);
+        assert!(matches!(res, Err(OffsetError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_try_offset_degenerate_segments() {
+        let mut cfg = OffsetCfg::default();
+        let poly = vec![pvertex(point(1.0, 1.0), 0.0), pvertex(point(1.0, 1.0), 0.0)];
+        let res = try_offset_polyline_to_polyline(&poly, 2.0, &mut cfg);
+        assert!(matches!(res, Err(OffsetError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_try_offset_collapsed() {
+        let mut cfg = OffsetCfg::default();
+        let res = try_offset_polyline_to_polyline(&square_cw(), 6.0, &mut cfg);
+        assert_eq!(res, Err(OffsetError::Collapsed));
+    }
+
+    #[test]
+    fn test_try_offset_repair() {
+        let mut poly = square_cw();
+        poly[2].p.y = f64::NAN;
+        poly.push(pvertex(point(0.0, 0.0), 0.0));
+        let mut cfg = OffsetCfg::default();
+        let res = try_offset_polyline_to_polyline(&poly, 2.0, &mut cfg);
+        assert_eq!(res, Err(OffsetError::NonFiniteCoordinate(2)));
+        cfg.options.repair = true;
+        let res = try_offset_polyline_to_polyline(&poly, 1.0, &mut cfg).unwrap();
+        assert_eq!(res.len(), 1);
+    }
+
+    #[test]
+    fn test_try_offset_arcline() {
+        let mut cfg = OffsetCfg::default();
+        let arcs = vec![
+            arcseg(point(0.0, 0.0), point(0.0, 10.0)),
+            arcseg(point(0.0, 10.0), point(10.0, 10.0)),
+            arcseg(point(10.0, 10.0), point(10.0, 0.0)),
+            arcseg(point(10.0, 0.0), point(0.0, 0.0)),
+        ];
+        let res = try_offset_arcline_to_arcline(&arcs, 2.0, &mut cfg).unwrap();
+        assert_eq!(res.len(), 1);
+        let res = try_offset_arcline_to_arcline(&arcs, 6.0, &mut cfg);
+        assert_eq!(res, Err(OffsetError::Collapsed));
+        let res = try_offset_arcline_to_arcline(&Vec::new(), 2.0, &mut cfg);
+        assert!(matches!(res, Err(OffsetError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_try_offset_budget() {
+        let poly = crate::poly::pline_01()[0].clone();
+        let cancel = std::sync::atomic::AtomicBool::new(true);
+        let mut cfg = OffsetCfg::from(OffsetOptions {
+            budget: Budget::default().with_cancel(&cancel),
+            ..OffsetOptions::default()
+        });
+        let res = try_offset_polyline_to_polyline(&poly, 8.0, &mut cfg);
+        assert_eq!(res, Err(OffsetError::Cancelled));
+
+        let mut cfg = OffsetCfg::default();
+        cfg.options.budget.deadline = Some(std::time::Instant::now());
+        let res = try_offset_polyline_to_polyline(&poly, 8.0, &mut cfg);
+        assert_eq!(res, Err(OffsetError::DeadlineExceeded));
+
+        let mut cfg = OffsetCfg::default();
+        cfg.options.budget.split_steps = 10;
+        let res = try_offset_polyline_to_polyline(&poly, 8.0, &mut cfg);
+        assert_eq!(res, Err(OffsetError::SplitIterationLimit(10)));
+    }
+
+    #[test]
+    fn test_try_offset_auto_tolerances() {
+        use crate::area::polyline_signed_area;
+        let area = |polys: &Vec<Polyline>| -> f64 {
+            polys.iter().map(|p| polyline_signed_area(p).abs()).sum()
+        };
+        let poly = crate::poly::pline_01()[0].clone();
+        let mut cfg = OffsetCfg::default();
+        let expected = area(&try_offset_polyline_to_polyline(&poly, 8.0, &mut cfg).unwrap());
+
+        // Large coordinates need the tolerances scaled with the input
+        let scale = 1e4;
+        let big: Polyline = poly
+            .iter()
+            .map(|v| pvertex(point(v.p.x * scale, v.p.y * scale), v.b))
+            .collect();
+        cfg.options.auto_tolerances = true;
+        let res = try_offset_polyline_to_polyline(&big, 8.0 * scale, &mut cfg).unwrap();
+        let ratio = area(&res) / (expected * scale * scale);
+        assert!((ratio - 1.0).abs() < 1e-6, "{}", ratio);
+    }
+
+    #[test]
+    fn test_try_offset_many_segments() {
+        use crate::area::polyline_signed_area;
+        // Clockwise square, with 100000 zero length segments after the first vertex.
+        // The ids of the corner joins of older versions collided with the following segments.
+        let mut poly = vec![pvertex(point(0.0, 0.0), 0.0)];
+        poly.extend(std::iter::repeat_n(pvertex(point(0.0, 10.0), 0.0), 100_000));
+        poly.push(pvertex(point(10.0, 10.0), 0.0));
+        poly.push(pvertex(point(10.0, 0.0), 0.0));
+        let mut cfg = OffsetCfg::default();
+        for off in [1.0, -1.0] {
+            let res = try_offset_polyline_with_origins(&poly, off, &mut cfg).unwrap();
+            assert_eq!(res.len(), 1);
+            let (offset, origins) = &res[0];
+            assert_eq!(offset.len(), origins.len());
+            assert!(origins.iter().any(|o| o.segment == 100_000));
+            let area = polyline_signed_area(offset).abs();
+            let expected = if off > 0.0 { 64.0 } else { 140.0 + std::f64::consts::PI };
+            assert!((area - expected).abs() < 1e-9, "{} {}", area, expected);
+        }
+    }
+
+    // Bits of the offsets of the test shapes, one line per loop
+    fn offset_bits() -> String {
+        let polys = [crate::poly::pline_01()[0].clone(), crate::poly::pline_02()];
+        let mut out = String::new();
+        for poly in polys.iter() {
+            for off in [3.0, 16.0, -5.0] {
+                let mut cfg = OffsetCfg::default();
+                for p in try_offset_polyline_to_polyline(poly, off, &mut cfg).unwrap() {
+                    out.push_str("bits:");
+                    for v in p.iter() {
+                        let bits = [v.p.x.to_bits(), v.p.y.to_bits(), v.b.to_bits()];
+                        out.push_str(&format!(" {:x} {:x} {:x}", bits[0], bits[1], bits[2]));
+                    }
+                    out.push('\n');
+                }
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_try_offset_deterministic() {
+        assert_eq!(offset_bits(), offset_bits());
+    }
+
+    #[test]
+    fn test_try_offset_deterministic_processes() {
+        // The child process prints the bits, the hash seeds of the processes differ
+        const CHILD: &str = "OFFROAD_OFFSET_BITS_CHILD";
+        if std::env::var_os(CHILD).is_some() {
+            print!("{}", offset_bits());
+            return;
+        }
+        let run = || {
+            let out = std::process::Command::new(std::env::current_exe().unwrap())
+                .args([
+                    "offset::test_try_offset::test_try_offset_deterministic_processes",
+                    "--exact",
+                    "--nocapture",
+                    "--test-threads=1",
+                ])
+                .env(CHILD, "1")
+                .output()
+                .unwrap();
+            assert!(out.status.success());
+            let out = String::from_utf8(out.stdout).unwrap();
+            // The test harness writes the name of the test on the line of the first loop
+            out.lines()
+                .filter_map(|line| line.find("bits:").map(|i| format!("{}\n", &line[i..])))
+                .collect::<String>()
+        };
+        let first = run();
+        assert!(!first.is_empty());
+        assert_eq!(first, run());
+        assert_eq!(first, offset_bits());
+    }
+
+    #[test]
+    fn test_try_offset_canonical() {
+        let poly = crate::poly::pline_01()[0].clone();
+        let mut cfg = OffsetCfg::from(OffsetOptions {
+            canonical: true,
+            ..OffsetOptions::default()
+        });
+        let res = try_offset_polyline_to_polyline(&poly, 16.0, &mut cfg).unwrap();
+        assert!(res.len() > 1);
+        for pair in res.windows(2) {
+            let min_x = |p: &Polyline| p.iter().map(|v| v.p.x).fold(f64::INFINITY, f64::min);
+            assert!(min_x(&pair[0]) <= min_x(&pair[1]));
+        }
+        for loop_ in res.iter() {
+            assert!(loop_.iter().all(|v| v.p.x >= loop_[0].p.x));
+        }
+        // Outer boundaries counter-clockwise, holes clockwise
+        let tree = ContourTree::new(res.clone());
+        assert!(tree.nodes.iter().all(|node| node.ccw != node.is_hole()));
+        assert_eq!(crate::canonical::canonical_polylines(res.clone()), res);
+    }
+
+    #[test]
+    fn test_try_offset_multiple() {
+        let poly = crate::poly::pline_01()[0].clone();
+        let offs = [1.0, 4.0, 8.0, -2.0, f64::NAN, 12.0, 40.0];
+        for join in [JoinStyle::Round, JoinStyle::Bevel] {
+            for auto_tolerances in [false, true] {
+                let mut cfg = OffsetCfg::from(OffsetOptions {
+                    join,
+                    auto_tolerances,
+                    ..OffsetOptions::default()
+                });
+                let res = try_offset_polyline_multiple(&poly, &offs, &mut cfg);
+                assert_eq!(res.len(), offs.len());
+                // Auto tolerances of all distances are those of the largest one
+                let size = points_size(poly.iter().map(|v| v.p));
+                let mut single = OffsetCfg::from(OffsetOptions {
+                    join,
+                    tolerances: cfg.options.tolerances_for(size, 40.0),
+                    ..OffsetOptions::default()
+                });
+                for (&off, res) in offs.iter().zip(res.iter()) {
+                    assert_eq!(*res, try_offset_polyline_to_polyline(&poly, off, &mut single), "{}", off);
+                }
+                assert!(matches!(res[4], Err(OffsetError::InvalidInput(_))));
+            }
+        }
+        let mut cfg = OffsetCfg::default();
+        assert!(offset_polyline_multiple(&poly, &[], &mut cfg).is_empty());
+    }
+
+    #[test]
+    fn test_try_offset_with_origins() {
+        use crate::origin::OriginKind;
+        // Clockwise square, negative offset is outward
+        let poly = vec![
+            pvertex(point(0.0, 0.0), 0.0),
+            pvertex(point(0.0, 10.0), 0.0),
+            pvertex(point(10.0, 10.0), 0.0),
+            pvertex(point(10.0, 0.0), 0.0),
+        ];
+        let mut cfg = OffsetCfg::default();
+        let res = try_offset_polyline_with_origins(&poly, -1.0, &mut cfg).unwrap();
+        assert_eq!(res.len(), 1);
+        let (offset, origins) = &res[0];
+        assert_eq!(offset.len(), 8);
+        assert_eq!(origins.len(), 8);
+        let n = offset.len();
+        for (i, origin) in origins.iter().enumerate() {
+            assert_eq!(origin.contour, 0);
+            if origin.kind == OriginKind::Join {
+                assert!(offset[i].b != 0.0);
+                continue;
+            }
+            // Offset segments are parallel to their input segment at distance 1
+            let (a, b) = (poly[origin.segment].p, poly[(origin.segment + 1) % 4].p);
+            let (dir, _) = (b - a).normalize(false);
+            for p in [offset[i].p, offset[(i + 1) % n].p] {
+                let dist = (p - a).perp(dir).abs();
+                assert!((dist - 1.0).abs() < 1e-9, "{:?} {}", origin, dist);
+            }
+        }
+        assert_eq!(origins.iter().filter(|o| o.kind == OriginKind::Join).count(), 4);
+
+        // Every output segment has an origin in the input
+        let poly = crate::poly::pline_01()[0].clone();
+        for join in [JoinStyle::Round, JoinStyle::Bevel, JoinStyle::Miter { limit: 4.0 }, JoinStyle::Extend] {
+            let mut cfg = OffsetCfg::from(OffsetOptions {
+                join,
+                ..OffsetOptions::default()
+            });
+            for off in [2.0, 8.0, -5.0] {
+                let res = try_offset_polyline_with_origins(&poly, off, &mut cfg).unwrap();
+                for (offset, origins) in res.iter() {
+                    assert_eq!(offset.len(), origins.len(), "{:?} {}", join, off);
+                    assert!(origins.iter().all(|o| o.segment < poly.len()));
+                }
+            }
+        }
+    }
 }
 
-fn offset_arcline_to_arcline_impl(arcs: &Arcline, off: f64, cfg: &mut OffsetCfg) -> Vec<Arc> {
-    let mut alines = Vec::new();
-    alines.push(arcs.clone());
-    let poly_raws = arcs_to_raws(&alines);
-    let offset_arcs = offset_single(&poly_raws, off, cfg);
-    offset_arcs
+#[cfg(test)]
+mod test_offset_outward {
+    use super::*;
+    use crate::area::arcline_signed_area;
+
+    fn square_ccw() -> Polyline {
+        vec![
+            pvertex(point(0.0, 0.0), 0.0),
+            pvertex(point(10.0, 0.0), 0.0),
+            pvertex(point(10.0, 10.0), 0.0),
+            pvertex(point(0.0, 10.0), 0.0),
+        ]
+    }
+
+    fn abs_area(res: &[Polyline]) -> f64 {
+        assert_eq!(res.len(), 1);
+        polyline_signed_area(&res[0]).abs()
+    }
+
+    // Square grown by 1.0 with round corners
+    const OUTWARD_AREA: f64 = 100.0 + 40.0 + std::f64::consts::PI;
+
+    #[test]
+    fn test_offset_outward_both_orientations() {
+        let mut cfg = OffsetCfg::default();
+        let ccw = offset_outward(&square_ccw(), 1.0, &mut cfg);
+        let cw = offset_outward(&polyline_reverse(&square_ccw()), 1.0, &mut cfg);
+        assert!((abs_area(&ccw) - OUTWARD_AREA).abs() < 1e-9);
+        assert!((abs_area(&cw) - OUTWARD_AREA).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_offset_inward_both_orientations() {
+        let mut cfg = OffsetCfg::default();
+        let ccw = offset_inward(&square_ccw(), 2.0, &mut cfg);
+        let cw = offset_inward(&polyline_reverse(&square_ccw()), 2.0, &mut cfg);
+        assert!((abs_area(&ccw) - 36.0).abs() < 1e-9);
+        assert!((abs_area(&cw) - 36.0).abs() < 1e-9);
+        // Negative distance goes the other way
+        let out = offset_inward(&square_ccw(), -1.0, &mut cfg);
+        assert!((abs_area(&out) - OUTWARD_AREA).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_offset_outward_y_down() {
+        // Same square with the Y axis pointing down
+        let mut cfg = OffsetCfg::default();
+        let flipped: Polyline = square_ccw()
+            .iter()
+            .map(|v| pvertex(point(v.p.x, -v.p.y), -v.b))
+            .collect();
+        let res = offset_outward(&flipped, 1.0, &mut cfg);
+        assert!((abs_area(&res) - OUTWARD_AREA).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_offset_outward_zero_area() {
+        let mut cfg = OffsetCfg::default();
+        let poly = vec![
+            pvertex(point(0.0, 0.0), 0.0),
+            pvertex(point(10.0, 0.0), 0.0),
+        ];
+        let res = try_offset_outward(&poly, 1.0, &mut cfg);
+        assert!(matches!(res, Err(OffsetError::InvalidInput(_))));
+        assert!(offset_inward(&poly, 1.0, &mut cfg).is_empty());
+    }
+
+    #[test]
+    fn test_offset_arcline_outward_inward() {
+        let mut cfg = OffsetCfg::default();
+        let ccw = vec![
+            arcseg(point(0.0, 0.0), point(10.0, 0.0)),
+            arcseg(point(10.0, 0.0), point(10.0, 10.0)),
+            arcseg(point(10.0, 10.0), point(0.0, 10.0)),
+            arcseg(point(0.0, 10.0), point(0.0, 0.0)),
+        ];
+        let cw: Arcline = ccw.iter().rev().map(|a| arcseg(a.b, a.a)).collect();
+        for arcs in [ccw, cw] {
+            let out = offset_arcline_outward(&arcs, 1.0, &mut cfg);
+            assert_eq!(out.len(), 1);
+            assert!((arcline_signed_area(&out[0]).abs() - OUTWARD_AREA).abs() < 1e-9);
+            let inside = offset_arcline_inward(&arcs, 2.0, &mut cfg);
+            assert_eq!(inside.len(), 1);
+            assert!((arcline_signed_area(&inside[0]).abs() - 36.0).abs() < 1e-9);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_join_style {
+    use super::*;
+
+    fn square_ccw() -> Polyline {
+        vec![
+            pvertex(point(0.0, 0.0), 0.0),
+            pvertex(point(10.0, 0.0), 0.0),
+            pvertex(point(10.0, 10.0), 0.0),
+            pvertex(point(0.0, 10.0), 0.0),
+        ]
+    }
+
+    fn l_shape_ccw() -> Polyline {
+        vec![
+            pvertex(point(0.0, 0.0), 0.0),
+            pvertex(point(20.0, 0.0), 0.0),
+            pvertex(point(20.0, 10.0), 0.0),
+            pvertex(point(10.0, 10.0), 0.0),
+            pvertex(point(10.0, 20.0), 0.0),
+            pvertex(point(0.0, 20.0), 0.0),
+        ]
+    }
+
+    fn offset_with(poly: &Polyline, off: f64, join: JoinStyle) -> Vec<Polyline> {
+        let mut cfg = OffsetCfg::from(OffsetOptions {
+            join,
+            ..OffsetOptions::default()
+        });
+        offset_polyline_to_polyline(poly, off, &mut cfg)
+    }
+
+    #[test]
+    fn test_join_style_square() {
+        let res = offset_with(&square_ccw(), 1.0, JoinStyle::Miter { limit: 2.0 });
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].len(), 4);
+        assert!((polyline_signed_area(&res[0]).abs() - 144.0).abs() < 1e-9);
+
+        let res = offset_with(&square_ccw(), 1.0, JoinStyle::Bevel);
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].len(), 8);
+        assert!((polyline_signed_area(&res[0]).abs() - 142.0).abs() < 1e-9);
+
+        // Miter of a square corner is sqrt(2) times the offset
+        let res = offset_with(&square_ccw(), 1.0, JoinStyle::Miter { limit: 1.4 });
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].len(), 8);
+    }
+
+    #[test]
+    fn test_join_style_concave_corner() {
+        let res = offset_with(&l_shape_ccw(), 1.0, JoinStyle::Miter { limit: 4.0 });
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].len(), 6);
+        // Inward the concave corner of the L is the one joined
+        let mut cfg = OffsetCfg::from(OffsetOptions {
+            join: JoinStyle::Bevel,
+            ..OffsetOptions::default()
+        });
+        let res = offset_inward(&l_shape_ccw(), 1.0, &mut cfg);
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].len(), 7);
+        let expected = 18.0 * 8.0 + 8.0 * 10.0 + 0.5;
+        assert!((polyline_signed_area(&res[0]).abs() - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_join_style_extend_lines() {
+        // Lines are extended to the miter tip, without limit
+        let res = offset_with(&square_ccw(), 1.0, JoinStyle::Extend);
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].len(), 4);
+        assert!((polyline_signed_area(&res[0]).abs() - 144.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_join_style_extend_arc() {
+        // Right side bulging out, circle center (6.25, 5) and radius 6.25
+        let mut poly = square_ccw();
+        poly[1].b = 0.5;
+        let res = offset_with(&poly, 1.0, JoinStyle::Extend);
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].len(), 4);
+        // The offset lines meet the offset circle of radius 7.25
+        let x = 6.25 + (7.25f64 * 7.25 - 6.0 * 6.0).sqrt();
+        for corner in [point(x, -1.0), point(x, 11.0)] {
+            assert!(res[0].iter().any(|v| v.p.close_enough(corner, 1e-9)), "{:?}", corner);
+        }
+        let round = offset_with(&poly, 1.0, JoinStyle::Round);
+        assert!(polyline_signed_area(&res[0]).abs() > polyline_signed_area(&round[0]).abs());
+    }
+
+    #[test]
+    fn test_join_style_extend_round_fallback() {
+        // Top side bulging in, the offset circle of radius 5.25 around (5, 13.75)
+        // does not reach the extended offsets of the sides
+        let mut poly = square_ccw();
+        poly[2].b = -0.5;
+        let res = offset_with(&poly, 1.0, JoinStyle::Extend);
+        assert_eq!(res.len(), 1);
+        // Two round joins at the top corners, extended lines at the bottom corners
+        assert_eq!(res[0].len(), 6);
+        assert_eq!(res[0].iter().filter(|v| v.b != 0.0).count(), 3);
+    }
+
+    #[test]
+    fn test_join_style_extend_dip() {
+        // Half circle of radius 3 dipping into the top side, its outward offset
+        // of radius 1.5 touches the offset of the top side
+        let poly = vec![
+            pvertex(point(0.0, 0.0), 0.0),
+            pvertex(point(20.0, 0.0), 0.0),
+            pvertex(point(20.0, 10.0), 0.0),
+            pvertex(point(13.0, 10.0), -1.0),
+            pvertex(point(7.0, 10.0), 0.0),
+            pvertex(point(0.0, 10.0), 0.0),
+        ];
+        for off in [1.5, 1.0, 2.0] {
+            let res = offset_with(&poly, off, JoinStyle::Extend);
+            let round = offset_with(&poly, off, JoinStyle::Round);
+            // The arc is not extended all around its circle into a separate loop
+            assert_eq!(res.len(), 1, "{}", off);
+            assert_eq!(round.len(), 1, "{}", off);
+            assert!(polyline_signed_area(&res[0]).abs() > polyline_signed_area(&round[0]).abs());
+        }
+    }
+
+    #[test]
+    fn test_join_style_pline_01() {
+        let poly = crate::poly::pline_01()[0].clone();
+        let reversed = polyline_reverse(&poly);
+        for join in [JoinStyle::Miter { limit: 4.0 }, JoinStyle::Bevel, JoinStyle::Extend] {
+            for off in [2.0, 8.0, 16.0, 25.0] {
+                let mut cfg = OffsetCfg::from(OffsetOptions {
+                    join,
+                    ..OffsetOptions::default()
+                });
+                let res = try_offset_polyline_to_polyline(&reversed, off, &mut cfg);
+                assert!(res.is_ok(), "{:?} {} {:?}", join, off, res.as_ref().err());
+            }
+        }
+        // Number of outward and inward loops with extend joins, for both orientations.
+        // At 12 the extended lines of the sharp corner at (128, 192) enclose a hole,
+        // as the miter joins do.
+        let expected = [(2.0, 1, 2), (3.0, 1, 2), (8.0, 1, 2), (12.0, 2, 4), (16.0, 1, 4), (24.0, 1, 3)];
+        for poly in [&poly, &reversed] {
+            for (off, outward, inward) in expected {
+                let mut cfg = OffsetCfg::from(OffsetOptions {
+                    join: JoinStyle::Extend,
+                    ..OffsetOptions::default()
+                });
+                assert_eq!(offset_outward(poly, off, &mut cfg).len(), outward, "{}", off);
+                assert_eq!(offset_inward(poly, off, &mut cfg).len(), inward, "{}", off);
+            }
+        }
+        let mut cfg = OffsetCfg::from(OffsetOptions {
+            join: JoinStyle::Miter { limit: 4.0 },
+            ..OffsetOptions::default()
+        });
+        assert_eq!(offset_outward(&poly, 12.0, &mut cfg).len(), 2);
+    }
 }
 
 #[doc(hidden)]
 /// Converts a vector of arcs into a vector of polylines.
-pub fn arcs_to_polylines(reconnect_arcs: &Vec<Vec<Arc>>) -> Vec<Polyline> {
+pub fn arcs_to_polylines(reconnect_arcs: &Vec<Vec<Arc>>, tol: &Tolerances) -> Vec<Polyline> {
     let mut polylines = Vec::with_capacity(reconnect_arcs.len());
     for arcs in reconnect_arcs.iter() {
-        let polyline = arcs_to_polylines_single(arcs);
+        let polyline = arcs_to_polylines_single(arcs, tol);
         polylines.push(polyline);
     }
     polylines
@@ -239,8 +1262,9 @@ pub fn arcs_to_polylines(reconnect_arcs: &Vec<Vec<Arc>>) -> Vec<Polyline> {
 #[doc(hidden)]
 /// function to convert from Vec<Arc> to Polyline
 /// Note: arcs is a loop of arcs and when converting to PVertex,
-/// some Arc can be either "a" to "b" or "b" to "a" oriented
-pub fn arcs_to_polylines_single(arcs: &Vec<Arc>) -> Polyline {
+/// some Arc can be either "a" to "b" or "b" to "a" oriented,
+/// arcs with ends closer than `tol.output` are connected
+pub fn arcs_to_polylines_single(arcs: &Vec<Arc>, tol: &Tolerances) -> Polyline {
     let mut polyline = Vec::new();
 
     if arcs.is_empty() {
@@ -264,7 +1288,7 @@ pub fn arcs_to_polylines_single(arcs: &Vec<Arc>) -> Polyline {
             let prev_end = current_end_point;
 
             // Check if arc.a connects to previous end point
-            let use_forward = prev_end.close_enough(arc.a, 1e-10);
+            let use_forward = prev_end.close_enough(arc.a, tol.output);
 
             if use_forward {
                 // Use arc in forward direction (a -> b)
@@ -310,7 +1334,7 @@ mod test_arcs_to_polylines {
         ];
 
         // Convert to polyline
-        let polyline = arcs_to_polylines_single(&arcs);
+        let polyline = arcs_to_polylines_single(&arcs, &Tolerances::default());
 
         // Should have 3 vertices (one for each arc start point)
         assert_eq!(polyline.len(), 3);
@@ -340,7 +1364,7 @@ mod test_arcs_to_polylines {
         ];
 
         // Convert to polyline
-        let polyline = arcs_to_polylines_single(&arcs);
+        let polyline = arcs_to_polylines_single(&arcs, &Tolerances::default());
 
         // Should have 2 vertices
         assert_eq!(polyline.len(), 2);
@@ -359,7 +1383,7 @@ mod test_arcs_to_polylines {
     #[test]
     fn test_arcs_to_polylines_single_empty() {
         let arcs = vec![];
-        let polyline = arcs_to_polylines_single(&arcs);
+        let polyline = arcs_to_polylines_single(&arcs, &Tolerances::default());
         assert_eq!(polyline.len(), 0);
     }
 
@@ -367,7 +1391,7 @@ mod test_arcs_to_polylines {
     fn test_arcs_to_polylines_single_single_arc() {
         let arcs = vec![arcseg(point(0.0, 0.0), point(1.0, 0.0))];
 
-        let polyline = arcs_to_polylines_single(&arcs);
+        let polyline = arcs_to_polylines_single(&arcs, &Tolerances::default());
 
         // Should have 1 vertex
         assert_eq!(polyline.len(), 1);
@@ -377,58 +1401,17 @@ mod test_arcs_to_polylines {
 }
 
 
-#[doc(hidden)]
-pub fn offset_polyline_multiple(
-    poly: &Polyline,
-    step: f64,
-    start: f64,
-    end: f64,
-    config: &mut OffsetCfg,
-) -> Vec<Polyline> {
-    let mut off = start;
-    let mut polylines = Vec::new();
-    while off < end {
-        let offset_polylines = offset_polyline_to_polyline(&poly, off, config);
-        polylines.extend(offset_polylines);
-        off += step;
-    }
-    polylines
-}
-
-fn offset_single(poly_raws: &Vec<Vec<OffsetRaw>>, off: f64, cfg: &mut OffsetCfg) -> Vec<Arc> {
-
-    let offset_raw = offset_arcs_raw::offset_polyline_raw(&poly_raws, off);
-    
-    if let Some(svg) = cfg.svg.as_mut()
-        && cfg.svg_raw
-    {
-        svg_offset_raws(svg, &offset_raw, "blue");
-    }
-
-    let offset_connect = offset_connect_raw(&offset_raw, off);
-    if let Some(svg) = cfg.svg.as_mut()
-        && cfg.svg_connect
-    {
-        svg.arclines(&offset_connect, "violet");
-    }
-
-    let mut offset_split = offset_split_arcs(&offset_raw, &offset_connect);
-    if let Some(svg) = cfg.svg.as_mut()
-        && cfg.svg_split
-    {
-        svg.arcline(&offset_split, "violet");
-        // svg.offset_segments_single_points(&offset_split, "violet"); // Method not available in togo
-    }
-
-    let offset_prune = offset_prune_invalid(&poly_raws, &mut offset_split, off);
-
-    if let Some(svg) = cfg.svg.as_mut()
-        && cfg.svg_prune
-    {
-        svg.arcline(&offset_prune, "violet");
-        // svg.offset_segments_single_points(&offset_prune, "violet"); // Method not available in togo
-    }
-    offset_prune
+// Runs the offset stages up to pruning, open polylines are not connected from the last to the first segment.
+pub(crate) fn offset_single(
+    poly_raws: &[Vec<OffsetRaw>],
+    off: f64,
+    cfg: &mut OffsetCfg,
+    closed: bool,
+    tol: &Tolerances,
+) -> Result<Vec<Arc>, OffsetError> {
+    OffsetPipeline::from_raws(poly_raws.to_vec(), off, cfg, closed, *tol)?
+        .prune()?
+        .into_arcs()
 }
 
 
@@ -2271,8 +3254,6 @@ mod test_offset {
         use togo::prelude::*;
         
         let mut cfg = OffsetCfg::default();
-        cfg.svg_orig = false;
-        cfg.svg_final = false;
 
         let poly_orig = pline_01()[0].clone();
         // Translate to fit in test viewport
@@ -2306,4 +3287,50 @@ mod test_offset {
             offset_internal.len()
         );
     }
+
+    #[test]
+    fn test_offset_negative_polyline() {
+        use crate::prelude::{offset_polyline_to_polyline, pline_01, OffsetCfg};
+        use togo::prelude::*;
+
+        let mut cfg = OffsetCfg::default();
+
+        let poly_orig = pline_01()[0].clone();
+        let poly = polyline_translate(&poly_orig, point(250.0, 100.0));
+        let poly_reversed = polyline_reverse(&poly);
+
+        let length = |plines: &Vec<Polyline>| -> f64 {
+            let mut res = 0.0;
+            for p in plines {
+                for i in 0..p.len() {
+                    let seg = arc_from_bulge(p[i].p, p[(i + 1) % p.len()].p, p[i].b);
+                    res += if seg.is_seg() {
+                        (seg.b - seg.a).norm()
+                    } else {
+                        seg.r * 4.0 * p[i].b.abs().atan()
+                    };
+                }
+            }
+            res
+        };
+
+        // Negative offset is the same as positive offset of the reversed polyline.
+        // Coincident offset segments may be grouped into loops differently.
+        for i in 1..100 {
+            let off = (i as f64) / 2.0;
+            let offset = offset_polyline_to_polyline(&poly, -off, &mut cfg);
+            let offset_reversed = offset_polyline_to_polyline(&poly_reversed, off, &mut cfg);
+            assert_eq!(offset.is_empty(), offset_reversed.is_empty(), "offset {}", off);
+            assert!(
+                (length(&offset) - length(&offset_reversed)).abs() < 1e-6,
+                "offset {}",
+                off
+            );
+        }
+
+        // Negative offset of the reversed polyline is external
+        let offset_polylines = offset_polyline_to_polyline(&poly_reversed, -(16.0 - 1e-9), &mut cfg);
+        assert_eq!(offset_polylines.len(), 1);
+        assert_eq!(offset_polylines[0].len(), 23);
+    }
 }
@@ -2,32 +2,50 @@
 
 use togo::prelude::*;
 
-use crate::offsetraw::OffsetRaw;
+use crate::{offsetraw::OffsetRaw, origin::{number_contours, offset_id}, tolerances::Tolerances};
 
 const ZERO: f64 = 0f64;
 
-pub fn offset_polyline_raw(plines: &Vec<Vec<OffsetRaw>>, off: f64) -> Vec<Vec<OffsetRaw>> {
+pub fn offset_polyline_raw(plines: &Vec<Vec<OffsetRaw>>, off: f64, tol: &Tolerances) -> Vec<Vec<OffsetRaw>> {
     let mut result = Vec::new();
     for pline in plines.iter() {
-        result.push(offset_polyline_raw_single(pline, off));
+        result.push(offset_polyline_raw_single(pline, off, tol));
     }
     result
 }
 
-fn offset_polyline_raw_single(pline: &Vec<OffsetRaw>, off: f64) -> Vec<OffsetRaw> {
+fn offset_polyline_raw_single(pline: &Vec<OffsetRaw>, off: f64, tol: &Tolerances) -> Vec<OffsetRaw> {
     let mut result = Vec::with_capacity(pline.len());
     for p in pline.iter() {
-        let offset = offset_segment(&p.arc, p.orig, p.g, off);
+        let offset = offset_segment(&p.arc, p.orig, p.g, off, tol.collapsed);
         result.push(offset);
     }
     result
 }
 
-pub(crate) fn offset_segment(seg: &Arc, orig: Point, g: f64, off: f64) -> OffsetRaw {
+// Offsets every segment with its own distance, offs has the same layout as plines
+pub fn offset_polyline_raw_variable(
+    plines: &[Vec<OffsetRaw>],
+    offs: &[Vec<f64>],
+    tol: &Tolerances,
+) -> Vec<Vec<OffsetRaw>> {
+    let mut result = Vec::with_capacity(plines.len());
+    for (pline, offs) in plines.iter().zip(offs.iter()) {
+        let offset = pline
+            .iter()
+            .zip(offs.iter())
+            .map(|(p, &off)| offset_segment(&p.arc, p.orig, p.g, off, tol.collapsed))
+            .collect();
+        result.push(offset);
+    }
+    result
+}
+
+pub(crate) fn offset_segment(seg: &Arc, orig: Point, g: f64, off: f64, eps: f64) -> OffsetRaw {
     if seg.is_seg() {
         line_offset(seg, orig, off)
     } else {
-        arc_offset(seg, orig, g, off)
+        arc_offset(seg, orig, g, off, eps)
     }
 }
 
@@ -47,10 +65,9 @@ fn line_offset(seg: &Arc, orig: Point, offset: f64) -> OffsetRaw {
     };
 }
 
-const EPS_COLLAPSED: f64 = 1E-10; // TODO: what should be the exact value.
-// Offsets arc on right side
+// Offsets arc on right side, arcs with radius (or length) below eps collapse to lines
 // #00028
-fn arc_offset(seg: &Arc, orig: Point, bulge: f64, offset: f64) -> OffsetRaw {
+fn arc_offset(seg: &Arc, orig: Point, bulge: f64, offset: f64, eps: f64) -> OffsetRaw {
     // Arc is always CCW
     //let seg = arc_from_bulge(seg.a, seg.b, bulge);
     let (v0_to_center, _) = (seg.a - seg.c).normalize(false);
@@ -61,9 +78,9 @@ fn arc_offset(seg: &Arc, orig: Point, bulge: f64, offset: f64) -> OffsetRaw {
     let offset_radius = seg.r + off;
     let a = seg.a + v0_to_center * off;
     let b = seg.b + v1_to_center * off;
-    if offset_radius < EPS_COLLAPSED || offset_radius.is_nan() || a.close_enough(b, EPS_COLLAPSED) {
-        // Collapsed arc is now line
-        let mut arc = arcseg(b, a);
+    if offset_radius < eps || offset_radius.is_nan() || a.close_enough(b, eps) {
+        // Collapsed arc is now line, from a to b for positive bulge, b to a for negative bulge
+        let mut arc = if bulge < ZERO { arcseg(b, a) } else { arcseg(a, b) };
         arc.id(seg.id);
         return OffsetRaw {
             arc: arc,
@@ -81,15 +98,19 @@ fn arc_offset(seg: &Arc, orig: Point, bulge: f64, offset: f64) -> OffsetRaw {
     }
 }
 
-pub fn poly_to_raws(plines: &Vec<Polyline>) -> Vec<Vec<OffsetRaw>> {
+// Arc ids of the segments tag their indices in the input, numbered on across the contours
+pub fn poly_to_raws(plines: &Vec<Polyline>, tol: &Tolerances) -> Vec<Vec<OffsetRaw>> {
     let mut varcs: Vec<Vec<OffsetRaw>> = Vec::new();
+    let mut counts = Vec::with_capacity(plines.len());
     for pline in plines {
-        varcs.push(poly_to_raws_single(pline));
+        varcs.push(poly_to_raws_single(pline, tol));
+        counts.push(pline.len());
     }
+    number_contours(&mut varcs, &counts);
     varcs
 }
 
-pub fn poly_to_raws_single(pline: &Polyline) -> Vec<OffsetRaw> {
+pub fn poly_to_raws_single(pline: &Polyline, tol: &Tolerances) -> Vec<OffsetRaw> {
     let mut offs = Vec::with_capacity(pline.len());
     let n = pline.len();
 
@@ -97,8 +118,9 @@ pub fn poly_to_raws_single(pline: &Polyline) -> Vec<OffsetRaw> {
     for i in 0..n {
         let bulge = pline[i].b;
         let next_i = (i + 1) % n; // Cyclic wrap-around
-        let seg = arc_from_bulge(pline[i].p, pline[next_i].p, bulge);
-        let check = seg.is_valid(EPS_COLLAPSED);
+        let mut seg = arc_from_bulge(pline[i].p, pline[next_i].p, bulge);
+        seg.id(offset_id(i));
+        let check = seg.is_valid(tol.collapsed);
         if !check {
             continue;
         }
@@ -114,22 +136,39 @@ pub fn poly_to_raws_single(pline: &Polyline) -> Vec<OffsetRaw> {
     offs
 }
 
-pub fn arcs_to_raws(arcss: &Vec<Arcline>) -> Vec<Vec<OffsetRaw>> {
+// Offset distances of the segments kept by poly_to_raws_single, offs has one distance per vertex
+pub fn segment_offsets(pline: &Polyline, offs: &[f64], tol: &Tolerances) -> Vec<f64> {
+    let n = pline.len();
+    let mut res = Vec::with_capacity(n);
+    for i in 0..n {
+        let seg = arc_from_bulge(pline[i].p, pline[(i + 1) % n].p, pline[i].b);
+        if seg.is_valid(tol.collapsed) {
+            res.push(offs[i]);
+        }
+    }
+    res
+}
+
+pub fn arcs_to_raws(arcss: &Vec<Arcline>, tol: &Tolerances) -> Vec<Vec<OffsetRaw>> {
     let mut varcs: Vec<Vec<OffsetRaw>> = Vec::new();
+    let mut counts = Vec::with_capacity(arcss.len());
     for arcs in arcss {
-        varcs.push(arcs_to_raws_single(arcs));
+        varcs.push(arcs_to_raws_single(arcs, tol));
+        counts.push(arcs.len());
     }
+    number_contours(&mut varcs, &counts);
     varcs
 }
 
-pub fn arcs_to_raws_single(arcs: &Arcline) -> Vec<OffsetRaw> {
+pub fn arcs_to_raws_single(arcs: &Arcline, tol: &Tolerances) -> Vec<OffsetRaw> {
     let mut offs = Vec::with_capacity(arcs.len());
     let n = arcs.len();
 
     // Cyclic loop: for each arc i, process it (arcs are already connected in sequence)
     for i in 0..n {
-        let seg = arcs[i];
-        let check = seg.is_valid(EPS_COLLAPSED);
+        let mut seg = arcs[i];
+        seg.id(offset_id(i));
+        let check = seg.is_valid(tol.collapsed);
         if !check {
             continue;
         }
@@ -176,6 +215,100 @@ pub fn arcs_to_raws_single(arcs: &Arcline) -> Vec<OffsetRaw> {
     offs
 }
 
+pub fn poly_to_raws_open(plines: &Vec<Polyline>, tol: &Tolerances) -> Vec<Vec<OffsetRaw>> {
+    let mut varcs: Vec<Vec<OffsetRaw>> = Vec::new();
+    let mut counts = Vec::with_capacity(plines.len());
+    for pline in plines {
+        varcs.push(poly_to_raws_open_single(pline, tol));
+        counts.push(pline.len().saturating_sub(1));
+    }
+    number_contours(&mut varcs, &counts);
+    varcs
+}
+
+pub fn poly_to_raws_open_single(pline: &Polyline, tol: &Tolerances) -> Vec<OffsetRaw> {
+    let mut offs = Vec::with_capacity(pline.len());
+    let n = pline.len();
+
+    // Open loop: no segment from the last vertex back to the first, the last bulge is ignored
+    for i in 0..n.saturating_sub(1) {
+        let bulge = pline[i].b;
+        let mut seg = arc_from_bulge(pline[i].p, pline[i + 1].p, bulge);
+        seg.id(offset_id(i));
+        let check = seg.is_valid(tol.collapsed);
+        if !check {
+            continue;
+        }
+        let orig = if bulge < ZERO { seg.a } else { seg.b };
+        let off = OffsetRaw {
+            arc: seg,
+            orig,
+            g: bulge,
+        };
+        offs.push(off);
+    }
+
+    offs
+}
+
+pub fn arcs_to_raws_open(arcss: &Vec<Arcline>, tol: &Tolerances) -> Vec<Vec<OffsetRaw>> {
+    let mut varcs: Vec<Vec<OffsetRaw>> = Vec::new();
+    let mut counts = Vec::with_capacity(arcss.len());
+    for arcs in arcss {
+        varcs.push(arcs_to_raws_open_single(arcs, tol));
+        counts.push(arcs.len());
+    }
+    number_contours(&mut varcs, &counts);
+    varcs
+}
+
+pub fn arcs_to_raws_open_single(arcs: &Arcline, tol: &Tolerances) -> Vec<OffsetRaw> {
+    let mut offs = Vec::with_capacity(arcs.len());
+    let n = arcs.len();
+
+    for i in 0..n {
+        let mut seg = arcs[i];
+        seg.id(offset_id(i));
+        let check = seg.is_valid(tol.collapsed);
+        if !check {
+            continue;
+        }
+
+        if seg.is_seg() {
+            let off = OffsetRaw {
+                arc: seg,
+                orig: seg.b,
+                g: ZERO,  // Line segments have no bulge
+            };
+            offs.push(off);
+            continue;
+        }
+
+        // Determine bulge sign from connectivity with next arc,
+        // the last arc has no next arc and uses the previous one
+        let bulge = if i + 1 < n {
+            let next_seg = arcs[i + 1];
+            if seg.b == next_seg.a || seg.b == next_seg.b { 1.0 } else { -1.0 }
+        } else if i > 0 {
+            let prev_seg = arcs[i - 1];
+            if seg.a == prev_seg.a || seg.a == prev_seg.b { 1.0 } else { -1.0 }
+        } else {
+            // Single arc, keep its CCW orientation
+            1.0
+        };
+
+        let orig = if bulge < ZERO { seg.a } else { seg.b };
+        let off = OffsetRaw {
+            arc: seg,
+            orig,
+            g: bulge,
+        };
+        offs.push(off);
+    }
+
+    offs
+}
+
 #[cfg(test)]
 mod test_offset_polyline_raw {
     use togo::prelude::*;
@@ -313,7 +446,7 @@ mod test_offset_polyline_raw {
         let circle0 = circle(point(arc0.c.x, arc0.c.y), 0.1);
         svg.circle(&circle0, "blue");
 
-        let offsetraw = offset_segment(&arc0, point(-52.0, 250.0), -0.6068148963145962, 16.0);
+        let offsetraw = offset_segment(&arc0, point(-52.0, 250.0), -0.6068148963145962, 16.0, Tolerances::default().collapsed);
         svg.arcsegment(&offsetraw.arc, "green");
         let circle1 = circle(point(offsetraw.arc.c.x, offsetraw.arc.c.y), 0.1);
         svg.circle(&circle1, "blue");
@@ -331,7 +464,7 @@ mod test_offset_polyline_raw {
         let bulge = 1.0; // positive bulge
 
         // Offset right by 2.0 units (positive offset)
-        let offset_result = arc_offset(&arc, arc.a, bulge, 2.0);
+        let offset_result = arc_offset(&arc, arc.a, bulge, 2.0, Tolerances::default().collapsed);
 
         // For positive bulge, offset should expand the radius: 10 + 2 = 12
         let expected_radius = 12.0;
@@ -354,7 +487,7 @@ mod test_offset_polyline_raw {
         // Offset right by 2.0 units (positive offset)
         // For negative bulge, the offset is negated, so the arc shrinks
         // Radius becomes: 10 + (-2.0) = 8.0
-        let offset_result = arc_offset(&arc, arc.a, bulge, 2.0);
+        let offset_result = arc_offset(&arc, arc.a, bulge, 2.0, Tolerances::default().collapsed);
 
         let expected_radius = 8.0;
         assert!(
@@ -468,4 +601,47 @@ mod test_offset_polyline_raw {
         eprintln!("Arc from neg: a={:?}, b={:?}", arc_neg.a, arc_neg.b);
         eprintln!("Arc from pos: a={:?}, b={:?}", arc_pos.a, arc_pos.b);
     }
+
+    #[test]
+    fn test_poly_to_raws_open_single() {
+        let pline = vec![
+            pvertex(point(0.0, 0.0), 0.0),
+            pvertex(point(10.0, 0.0), 1.0),
+            pvertex(point(10.0, 10.0), 0.0),
+        ];
+        // Closed version also has the segment from the last to the first vertex
+        assert_eq!(poly_to_raws_single(&pline, &Tolerances::default()).len(), 3);
+        let raws = poly_to_raws_open_single(&pline, &Tolerances::default());
+        assert_eq!(raws.len(), 2);
+        assert_eq!(raws[1].orig, point(10.0, 10.0));
+        assert!(poly_to_raws_open_single(&vec![pvertex(point(0.0, 0.0), 0.0)], &Tolerances::default()).is_empty());
+    }
+
+    #[test]
+    fn test_arcs_to_raws_open_single() {
+        // Last arc is reversed, detected from the previous arc
+        let arcs = vec![
+            arcseg(point(0.0, 0.0), point(10.0, 0.0)),
+            arc_from_bulge(point(10.0, 0.0), point(10.0, 10.0), -1.0),
+        ];
+        let raws = arcs_to_raws_open_single(&arcs, &Tolerances::default());
+        assert_eq!(raws.len(), 2);
+        assert_eq!(raws[1].g, -1.0);
+        assert_eq!(raws[1].orig, point(10.0, 10.0));
+    }
+
+    #[test]
+    fn test_arc_offset_collapsed_negative_offset() {
+        // Half circle, radius 1, offset to the left (inside) collapses
+        let seg = arc_from_bulge(point(0.0, 0.0), point(2.0, 0.0), 1.0);
+        let res = offset_segment(&seg, seg.b, 1.0, -2.0, Tolerances::default().collapsed);
+        assert!(res.arc.is_seg());
+        assert_eq!(res.g, 0.0);
+        assert_eq!(res.arc.a, point(2.0, 0.0));
+        assert_eq!(res.arc.b, point(0.0, 0.0));
+        // Same arc traversed in the other direction, offset to the right
+        let res_reversed = offset_segment(&seg, seg.a, -1.0, 2.0, Tolerances::default().collapsed);
+        assert_eq!(res_reversed.arc.a, res.arc.b);
+        assert_eq!(res_reversed.arc.b, res.arc.a);
+    }
 }
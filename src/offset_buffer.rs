@@ -0,0 +1,478 @@
+//! Two-sided buffer of open Polylines and Arclines.
+//!
+//! The buffer is the closed outline at distance `off` on both sides of an open path,
+//! with the two ends closed by end caps. It is used for example for single-line text
+//! engraving and PCB trace outlines.
+
+use togo::prelude::*;
+
+use crate::{
+    area::{Orientation, arcline_oriented, arcline_signed_area_with_tolerance},
+    error::OffsetError,
+    offset::{OffsetCfg, arcs_to_polylines, check_arcline, check_polyline},
+    observer::polyline_arcs,
+    offset_arcs_raw::{arcs_to_raws_open_single, poly_to_raws_open_single},
+    offset_prune_invalid::{FlatEnd, remove_duplicates},
+    offsetraw::OffsetRaw,
+    pipeline::OffsetPipeline,
+    tolerances::{Tolerances, points_size},
+};
+
+/// Shape of the buffer at the two ends of the path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EndCap {
+    /// Half circle around the end point
+    #[default]
+    Round,
+    /// Straight line through the end point
+    Butt,
+    /// Half square around the end point, the buffer is extended by the offset distance
+    Square,
+}
+
+/// Computes the buffer of an open Polyline and returns its outline as closed Polylines.
+///
+/// Both sides of the path are offset by `off` and the ends are closed with `cap`.
+/// Self-intersecting paths may produce more than one outline (for example, holes).
+///
+/// # Arguments
+///
+/// * `poly` - The input polyline, an open sequence of connected PVertex-es.
+///   The bulge of the last vertex is ignored.
+/// * `off` - The buffer distance, must be positive.
+/// * `cap` - The end cap style.
+/// * `cfg` - Configuration options controlling the offsetting behavior and observing the stages.
+///
+/// # Returns
+///
+/// Closed polylines of the outline, the outer boundary counter-clockwise and the holes
+/// clockwise. Invalid or degenerate cases produce an empty vector
+/// (use `try_buffer_polyline` to find out why).
+///
+/// # Examples
+///
+/// ```rust
+/// use togo::prelude::*;
+/// use offroad::prelude::*;
+///
+/// let mut cfg = OffsetCfg::default();
+/// let poly = vec![
+///     pvertex(point(0.0, 0.0), 0.0),
+///     pvertex(point(10.0, 0.0), 0.0),
+/// ];
+///
+/// // Rectangle around the segment
+/// let outline = buffer_polyline(&poly, 1.0, EndCap::Butt, &mut cfg);
+/// assert_eq!(outline.len(), 1);
+/// assert_eq!(outline[0].len(), 4);
+/// ```
+pub fn buffer_polyline(
+    poly: &Polyline,
+    off: f64,
+    cap: EndCap,
+    cfg: &mut OffsetCfg,
+) -> Vec<Polyline> {
+    try_buffer_polyline(poly, off, cap, cfg).unwrap_or_default()
+}
+
+/// Computes the buffer of an open Polyline, reporting failures as `OffsetError`.
+///
+/// Same as `buffer_polyline`, but returns an `OffsetError` instead of an empty vector,
+/// see `try_offset_polyline_to_polyline` for the list of errors.
+///
+/// # Errors
+///
+/// See `try_offset_polyline_to_polyline`.
+pub fn try_buffer_polyline(
+    poly: &Polyline,
+    off: f64,
+    cap: EndCap,
+    cfg: &mut OffsetCfg,
+) -> Result<Vec<Polyline>, OffsetError> {
+    check_polyline(poly, off)?;
+    check_buffer(off)?;
+    if let Some(observer) = cfg.observer.as_mut() {
+        observer.input(&[polyline_arcs(poly, false)]);
+    }
+
+    let tol = cfg.options.tolerances_for(points_size(poly.iter().map(|v| v.p)), off);
+    let raws = poly_to_raws_open_single(poly, &tol);
+    let loops = buffer_raws(raws, off, cap, cfg, &tol)?;
+    let final_poly = arcs_to_polylines(&loops, &tol);
+
+    Ok(final_poly)
+}
+
+/// Computes the buffer of an open Arcline and returns its outline as closed Arclines.
+///
+/// This function is similar to `buffer_polyline` but operates on arclines.
+///
+/// # Examples
+///
+/// ```rust
+/// use togo::prelude::*;
+/// use offroad::prelude::*;
+///
+/// let mut cfg = OffsetCfg::default();
+/// let arcline = vec![
+///     arcseg(point(0.0, 0.0), point(10.0, 0.0)),
+///     arcseg(point(10.0, 0.0), point(10.0, 10.0)),
+/// ];
+///
+/// let outline = buffer_arcline(&arcline, 1.0, EndCap::Round, &mut cfg);
+/// assert_eq!(outline.len(), 1);
+/// ```
+pub fn buffer_arcline(
+    arcs: &Arcline,
+    off: f64,
+    cap: EndCap,
+    cfg: &mut OffsetCfg,
+) -> Vec<Arcline> {
+    try_buffer_arcline(arcs, off, cap, cfg).unwrap_or_default()
+}
+
+/// Computes the buffer of an open Arcline, reporting failures as `OffsetError`.
+///
+/// Same as `buffer_arcline`, but returns an `OffsetError` instead of an empty vector.
+///
+/// # Errors
+///
+/// See `try_offset_polyline_to_polyline`.
+pub fn try_buffer_arcline(
+    arcs: &Arcline,
+    off: f64,
+    cap: EndCap,
+    cfg: &mut OffsetCfg,
+) -> Result<Vec<Arcline>, OffsetError> {
+    check_arcline(arcs, off)?;
+    check_buffer(off)?;
+    if let Some(observer) = cfg.observer.as_mut() {
+        observer.input(std::slice::from_ref(arcs));
+    }
+
+    let tol = cfg.options.tolerances_for(points_size(arcs.iter().flat_map(|a| [a.a, a.b])), off);
+    let raws = arcs_to_raws_open_single(arcs, &tol);
+    let final_arcs = buffer_raws(raws, off, cap, cfg, &tol)?;
+
+    Ok(final_arcs)
+}
+
+fn check_buffer(off: f64) -> Result<(), OffsetError> {
+    if off <= 0.0 {
+        return Err(OffsetError::InvalidInput(format!(
+            "buffer distance {} is not positive",
+            off
+        )));
+    }
+    Ok(())
+}
+
+// Offsets the path on both sides, adds the end caps and reconnects the outline.
+fn buffer_raws(
+    raws: Vec<OffsetRaw>,
+    off: f64,
+    cap: EndCap,
+    cfg: &mut OffsetCfg,
+    tol: &Tolerances,
+) -> Result<Vec<Arcline>, OffsetError> {
+    let ends = flat_ends(&raws, off, cap);
+    // The left side is the right side of the reversed path
+    let reversed = reverse_raws(&raws);
+    let mut pipeline =
+        OffsetPipeline::from_raws_flat_ends(vec![raws, reversed], off, cfg, ends, *tol)?.connect();
+    let caps = end_caps(&pipeline.raws()[0], &pipeline.raws()[1], off, cap, tol.collapsed);
+    pipeline.connect_arcs_mut().push(caps);
+
+    // Both sides share the ids of the input segments, so the path itself is the only source,
+    // and the sides of a path returning on itself coincide
+    let mut pipeline = pipeline.prune()?;
+    let arcs = remove_duplicates(std::mem::take(pipeline.arcs_mut()), tol.vertex);
+    *pipeline.arcs_mut() = arcs;
+    let loops = pipeline.reconnect()?;
+    Ok(orient_outline(loops, tol.vertex))
+}
+
+// Orients the outer boundary counter-clockwise and the holes clockwise.
+// The buffer of a path is connected, the loop with the largest area is the outer boundary.
+fn orient_outline(loops: Vec<Arcline>, tolerance: f64) -> Vec<Arcline> {
+    let areas: Vec<f64> = loops
+        .iter()
+        .map(|arcs| arcline_signed_area_with_tolerance(arcs, tolerance).abs())
+        .collect();
+    let outer = (0..loops.len()).max_by(|&i, &j| areas[i].total_cmp(&areas[j]));
+    loops
+        .iter()
+        .enumerate()
+        .map(|(i, arcs)| {
+            let orientation = if Some(i) == outer { Orientation::Ccw } else { Orientation::Cw };
+            arcline_oriented(arcs, orientation, tolerance).0
+        })
+        .collect()
+}
+
+// Flat ends of the path with butt and square caps, square caps cover the offset distance past the ends.
+fn flat_ends(raws: &[OffsetRaw], off: f64, cap: EndCap) -> Vec<FlatEnd> {
+    let ext = match cap {
+        EndCap::Round => return Vec::new(),
+        EndCap::Butt => 0.0,
+        EndCap::Square => off,
+    };
+    let (Some(first), Some(last)) = (raws.first(), raws.last()) else {
+        return Vec::new();
+    };
+    let start = raw_start(first);
+    let end = raw_end(last);
+    vec![
+        FlatEnd { id: first.arc.id, p: start, dir: -raw_tangent(first, start), ext },
+        FlatEnd { id: last.arc.id, p: end, dir: raw_tangent(last, end), ext },
+    ]
+}
+
+// Unit tangent of the segment at its point p, in the path direction.
+fn raw_tangent(raw: &OffsetRaw, p: Point) -> Point {
+    let tangent = if raw.arc.is_seg() {
+        raw.arc.b - raw.arc.a
+    } else {
+        let v = p - raw.arc.c;
+        point(-v.y, v.x)
+    };
+    let tangent = tangent.normalize(false).0;
+    if raw.g < 0.0 { -tangent } else { tangent }
+}
+
+// Reverses the direction of the path, the segments keep their ids.
+fn reverse_raws(raws: &[OffsetRaw]) -> Vec<OffsetRaw> {
+    raws.iter()
+        .rev()
+        .map(|raw| {
+            let mut arc = if raw.arc.is_seg() {
+                arcseg(raw.arc.b, raw.arc.a)
+            } else {
+                raw.arc
+            };
+            arc.id(raw.arc.id);
+            let g = -raw.g;
+            let orig = if g < 0.0 { arc.a } else { arc.b };
+            OffsetRaw::new(arc, orig, g)
+        })
+        .collect()
+}
+
+// End and start point of the offset segment in the path direction.
+fn raw_end(raw: &OffsetRaw) -> Point {
+    if raw.g < 0.0 { raw.arc.a } else { raw.arc.b }
+}
+
+fn raw_start(raw: &OffsetRaw) -> Point {
+    if raw.g < 0.0 { raw.arc.b } else { raw.arc.a }
+}
+
+// Caps at the end of the path (right side to left side) and at the start (left to right side).
+fn end_caps(right: &[OffsetRaw], left: &[OffsetRaw], off: f64, cap: EndCap, eps: f64) -> Vec<Arc> {
+    let mut res = Vec::new();
+    if let (Some(first), Some(last)) = (left.first(), right.last()) {
+        end_cap(&mut res, last, first, off, cap, eps);
+    }
+    if let (Some(first), Some(last)) = (right.first(), left.last()) {
+        end_cap(&mut res, last, first, off, cap, eps);
+    }
+    res
+}
+
+// Closes the gap between the end of incoming and the start of outgoing around the path end point.
+// Caps get the id of the terminal segment, so they are not pruned against it.
+// Cap parts shorter than eps are dropped.
+fn end_cap(
+    res: &mut Vec<Arc>,
+    incoming: &OffsetRaw,
+    outgoing: &OffsetRaw,
+    off: f64,
+    cap: EndCap,
+    eps: f64,
+) {
+    let center = incoming.orig;
+    let a = raw_end(incoming);
+    let b = raw_start(outgoing);
+    let id = incoming.arc.id;
+    let mut parts = Vec::with_capacity(3);
+    match cap {
+        EndCap::Round => parts.push(arc(a, b, center, off)),
+        EndCap::Butt => parts.push(arcseg(a, b)),
+        EndCap::Square => {
+            // Direction away from the path, perpendicular to the side offsets
+            let v = a - center;
+            let ext = point(-v.y, v.x);
+            parts.push(arcseg(a, a + ext));
+            parts.push(arcseg(a + ext, b + ext));
+            parts.push(arcseg(b + ext, b));
+        }
+    }
+    for mut part in parts {
+        if part.is_valid(eps) {
+            part.id(id);
+            res.push(part);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_offset_buffer {
+    use super::*;
+    use crate::area::polyline_signed_area;
+
+    fn segment_path() -> Polyline {
+        vec![pvertex(point(0.0, 0.0), 0.0), pvertex(point(10.0, 0.0), 0.0)]
+    }
+
+    fn bounds(pline: &Polyline) -> (f64, f64, f64, f64) {
+        let mut res = (f64::MAX, f64::MIN, f64::MAX, f64::MIN);
+        for v in pline {
+            res = (res.0.min(v.p.x), res.1.max(v.p.x), res.2.min(v.p.y), res.3.max(v.p.y));
+        }
+        res
+    }
+
+    #[test]
+    fn test_buffer_round() {
+        let mut cfg = OffsetCfg::default();
+        let res = buffer_polyline(&segment_path(), 1.0, EndCap::Round, &mut cfg);
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].len(), 4);
+        assert_eq!(res[0].iter().filter(|v| v.b != 0.0).count(), 2);
+        let (min_x, max_x, min_y, max_y) = bounds(&res[0]);
+        assert!((min_x - 0.0).abs() < 1e-10 && (max_x - 10.0).abs() < 1e-10);
+        assert!((min_y + 1.0).abs() < 1e-10 && (max_y - 1.0).abs() < 1e-10);
+    }
+
+    #[test]
+    fn test_buffer_butt() {
+        let mut cfg = OffsetCfg::default();
+        let res = buffer_polyline(&segment_path(), 1.0, EndCap::Butt, &mut cfg);
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].len(), 4);
+        assert!(res[0].iter().all(|v| v.b == 0.0));
+        let (min_x, max_x, _, _) = bounds(&res[0]);
+        assert!((min_x - 0.0).abs() < 1e-10 && (max_x - 10.0).abs() < 1e-10);
+    }
+
+    #[test]
+    fn test_buffer_square() {
+        let mut cfg = OffsetCfg::default();
+        let res = buffer_polyline(&segment_path(), 1.0, EndCap::Square, &mut cfg);
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].len(), 8);
+        let (min_x, max_x, min_y, max_y) = bounds(&res[0]);
+        assert!((min_x + 1.0).abs() < 1e-10 && (max_x - 11.0).abs() < 1e-10);
+        assert!((min_y + 1.0).abs() < 1e-10 && (max_y - 1.0).abs() < 1e-10);
+    }
+
+    #[test]
+    fn test_buffer_corner() {
+        let poly = vec![
+            pvertex(point(0.0, 0.0), 0.0),
+            pvertex(point(10.0, 0.0), 0.0),
+            pvertex(point(10.0, 10.0), 0.0),
+        ];
+        let mut cfg = OffsetCfg::default();
+        let res = buffer_polyline(&poly, 1.0, EndCap::Butt, &mut cfg);
+        assert_eq!(res.len(), 1);
+        // two sides of two segments, outer join arc and two caps, inner corner is trimmed
+        assert_eq!(res[0].len(), 7);
+    }
+
+    #[test]
+    fn test_buffer_ring_with_hole() {
+        // Almost closed square path, the buffer closes the gap and has a hole
+        let poly = vec![
+            pvertex(point(0.0, 0.0), 0.0),
+            pvertex(point(10.0, 0.0), 0.0),
+            pvertex(point(10.0, 10.0), 0.0),
+            pvertex(point(0.0, 10.0), 0.0),
+            pvertex(point(0.0, 1.0), 0.0),
+        ];
+        let mut cfg = OffsetCfg::default();
+        let res = buffer_polyline(&poly, 1.0, EndCap::Round, &mut cfg);
+        assert_eq!(res.len(), 2);
+        // Outer boundary counter-clockwise, the hole clockwise
+        let mut areas: Vec<f64> = res.iter().map(polyline_signed_area).collect();
+        areas.sort_by(f64::total_cmp);
+        assert!(areas[0] < 0.0 && areas[1] > 0.0);
+    }
+
+    #[test]
+    fn test_buffer_hairpin() {
+        // Path returning on itself, the sides of the two segments coincide
+        let poly = vec![
+            pvertex(point(0.0, 0.0), 0.0),
+            pvertex(point(10.0, 0.0), 0.0),
+            pvertex(point(0.0, 0.0), 0.0),
+        ];
+        let mut cfg = OffsetCfg::default();
+        let res = buffer_polyline(&poly, 1.0, EndCap::Round, &mut cfg);
+        assert_eq!(res.len(), 1);
+        // Stadium: two lines and two half circles
+        assert_eq!(res[0].len(), 4);
+    }
+
+    #[test]
+    fn test_buffer_arcline() {
+        // Half circle path
+        let arcs = vec![arc_from_bulge(point(0.0, 0.0), point(10.0, 0.0), 1.0)];
+        let mut cfg = OffsetCfg::default();
+        let res = try_buffer_arcline(&arcs, 1.0, EndCap::Round, &mut cfg).unwrap();
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].len(), 4);
+    }
+
+    fn path(points: &[(f64, f64)]) -> Polyline {
+        points.iter().map(|&(x, y)| pvertex(point(x, y), 0.0)).collect()
+    }
+
+    #[test]
+    fn test_buffer_close_sides() {
+        // The sides of the buffer meet near the ends of the paths
+        let cases = [
+            (path(&[(0.0, 0.0), (10.0, 0.0), (0.0, 1.0)]), vec![0.5, 1.0, 2.0]),
+            (path(&[(0.0, 0.0), (10.0, 0.0), (0.0, 1.0), (10.0, 2.0)]), vec![0.5, 1.0, 2.0]),
+            (path(&[(0.0, 0.0), (10.0, 0.0), (10.0, 1.5), (2.0, 1.5)]), vec![1.0, 2.0]),
+        ];
+        for (poly, offs) in cases.iter() {
+            for &off in offs.iter() {
+                let mut areas = Vec::new();
+                for cap in [EndCap::Butt, EndCap::Round, EndCap::Square] {
+                    let mut cfg = OffsetCfg::default();
+                    let res = try_buffer_polyline(poly, off, cap, &mut cfg).unwrap();
+                    assert_eq!(res.len(), 1, "{:?} at {}", cap, off);
+                    let area = polyline_signed_area(&res[0]);
+                    assert!(area > 0.0, "{:?} at {} is clockwise", cap, off);
+                    areas.push(area);
+                }
+                // Butt caps are inside of round caps, round caps inside of square caps
+                assert!(areas[0] < areas[1] && areas[1] < areas[2], "{:?} at {}", areas, off);
+            }
+        }
+    }
+
+    #[test]
+    fn test_buffer_hook_bounds() {
+        // Butt caps at (0, 0) and (2, 1.5), the cap at (2, 1.5) is partly inside of the buffer
+        // of the first segment
+        let poly = path(&[(0.0, 0.0), (10.0, 0.0), (10.0, 1.5), (2.0, 1.5)]);
+        let mut cfg = OffsetCfg::default();
+        let res = try_buffer_polyline(&poly, 2.0, EndCap::Butt, &mut cfg).unwrap();
+        assert_eq!(res.len(), 1);
+        let (min_x, max_x, min_y, max_y) = bounds(&res[0]);
+        assert!((min_x - 0.0).abs() < 1e-10 && (max_x - 12.0).abs() < 1e-10);
+        assert!((min_y + 2.0).abs() < 1e-10 && (max_y - 3.5).abs() < 1e-10);
+    }
+
+    #[test]
+    fn test_buffer_invalid_distance() {
+        let mut cfg = OffsetCfg::default();
+        let res = try_buffer_polyline(&segment_path(), 0.0, EndCap::Round, &mut cfg);
+        assert!(matches!(res, Err(OffsetError::InvalidInput(_))));
+        let res = try_buffer_polyline(&segment_path(), -1.0, EndCap::Round, &mut cfg);
+        assert!(matches!(res, Err(OffsetError::InvalidInput(_))));
+    }
+}
+
@@ -4,82 +4,593 @@ use robust::{Coord, orient2d};
 
 use togo::prelude::*;
 
-use crate::offsetraw::OffsetRaw;
+use crate::{
+    offset::JoinStyle,
+    offsetraw::OffsetRaw,
+    origin::{is_join_of, join_id},
+    tolerances::Tolerances,
+};
 
 const ZERO: f64 = 0f64;
-const EPS_CONNECT_RAW: f64 = 1e-8;
+
+// Arcs extended at both ends stay this angle short of a full circle.
+// Extensions that meet the next offset only later curl around the inside of the arc,
+// the corner gets a round join instead.
+const EXTEND_TURN_MARGIN: f64 = std::f64::consts::FRAC_PI_8;
 
 #[doc(hidden)]
 // Connect the ends of the raw offset segments with arcs.
-pub fn offset_connect_raw(raws: &Vec<Vec<OffsetRaw>>, off: f64) -> Vec<Vec<Arc>> {
+pub fn offset_connect_raw(
+    raws: &Vec<Vec<OffsetRaw>>,
+    off: f64,
+    join: JoinStyle,
+    tol: &Tolerances,
+) -> Vec<Vec<Arc>> {
     let mut res = Vec::with_capacity(raws.len());
     for raw in raws.iter() {
-        res.push(offset_connect_raw_single(raw, off));
+        res.push(offset_connect_raw_single(raw, off, join, tol));
     }
     res
 }
 
-#[doc(hidden)]
-pub const ID_PADDING: usize = 100000;
-pub fn offset_connect_raw_single(raws: &Vec<OffsetRaw>, off: f64) -> Vec<Arc> {
+pub fn offset_connect_raw_single(
+    raws: &Vec<OffsetRaw>,
+    off: f64,
+    join: JoinStyle,
+    tol: &Tolerances,
+) -> Vec<Arc> {
     let mut res = Vec::with_capacity(raws.len() + 1);
-    if off < ZERO {
-        // Negative offset, no connection
+    if raws.is_empty() {
         return res;
     }
-    if raws.is_empty() {
+    for pair in raws.windows(2) {
+        connect_raws(&mut res, &pair[0], &pair[1], off, join, tol.connect);
+    }
+    // close end of line
+    let (Some(last), Some(raw_next)) = (raws.last(), raws.first()) else {
         return res;
+    };
+    connect_raws(&mut res, last, raw_next, off, join, tol.connect);
+    res
+}
+
+#[doc(hidden)]
+// Connect the ends of the raw offset segments of open polylines with arcs.
+pub fn offset_connect_raw_open(
+    raws: &[Vec<OffsetRaw>],
+    off: f64,
+    join: JoinStyle,
+    tol: &Tolerances,
+) -> Vec<Vec<Arc>> {
+    let mut res = Vec::with_capacity(raws.len());
+    for raw in raws.iter() {
+        res.push(offset_connect_raw_open_single(raw, off, join, tol));
+    }
+    res
+}
+
+// Same as offset_connect_raw_single, but the last and the first segment are not connected.
+pub fn offset_connect_raw_open_single(
+    raws: &[OffsetRaw],
+    off: f64,
+    join: JoinStyle,
+    tol: &Tolerances,
+) -> Vec<Arc> {
+    let mut res = Vec::with_capacity(raws.len());
+    for pair in raws.windows(2) {
+        connect_raws(&mut res, &pair[0], &pair[1], off, join, tol.connect);
     }
-    let last = raws.len() - 1;
-    for i in 0..last {
-        // make arcs ccw
-        let old = raws[i].arc;
-        let old_next = raws[i + 1].arc;
-        let g0 = raws[i].g;
-        let g1 = raws[i + 1].g;
-        let orig = raws[i].orig;
-        //let mut connect = arc(old.b, old_next.a, orig, off);
-        let (mut connect, check, convex) = arc_connect_new(old, old_next, g0, g1, orig, off);
-        connect.id(ID_PADDING + old.id);
-        if convex {
-            // only add connecting arcs between convex arcs formation
-            if check {
-                // only add valid arcs
-                res.push(connect);
-            } else {
-                // Small arc replaced by line
-                let mut small = arcseg(connect.a, connect.b);
-                small.id(ID_PADDING + old.id);
-                res.push(small);
+    res
+}
+
+#[doc(hidden)]
+// Connect the ends of the raw offset segments with their own offset distances,
+// offs has the same layout as raws.
+pub fn offset_connect_raw_variable(
+    raws: &mut [Vec<OffsetRaw>],
+    offs: &[Vec<f64>],
+    tol: &Tolerances,
+) -> Vec<Vec<Arc>> {
+    let eps = tol.connect;
+    let mut res = Vec::with_capacity(raws.len());
+    for (raw, offs) in raws.iter_mut().zip(offs.iter()) {
+        let n = raw.len();
+        let mut arcs = Vec::with_capacity(n);
+        for i in 0..n {
+            let j = (i + 1) % n;
+            if offs[i] == offs[j] {
+                connect_raws(&mut arcs, &raw[i], &raw[j], offs[i], JoinStyle::Round, eps);
+            } else if let Some((connect, cut)) = connect_transition(&raw[i], &raw[j], offs[i], offs[j], eps) {
+                // The offset with the smaller distance ends exactly on the arc,
+                // its part inside of the arc is never valid
+                if offs[j].abs() > offs[i].abs() {
+                    trim_raw(&mut raw[i], cut, true, eps);
+                } else {
+                    trim_raw(&mut raw[j], cut, false, eps);
+                }
+                arcs.push(connect);
             }
         }
+        res.push(arcs);
     }
-    // close end of line
-    let last = raws.last().unwrap();
-    let old = last.arc;
-    let raw_next = raws.first().unwrap();
+    res
+}
+
+// Arc connecting offsets with different distances, with the larger distance around the corner,
+// and the point where it crosses the offset with the smaller distance.
+fn connect_transition(
+    raw: &OffsetRaw,
+    raw_next: &OffsetRaw,
+    off0: f64,
+    off1: f64,
+    eps: f64,
+) -> Option<(Arc, Point)> {
+    let orig = raw.orig;
+    let end = raw_end(raw);
+    let start = raw_start(raw_next);
+    let (a, b, r, cut) = if off1.abs() > off0.abs() {
+        let a = transition_point(raw, end, orig, off1.abs(), false)?;
+        (a, start, off1.abs(), a)
+    } else {
+        let b = transition_point(raw_next, start, orig, off0.abs(), true)?;
+        (end, b, off0.abs(), b)
+    };
+    // Right side goes CCW from a to b, left side CW
+    let mut connect = if off0 >= ZERO {
+        arc(a, b, orig, r)
+    } else {
+        arc(b, a, orig, r)
+    };
+    // Concave corners need the arc only where it is outside of both offsets
+    if ccw_angle(orig, connect.a, connect.b) > 1.5 * std::f64::consts::PI
+        || !connect.is_valid(eps)
+    {
+        return None;
+    }
+    connect.id(join_id(raw.arc.id));
+    Some((connect, cut))
+}
+
+// Moves the end (or the start) of the offset to the point, when the point is on the offset
+fn trim_raw(raw: &mut OffsetRaw, p: Point, at_end: bool, eps: f64) {
+    let old = raw.arc;
+    let on_raw = if old.is_seg() {
+        dist_point_segment(&p, &segment(old.a, old.b)).0 < eps
+    } else {
+        match dist_point_arc(&p, &old) {
+            DistPointArcConfig::OnePoint(dist, _) | DistPointArcConfig::Equidistant(dist, _) => {
+                dist < eps
+            }
+        }
+    };
+    if !on_raw {
+        return;
+    }
+    // The path goes from a to b for positive g
+    let (a, b) = if at_end == (raw.g >= ZERO) {
+        (old.a, p)
+    } else {
+        (p, old.b)
+    };
+    let mut trimmed = if old.is_seg() {
+        arcseg(a, b)
+    } else {
+        arc(a, b, old.c, old.r)
+    };
+    if trimmed.is_valid(eps) {
+        trimmed.id(old.id);
+        raw.arc = trimmed;
+    }
+}
+
+// Point of the offset (or its extension) at distance r from orig,
+// before the point p in the path direction, or after it when ahead is true.
+fn transition_point(raw: &OffsetRaw, p: Point, orig: Point, r: f64, ahead: bool) -> Option<Point> {
+    let seg = &raw.arc;
+    let candidates = if seg.is_seg() {
+        line_circle_points(&line_of(seg), &circle(orig, r))
+    } else {
+        match int_circle_circle(circle(seg.c, seg.r), circle(orig, r)) {
+            CircleCircleConfig::NoncocircularOnePoint(p) => vec![p],
+            CircleCircleConfig::NoncocircularTwoPoints(p0, p1) => vec![p0, p1],
+            _ => Vec::new(),
+        }
+    };
+    // Direction of the path at p
+    let dir = if seg.is_seg() {
+        seg.b - seg.a
+    } else {
+        let radius = p - seg.c;
+        point(-radius.y, radius.x)
+    };
+    let dir = if raw.g >= ZERO { dir } else { point(-dir.x, -dir.y) };
+    candidates
+        .into_iter()
+        .filter(|q| ((*q - p).dot(dir) > ZERO) == ahead)
+        .min_by(|q0, q1| (*q0 - p).norm().total_cmp(&(*q1 - p).norm()))
+}
+
+// Adds the arc (or lines for miter and bevel joins) connecting the end of raw with the start of raw_next
+fn connect_raws(
+    res: &mut Vec<Arc>,
+    raw: &OffsetRaw,
+    raw_next: &OffsetRaw,
+    off: f64,
+    join: JoinStyle,
+    eps: f64,
+) {
+    // make arcs ccw
+    let old = raw.arc;
     let old_next = raw_next.arc;
-    let g0 = last.g;
+    let g0 = raw.g;
     let g1 = raw_next.g;
-    let orig = last.orig;
+    let orig = raw.orig;
     // let mut connect = arc(old.b, old_next.a, orig, off);
-    let (mut connect, check, convex) = arc_connect_new(old, old_next, g0, g1, orig, off);
-    connect.id(ID_PADDING + old.id);
+    let (mut connect, check, convex) = arc_connect_new(old, old_next, g0, g1, orig, off, eps);
+    connect.id(join_id(old.id));
     if convex {
         // only add connecting arcs between convex arcs formation
         if check {
             // only add valid arcs
-            res.push(connect);
+            match corner_join(raw, raw_next, off, join, eps) {
+                CornerJoin::Round => res.push(connect),
+                CornerJoin::Bevel => {
+                    push_join_line(res, connect.a, connect.b, old.id);
+                    // The round join splits the offsets behind the bevel line,
+                    // it is removed in the prune stage
+                    res.push(connect);
+                }
+                CornerJoin::Fill(pieces, _) => res.extend(pieces),
+            }
         } else {
             // Small arc replaced by line
             let mut small = arcseg(connect.a, connect.b);
-            small.id(ID_PADDING + old.id);
+            small.id(join_id(old.id));
             res.push(small);
         }
     }
+}
+
+// Join of a valid convex corner
+enum CornerJoin {
+    Round,
+    Bevel,
+    // Lines or arcs from the end of the offset to the start of the next offset, and the polygon
+    // they add to the offset area (with the corner point)
+    Fill(Vec<Arc>, Polyline),
+}
+
+fn corner_join(raw: &OffsetRaw, raw_next: &OffsetRaw, off: f64, join: JoinStyle, eps: f64) -> CornerJoin {
+    let end = raw_end(raw);
+    let start = raw_start(raw_next);
+    let orig = raw.orig;
+    let tip = match join {
+        JoinStyle::Round => return CornerJoin::Round,
+        JoinStyle::Bevel => return CornerJoin::Bevel,
+        JoinStyle::Miter { limit } => match miter_point(end, start, orig, off.abs(), limit) {
+            Some(tip) => tip,
+            None => return CornerJoin::Bevel,
+        },
+        JoinStyle::Extend => {
+            return match extend_point(raw, raw_next, eps) {
+                Some(ext) => extend_join(raw, raw_next, ext),
+                None => CornerJoin::Round,
+            };
+        }
+    };
+    // Each half of the miter takes the id of the segment it extends
+    let mut pieces = Vec::with_capacity(2);
+    push_join_line(&mut pieces, end, tip, raw.arc.id);
+    push_join_line(&mut pieces, tip, start, raw_next.arc.id);
+    let fill = vec![
+        pvertex(orig, ZERO),
+        pvertex(end, ZERO),
+        pvertex(tip, ZERO),
+        pvertex(start, ZERO),
+    ];
+    CornerJoin::Fill(pieces, fill)
+}
+
+// End of the offset, in the path direction
+fn raw_end(raw: &OffsetRaw) -> Point {
+    if raw.g >= ZERO { raw.arc.b } else { raw.arc.a }
+}
+
+// Start of the offset, in the path direction
+fn raw_start(raw: &OffsetRaw) -> Point {
+    if raw.g >= ZERO { raw.arc.a } else { raw.arc.b }
+}
+
+/// Convex corner with a miter, extend or bevel join.
+///
+/// Miter and extend joins add the polygon from the corner point through the joined
+/// offset ends to the offset area, bevel joins remove the part of the round join
+/// behind the bevel line.
+/// For bevel joins the round join arc is also added to the offsets, so that the offsets
+/// are split where they leave the removed part.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JoinCorner {
+    /// Corner point of the input
+    pub orig: Point,
+    /// Round join arc of the corner
+    pub round: Arc,
+    /// Polygon added to the offset area, none for bevel joins
+    pub fill: Option<Polyline>,
+}
+
+#[doc(hidden)]
+// Finds the corners joined by miter, extend or bevel, none for round joins.
+pub fn offset_join_corners(
+    raws: &[Vec<OffsetRaw>],
+    off: f64,
+    join: JoinStyle,
+    closed: bool,
+    tol: &Tolerances,
+) -> Vec<JoinCorner> {
+    let eps = tol.connect;
+    let mut res = Vec::new();
+    if join == JoinStyle::Round {
+        return res;
+    }
+    for raw in raws.iter() {
+        for pair in raw.windows(2) {
+            res.extend(join_corner(&pair[0], &pair[1], off, join, eps));
+        }
+        if closed
+            && let (Some(last), Some(first)) = (raw.last(), raw.first())
+        {
+            res.extend(join_corner(last, first, off, join, eps));
+        }
+    }
     res
 }
 
+fn join_corner(
+    raw: &OffsetRaw,
+    raw_next: &OffsetRaw,
+    off: f64,
+    join: JoinStyle,
+    eps: f64,
+) -> Option<JoinCorner> {
+    let (mut round, check, convex) =
+        arc_connect_new(raw.arc, raw_next.arc, raw.g, raw_next.g, raw.orig, off, eps);
+    round.id(join_id(raw.arc.id));
+    // Small joins are replaced by lines for all join styles
+    if !convex || !check {
+        return None;
+    }
+    let fill = match corner_join(raw, raw_next, off, join, eps) {
+        CornerJoin::Round => return None,
+        CornerJoin::Bevel => None,
+        CornerJoin::Fill(_, fill) => Some(fill),
+    };
+    Some(JoinCorner {
+        orig: raw.orig,
+        round,
+        fill,
+    })
+}
+
+fn push_join_line(res: &mut Vec<Arc>, a: Point, b: Point, id: usize) {
+    let mut line = arcseg(a, b);
+    line.id(join_id(id));
+    res.push(line);
+}
+
+// Intersection of the tangents of the join arc at its ends, the offsets are perpendicular
+// to the corner radius at both ends. None when the miter is longer than limit * off.
+fn miter_point(a: Point, b: Point, orig: Point, off: f64, limit: f64) -> Option<Point> {
+    let mid = (a - orig) + (b - orig);
+    let len = mid.norm();
+    // Ratio of the miter length to the offset distance, 1 / cos(angle / 2)
+    if len * limit < 2.0 * off {
+        return None;
+    }
+    Some(orig + mid * (2.0 * off * off / (len * len)))
+}
+
+// Point where the offsets meet when the first one is extended past its end and the next one
+// before its start, lines along their direction and arcs along their circle.
+// The closest such point (by the total length of the extensions), none when they do not meet.
+fn extend_point(raw: &OffsetRaw, raw_next: &OffsetRaw, eps: f64) -> Option<Point> {
+    let (arc0, arc1) = (&raw.arc, &raw_next.arc);
+    let candidates: Vec<Point> = match (arc0.is_seg(), arc1.is_seg()) {
+        (true, true) => match int_line_line(&line_of(arc0), &line_of(arc1)) {
+            LineLineConfig::OnePoint(p, _, _) => vec![p],
+            _ => Vec::new(),
+        },
+        (true, false) => line_circle_points(&line_of(arc0), &circle(arc1.c, arc1.r)),
+        (false, true) => line_circle_points(&line_of(arc1), &circle(arc0.c, arc0.r)),
+        (false, false) => match int_circle_circle(circle(arc0.c, arc0.r), circle(arc1.c, arc1.r)) {
+            CircleCircleConfig::NoncocircularOnePoint(p) => vec![p],
+            CircleCircleConfig::NoncocircularTwoPoints(p0, p1) => vec![p0, p1],
+            _ => Vec::new(),
+        },
+    };
+    let end = raw_end(raw);
+    let start = raw_start(raw_next);
+    candidates
+        .into_iter()
+        .filter_map(|p| {
+            let len0 = extension_length(raw, end, p, true, eps)?;
+            let len1 = extension_length(raw_next, start, p, false, eps)?;
+            Some((p, len0 + len1))
+        })
+        .min_by(|(_, len0), (_, len1)| len0.total_cmp(len1))
+        .map(|(p, _)| p)
+}
+
+fn line_of(seg: &Arc) -> Line {
+    line(seg.a, seg.b - seg.a)
+}
+
+fn line_circle_points(line: &Line, circle: &Circle) -> Vec<Point> {
+    match int_line_circle(line, circle) {
+        LineCircleConfig::OnePoint(p, _) => vec![p],
+        LineCircleConfig::TwoPoints(p0, p1, _, _) => vec![p0, p1],
+        LineCircleConfig::NoIntersection() => Vec::new(),
+    }
+}
+
+// Length of the extension of the offset from its end (or backwards from its start) to p,
+// none when p is not on the extension, or the arc would come near a full turn
+// with the same extension at both ends (see EXTEND_TURN_MARGIN).
+fn extension_length(raw: &OffsetRaw, from: Point, p: Point, forward: bool, eps: f64) -> Option<f64> {
+    let seg = &raw.arc;
+    // Direction of the path along the offset
+    let ccw = raw.g >= ZERO;
+    let len = if seg.is_seg() {
+        let dir = if ccw { seg.b - seg.a } else { seg.a - seg.b };
+        let dir = if forward { dir } else { -dir };
+        (p - from).dot(dir) / dir.norm()
+    } else {
+        let angle = if ccw == forward {
+            ccw_angle(seg.c, from, p)
+        } else {
+            ccw_angle(seg.c, p, from)
+        };
+        let span = ccw_angle(seg.c, seg.a, seg.b);
+        if span + 2.0 * angle + eps / seg.r >= std::f64::consts::TAU - EXTEND_TURN_MARGIN {
+            return None;
+        }
+        angle * seg.r
+    };
+    if len > eps { Some(len) } else { None }
+}
+
+// Angle from a to b around c, counter clockwise in [0, 2pi)
+fn ccw_angle(c: Point, a: Point, b: Point) -> f64 {
+    let u = a - c;
+    let v = b - c;
+    let angle = u.perp(v).atan2(u.dot(v));
+    if angle < ZERO { angle + std::f64::consts::TAU } else { angle }
+}
+
+// Extensions of both offsets to the point where they meet
+fn extend_join(raw: &OffsetRaw, raw_next: &OffsetRaw, ext: Point) -> CornerJoin {
+    let end = raw_end(raw);
+    let start = raw_start(raw_next);
+    let (piece0, bulge0) = extension_piece(raw, end, ext);
+    let (piece1, bulge1) = extension_piece(raw_next, ext, start);
+    let fill = vec![
+        pvertex(raw.orig, ZERO),
+        pvertex(end, bulge0),
+        pvertex(ext, bulge1),
+        pvertex(start, ZERO),
+    ];
+    CornerJoin::Fill(vec![piece0, piece1], fill)
+}
+
+// Piece of the extended offset from a to b in the path direction, and its bulge
+fn extension_piece(raw: &OffsetRaw, a: Point, b: Point) -> (Arc, f64) {
+    let seg = &raw.arc;
+    let mut piece = if seg.is_seg() {
+        arcseg(a, b)
+    } else if raw.g >= ZERO {
+        arc(a, b, seg.c, seg.r)
+    } else {
+        arc(b, a, seg.c, seg.r)
+    };
+    piece.id(join_id(seg.id));
+    let bulge = if piece.is_seg() {
+        ZERO
+    } else if raw.g >= ZERO {
+        bulge_from_arc(piece.a, piece.b, piece.c, piece.r).abs()
+    } else {
+        -bulge_from_arc(piece.a, piece.b, piece.c, piece.r).abs()
+    };
+    (piece, bulge)
+}
+
+/// Merges the miter and extend join pieces with the offsets they extend.
+///
+/// Does nothing for other join styles. The arcs of each loop (or open chain when
+/// `closed` is false) are expected in order, as returned by the reconnect stage.
+pub fn merge_join_extensions(loops: &mut [Vec<Arc>], join: JoinStyle, closed: bool, tol: &Tolerances) {
+    if !matches!(join, JoinStyle::Miter { .. } | JoinStyle::Extend) {
+        return;
+    }
+    for arcs in loops.iter_mut() {
+        merge_join_extensions_single(arcs, closed, tol.connect);
+    }
+}
+
+fn merge_join_extensions_single(arcs: &mut Vec<Arc>, closed: bool, eps: f64) {
+    let mut i = 0;
+    while arcs.len() > 1 && i < arcs.len() {
+        if i + 1 == arcs.len() && !closed {
+            break;
+        }
+        let j = (i + 1) % arcs.len();
+        let merged = if !is_join_of(arcs[i].id, arcs[j].id) && !is_join_of(arcs[j].id, arcs[i].id) {
+            None
+        } else if arcs[i].is_seg() && arcs[j].is_seg() {
+            merge_lines(&arcs[i], &arcs[j], eps)
+        } else if arcs[i].is_arc() && arcs[j].is_arc() {
+            merge_arcs(&arcs[i], &arcs[j], eps)
+        } else {
+            None
+        };
+        match merged {
+            Some(merged) => {
+                arcs[i] = merged;
+                let _ = arcs.remove(j);
+                if j < i {
+                    i -= 1;
+                }
+            }
+            None => i += 1,
+        }
+    }
+}
+
+// Collinear line and its extension, as one line in the same traversal direction
+fn merge_lines(line: &Arc, next: &Arc, eps: f64) -> Option<Arc> {
+    let (start, shared) = if line.b.close_enough(next.a, eps)
+        || line.b.close_enough(next.b, eps)
+    {
+        (line.a, line.b)
+    } else {
+        (line.b, line.a)
+    };
+    let end = if next.a.close_enough(shared, eps) {
+        next.b
+    } else if next.b.close_enough(shared, eps) {
+        next.a
+    } else {
+        return None;
+    };
+    let u = shared - start;
+    let v = end - shared;
+    if u.perp(v).abs() > eps * u.norm() * v.norm() || u.dot(v) <= ZERO {
+        return None;
+    }
+    let mut merged = arcseg(start, end);
+    merged.id(line.id.min(next.id));
+    Some(merged)
+}
+
+// Arc and its extension on the same circle, as one arc
+fn merge_arcs(arc0: &Arc, arc1: &Arc, eps: f64) -> Option<Arc> {
+    if !arc0.c.close_enough(arc1.c, eps) || (arc0.r - arc1.r).abs() > eps {
+        return None;
+    }
+    // Arcs are CCW, the extension continues at one of the ends
+    let (a, b) = if arc0.b.close_enough(arc1.a, eps) {
+        (arc0.a, arc1.b)
+    } else if arc1.b.close_enough(arc0.a, eps) {
+        (arc1.a, arc0.b)
+    } else {
+        return None;
+    };
+    if a.close_enough(b, eps) {
+        return None; // full circle
+    }
+    let mut merged = arc(a, b, arc0.c, arc0.r);
+    merged.id(arc0.id.min(arc1.id));
+    Some(merged)
+}
+
 fn arc_connect_new(
     old: Arc,
     old_next: Arc,
@@ -87,62 +598,46 @@ fn arc_connect_new(
     g1: f64,
     orig: Point,
     off: f64,
+    eps: f64,
 ) -> (Arc, bool, bool) {
-    let seg: Arc;
-    let convex: bool;
+    // End of the first offset and start of the next one, in the path direction
+    let end = if g0 >= ZERO { old.b } else { old.a };
+    let start = if g1 >= ZERO { old_next.a } else { old_next.b };
     let b = Coord {
         x: orig.x,
         y: orig.y,
     };
-    if g0 >= ZERO && g1 >= ZERO {
-        seg = arc(old.b, old_next.a, orig, off);
-        let a = Coord {
-            x: old.b.x,
-            y: old.b.y,
-        };
-        let c = Coord {
-            x: old_next.a.x,
-            y: old_next.a.y,
-        };
-        convex = orient2d(a, b, c) < ZERO;
-    } else if g0 >= ZERO && g1 < ZERO {
-        seg = arc(old.b, old_next.b, orig, off);
-        let a = Coord {
-            x: old.b.x,
-            y: old.b.y,
-        };
-        let c = Coord {
-            x: old_next.b.x,
-            y: old_next.b.y,
-        };
-        convex = orient2d(a, b, c) < ZERO;
-    } else if g0 < ZERO && g1 >= ZERO {
-        seg = arc(old.a, old_next.a, orig, off);
-        let a = Coord {
-            x: old.a.x,
-            y: old.a.y,
-        };
-        let c = Coord {
-            x: old_next.a.x,
-            y: old_next.a.y,
-        };
-        convex = orient2d(a, b, c) < ZERO;
+    let a = Coord { x: end.x, y: end.y };
+    let c = Coord {
+        x: start.x,
+        y: start.y,
+    };
+    let seg: Arc;
+    let convex: bool;
+    if off >= ZERO {
+        // Right side, the connection goes CCW from end to start
+        seg = arc(end, start, orig, off);
+        convex = is_convex(a, b, c, eps);
     } else {
-        // g0 < 0 && g1 < 0
-        seg = arc(old.a, old_next.b, orig, off);
-        let a = Coord {
-            x: old.a.x,
-            y: old.a.y,
-        };
-        let c = Coord {
-            x: old_next.b.x,
-            y: old_next.b.y,
-        };
-        convex = orient2d(a, b, c) < ZERO;
+        // Left side, the connection goes CW from end to start, and arcs are always CCW
+        seg = arc(start, end, orig, -off);
+        convex = is_convex(c, b, a, eps);
     }
     // We only create new arc if the arcs to be connected form convex angle.
     // In concave case, we do not need connection because it will be removed as invalid latter
-    (seg, seg.is_valid(EPS_CONNECT_RAW), convex)
+    (seg, seg.is_valid(eps), convex)
+}
+
+// Convex angle, or the path turns back (the offset ends are on the opposite sides of orig,
+// at the same distance from it)
+fn is_convex(a: Coord<f64>, b: Coord<f64>, c: Coord<f64>, eps: f64) -> bool {
+    let orient = orient2d(a, b, c);
+    if orient != ZERO {
+        return orient < ZERO;
+    }
+    let (ax, ay, cx, cy) = (a.x - b.x, a.y - b.y, c.x - b.x, c.y - b.y);
+    let opposite = ax * cx + ay * cy < ZERO;
+    opposite && (ax.hypot(ay) - cx.hypot(cy)).abs() < eps
 }
 
 #[cfg(test)]
@@ -160,16 +655,16 @@ mod test_offset_connect_raw {
             pvertex(point(100.0, 100.0), 0.5),
             pvertex(point(200.0, 200.0), 0.5),
         ]];
-        let poly_raws = poly_to_raws(&pline);
+        let poly_raws = poly_to_raws(&pline, &Tolerances::default());
         let mut svg = svg(300.0, 350.0);
         svg_offset_raws(&mut svg, &poly_raws, "red");
 
         let off: f64 = 52.25;
 
-        let offset_raw = offset_polyline_raw(&poly_raws, off);
+        let offset_raw = offset_polyline_raw(&poly_raws, off, &Tolerances::default());
         svg_offset_raws(&mut svg, &offset_raw, "blue");
 
-        let offset_connect = offset_connect_raw(&offset_raw, off);
+        let offset_connect = offset_connect_raw(&offset_raw, off, JoinStyle::Round, &Tolerances::default());
         svg.arclines(&offset_connect, "violet");
 
         svg.write();
@@ -187,16 +682,16 @@ mod test_offset_connect_raw {
             pvertex(point(100.0, 300.0), 0.5),
             pvertex(point(0.0, 200.0), 0.5),
         ]];
-        let poly_raws = poly_to_raws(&pline);
+        let poly_raws = poly_to_raws(&pline, &Tolerances::default());
         let mut svg = svg(300.0, 400.0);
         svg_offset_raws(&mut svg, &poly_raws, "red");
 
         let off: f64 = 22.0;
 
-        let offset_raw = offset_polyline_raw(&poly_raws, off);
+        let offset_raw = offset_polyline_raw(&poly_raws, off, &Tolerances::default());
         svg_offset_raws(&mut svg, &offset_raw, "blue");
 
-        let offset_connect = offset_connect_raw(&offset_raw, off);
+        let offset_connect = offset_connect_raw(&offset_raw, off, JoinStyle::Round, &Tolerances::default());
         svg.arclines(&offset_connect, "violet");
 
         svg.write();
@@ -259,9 +754,9 @@ mod test_offset_connect_raw {
         svg.polyline(&plines[0], "grey");
 
         let off: f64 = 16.00;
-        let poly_raws = poly_to_raws(&plines);
-        let offset_raw1 = offset_polyline_raw(&poly_raws, off);
-        let offset_raw2 = offset_connect_raw(&offset_raw1, off);
+        let poly_raws = poly_to_raws(&plines, &Tolerances::default());
+        let offset_raw1 = offset_polyline_raw(&poly_raws, off, &Tolerances::default());
+        let offset_raw2 = offset_connect_raw(&offset_raw1, off, JoinStyle::Round, &Tolerances::default());
 
         svg_offset_raws(&mut svg, &offset_raw1, "red");
         svg.arclines(&offset_raw2, "blue");
@@ -271,12 +766,14 @@ mod test_offset_connect_raw {
 
 #[cfg(test)]
 mod test_offset_connect_raw_single {
+    use crate::offset_arcs_raw::offset_polyline_raw;
+
     use super::*;
 
     #[test]
     fn test_empty_input() {
         let raws = vec![];
-        let result = offset_connect_raw_single(&raws, 5.0);
+        let result = offset_connect_raw_single(&raws, 5.0, JoinStyle::Round, &Tolerances::default());
         assert_eq!(result.len(), 0);
     }
 
@@ -286,7 +783,7 @@ mod test_offset_connect_raw_single {
         let raw = OffsetRaw::new(arc, point(0.0, 0.0), 0.0);
         let raws = vec![raw];
 
-        let result = offset_connect_raw_single(&raws, 5.0);
+        let result = offset_connect_raw_single(&raws, 5.0, JoinStyle::Round, &Tolerances::default());
 
         // Single element creates a closing connection, but only if it passes validity checks
         assert!(result.len() <= 1);
@@ -308,7 +805,7 @@ mod test_offset_connect_raw_single {
         let raw2 = OffsetRaw::new(arc2, point(2.0, 1.5), 0.0); // orig on vertical line
         let raws = vec![raw1, raw2];
 
-        let result = offset_connect_raw_single(&raws, offset_dist);
+        let result = offset_connect_raw_single(&raws, offset_dist, JoinStyle::Round, &Tolerances::default());
 
         // Function only adds connections if they pass validity and convexity checks
         assert!(result.len() <= 2);
@@ -325,7 +822,7 @@ mod test_offset_connect_raw_single {
         let raws = vec![raw1, raw2];
 
         let offset = 1.0;
-        let result = offset_connect_raw_single(&raws, offset);
+        let result = offset_connect_raw_single(&raws, offset, JoinStyle::Round, &Tolerances::default());
 
         // Mixed g values with gaps - connection depends on geometry
         assert!(result.len() <= 2);
@@ -343,7 +840,7 @@ mod test_offset_connect_raw_single {
         let raws = vec![raw1, raw2];
 
         let offset = 1.0;
-        let result = offset_connect_raw_single(&raws, offset);
+        let result = offset_connect_raw_single(&raws, offset, JoinStyle::Round, &Tolerances::default());
 
         // Line segments with gap
         assert!(result.len() <= 2);
@@ -367,7 +864,7 @@ mod test_offset_connect_raw_single {
         let raw4 = OffsetRaw::new(arc4, point(0.0, 0.5), 0.0);  // orig on left edge
         let raws = vec![raw1, raw2, raw3, raw4];
 
-        let result = offset_connect_raw_single(&raws, offset_dist);
+        let result = offset_connect_raw_single(&raws, offset_dist, JoinStyle::Round, &Tolerances::default());
 
         // Should attempt 4 corner connections, but only valid+convex ones are added
         assert!(result.len() <= 4);
@@ -387,7 +884,7 @@ mod test_offset_connect_raw_single {
         let raws = vec![raw1, raw2, raw3];
 
         let offset = 1.0;
-        let result = offset_connect_raw_single(&raws, offset);
+        let result = offset_connect_raw_single(&raws, offset, JoinStyle::Round, &Tolerances::default());
 
         // Should attempt 3 connections, but only valid ones are added
         assert!(result.len() <= 3);
@@ -408,7 +905,7 @@ mod test_offset_connect_raw_single {
         let raws = vec![raw1, raw2];
 
         let offset = 1.0;
-        let result = offset_connect_raw_single(&raws, offset);
+        let result = offset_connect_raw_single(&raws, offset, JoinStyle::Round, &Tolerances::default());
 
         // Valid arc segments with gap
         assert!(result.len() <= 2);
@@ -425,7 +922,7 @@ mod test_offset_connect_raw_single {
         let raws = vec![raw1, raw2];
 
         let offset = 0.0;
-        let result = offset_connect_raw_single(&raws, offset);
+        let result = offset_connect_raw_single(&raws, offset, JoinStyle::Round, &Tolerances::default());
 
         // Zero offset - no connections should be made
         assert_eq!(result.len(), 0);
@@ -433,19 +930,32 @@ mod test_offset_connect_raw_single {
 
     #[test]
     fn test_negative_offset() {
-        let arc1 = arcseg(point(0.0, 0.0), point(1.0, 0.0));
-        let arc2 = arcseg(point(2.0, 0.0), point(3.0, 0.0));
+        // Square (0,0), (0,10), (10,10), (10,0) is CW, the left side is outside
+        let arcs = [
+            arcseg(point(0.0, 0.0), point(0.0, 10.0)),
+            arcseg(point(0.0, 10.0), point(10.0, 10.0)),
+            arcseg(point(10.0, 10.0), point(10.0, 0.0)),
+            arcseg(point(10.0, 0.0), point(0.0, 0.0)),
+        ];
+        let offset_raw = offset_polyline_raw(&offset_raw_input(&arcs), -1.0, &Tolerances::default());
 
-        // Line segments must have g = 0.0
-        let raw1 = OffsetRaw::new(arc1, point(0.5, 0.0), 0.0);
-        let raw2 = OffsetRaw::new(arc2, point(2.5, 0.0), 0.0);
-        let raws = vec![raw1, raw2];
+        let result = offset_connect_raw_single(&offset_raw[0], -1.0, JoinStyle::Round, &Tolerances::default());
 
-        let offset = -1.0;
-        let result = offset_connect_raw_single(&raws, offset);
+        // Negative offset - connections on the left side, at all four convex corners
+        assert_eq!(result.len(), 4);
+        assert_eq!(result[0].c, point(0.0, 10.0));
+        assert_eq!(result[0].r, 1.0);
+        // CCW from the start of the next offset to the end of the previous one
+        assert_eq!(result[0].a, point(0.0, 11.0));
+        assert_eq!(result[0].b, point(-1.0, 10.0));
 
-        // Negative offset - no connections should be made  
-        assert_eq!(result.len(), 0);
+        // The right side is inside, no connections at concave corners
+        let offset_raw = offset_polyline_raw(&offset_raw_input(&arcs), 1.0, &Tolerances::default());
+        assert!(offset_connect_raw_single(&offset_raw[0], 1.0, JoinStyle::Round, &Tolerances::default()).is_empty());
+    }
+
+    fn offset_raw_input(arcs: &[Arc]) -> Vec<Vec<OffsetRaw>> {
+        vec![arcs.iter().map(|a| OffsetRaw::new(*a, a.b, 0.0)).collect()]
     }
 
     #[test]
@@ -459,7 +969,7 @@ mod test_offset_connect_raw_single {
         let raws = vec![raw1, raw2];
 
         let offset = 1000.0;
-        let result = offset_connect_raw_single(&raws, offset);
+        let result = offset_connect_raw_single(&raws, offset, JoinStyle::Round, &Tolerances::default());
 
         // Large offset values
         assert!(result.len() <= 2);
@@ -477,7 +987,7 @@ mod test_offset_connect_raw_single {
         let raws = vec![raw1, raw2];
 
         let offset = 1.0;
-        let result = offset_connect_raw_single(&raws, offset);
+        let result = offset_connect_raw_single(&raws, offset, JoinStyle::Round, &Tolerances::default());
 
         // Large gap - connection validity depends on geometry
         assert!(result.len() <= 2);
@@ -499,7 +1009,7 @@ mod test_offset_connect_raw_single {
         let raw3 = OffsetRaw::new(arc3, point(9.0, 0.0), 0.0);
         let raws = vec![raw1, raw2, raw3];
 
-        let result = offset_connect_raw_single(&raws, offset_dist);
+        let result = offset_connect_raw_single(&raws, offset_dist, JoinStyle::Round, &Tolerances::default());
 
         // Should attempt to create connecting arcs between the gaps
         assert!(result.len() <= raws.len());
@@ -520,7 +1030,7 @@ mod test_offset_connect_raw_single {
         let raw2 = OffsetRaw::new(arc2, point(2.0, 2.0), 0.0); // orig on vertical line
         let raws = vec![raw1, raw2];
 
-        let result = offset_connect_raw_single(&raws, offset_dist);
+        let result = offset_connect_raw_single(&raws, offset_dist, JoinStyle::Round, &Tolerances::default());
 
         // Corner connection depends on geometry validity
         assert!(result.len() <= 2);
@@ -537,7 +1047,7 @@ mod test_offset_connect_raw_single {
         let raws = vec![raw1, raw2];
 
         let offset = 1.0;
-        let result = offset_connect_raw_single(&raws, offset);
+        let result = offset_connect_raw_single(&raws, offset, JoinStyle::Round, &Tolerances::default());
 
         // Large gap connection - geometry may or may not be valid
         assert!(result.len() <= 2);
@@ -558,7 +1068,7 @@ mod test_offset_connect_raw_single {
         let raws = vec![raw1, raw2];
 
         let offset = 1.0;
-        let result = offset_connect_raw_single(&raws, offset);
+        let result = offset_connect_raw_single(&raws, offset, JoinStyle::Round, &Tolerances::default());
 
         // Curved segments with gap - depends on curvature and gap size
         assert!(result.len() <= 2);
@@ -575,7 +1085,7 @@ mod test_offset_connect_raw_single {
         let raws = vec![raw1, raw2];
 
         let offset = 0.1;
-        let result = offset_connect_raw_single(&raws, offset);
+        let result = offset_connect_raw_single(&raws, offset, JoinStyle::Round, &Tolerances::default());
 
         // Very small segments - may not produce valid connections
         assert!(result.len() <= 2);
@@ -592,12 +1102,27 @@ mod test_offset_connect_raw_single {
         let raws = vec![raw1, raw2];
 
         let offset = 1.0;
-        let result = offset_connect_raw_single(&raws, offset);
+        let result = offset_connect_raw_single(&raws, offset, JoinStyle::Round, &Tolerances::default());
 
         // Angle type affects connection validity
         assert!(result.len() <= 2);
     }
 
+    #[test]
+    fn test_turn_back() {
+        // Offsets of the path (0,0) -> (10,0) -> (0,0), the path turns back at (10,0)
+        let arc1 = arcseg(point(0.0, -1.0), point(10.0, -1.0));
+        let arc2 = arcseg(point(10.0, 1.0), point(0.0, 1.0));
+        let raw1 = OffsetRaw::new(arc1, point(10.0, 0.0), 0.0);
+        let raw2 = OffsetRaw::new(arc2, point(0.0, 0.0), 0.0);
+        let result = offset_connect_raw_open_single(&[raw1, raw2], 1.0, JoinStyle::Round, &Tolerances::default());
+        // Half circle around the turning point
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].a, point(10.0, -1.0));
+        assert_eq!(result[0].b, point(10.0, 1.0));
+        assert_eq!(result[0].c, point(10.0, 0.0));
+    }
+
     #[test]
     fn test_all_g_value_combinations() {
         // Test various g values with curved arcs (not line segments)
@@ -620,7 +1145,7 @@ mod test_offset_connect_raw_single {
             let raw2 = OffsetRaw::new(arc2, point(2.5, 0.0), g2);
             let raws = vec![raw1, raw2];
 
-            let result = offset_connect_raw_single(&raws, 1.0);
+            let result = offset_connect_raw_single(&raws, 1.0, JoinStyle::Round, &Tolerances::default());
             
             // Each combination should handle gracefully
             assert!(result.len() <= 2);
@@ -636,13 +1161,13 @@ mod test_offset_connect_raw_single {
         let mut raw1 = OffsetRaw::new(arc1, point(0.5, 0.0), 0.0);
         let mut raw2 = OffsetRaw::new(arc2, point(2.5, 0.0), 0.0);
         
-        // Set specific IDs to test padding
+        // Set specific IDs to test the join ids
         raw1.arc.id(5);
         raw2.arc.id(10);
         let raws = vec![raw1, raw2];
 
         let offset = 1.0;
-        let result = offset_connect_raw_single(&raws, offset);
+        let result = offset_connect_raw_single(&raws, offset, JoinStyle::Round, &Tolerances::default());
 
         // Check ID assignment logic - arcs may be replaced by arcseg with different IDs
         if !result.is_empty() {
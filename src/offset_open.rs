@@ -0,0 +1,377 @@
+//! Single-sided offsetting of open Polylines and Arclines.
+//!
+//! Open contours (slot edges, chamfer paths, ...) have no segment from the last
+//! vertex back to the first one, so their offset is a set of open chains.
+
+use std::collections::HashMap;
+
+use togo::prelude::*;
+
+use crate::{
+    error::OffsetError,
+    graph::{
+        find_chains::{ArcChain, find_chains_with_tolerance},
+        merge_ends::merge_close_endpoints,
+    },
+    observer::polyline_arcs,
+    offset::{JoinStyle, OffsetCfg, check_arcline, check_polyline, offset_single},
+    offset_arcs_raw::{arcs_to_raws_open, poly_to_raws_open},
+    offset_connect_raw::merge_join_extensions,
+    offsetraw::OffsetRaw,
+    tolerances::{Tolerances, points_size},
+};
+
+/// Computes the offset of an open Polyline and returns result as multiple open Polylines.
+///
+/// Only one side of the polyline is offset, selected by the sign of `off`. The two ends of the
+/// offset stay open (there are no end caps) and the result is trimmed at self-intersections.
+/// Each resulting polyline is open: its last vertex is the end point of the chain,
+/// and its bulge is 0.
+///
+/// # Arguments
+///
+/// * `poly` - The input polyline, an open sequence of connected PVertex-es.
+///   The bulge of the last vertex is ignored.
+/// * `off` - The offset distance. Positive values offset to the "right" side of the polyline,
+///   negative values to the "left" side.
+/// * `cfg` - Configuration options controlling the offsetting behavior and observing the stages.
+///   `cfg.options.repair` is not used, `repair_polyline` expects a closed polyline.
+///
+/// # Returns
+///
+/// Open polylines ordered along the input and oriented in the input direction.
+/// Invalid or degenerate cases produce an empty vector
+/// (use `try_offset_open_polyline` to find out why).
+///
+/// # Examples
+///
+/// ```rust
+/// use togo::prelude::*;
+/// use offroad::prelude::*;
+///
+/// let mut cfg = OffsetCfg::default();
+/// let poly = vec![
+///     pvertex(point(0.0, 0.0), 0.0),
+///     pvertex(point(10.0, 0.0), 0.0),
+///     pvertex(point(10.0, 10.0), 0.0),
+/// ];
+///
+/// // The right side of the path is the outer side of the corner
+/// let offset_polylines = offset_open_polyline(&poly, 2.0, &mut cfg);
+/// assert_eq!(offset_polylines.len(), 1);
+/// // line, corner arc, line and the end vertex
+/// assert_eq!(offset_polylines[0].len(), 4);
+/// assert_eq!(offset_polylines[0][0].p, point(0.0, -2.0));
+/// assert_eq!(offset_polylines[0][3].p, point(12.0, 10.0));
+/// ```
+pub fn offset_open_polyline(poly: &Polyline, off: f64, cfg: &mut OffsetCfg) -> Vec<Polyline> {
+    try_offset_open_polyline(poly, off, cfg).unwrap_or_default()
+}
+
+/// Computes the offset of an open Polyline, reporting failures as `OffsetError`.
+///
+/// Same as `offset_open_polyline`, but returns an `OffsetError` instead of an empty vector,
+/// see `try_offset_polyline_to_polyline` for the list of errors.
+/// `OffsetError::UnclosedResult` is never reported, the result is open by design.
+///
+/// # Errors
+///
+/// See `try_offset_polyline_to_polyline`.
+pub fn try_offset_open_polyline(
+    poly: &Polyline,
+    off: f64,
+    cfg: &mut OffsetCfg,
+) -> Result<Vec<Polyline>, OffsetError> {
+    check_polyline(poly, off)?;
+    if let Some(observer) = cfg.observer.as_mut() {
+        observer.input(&[polyline_arcs(poly, false)]);
+    }
+
+    let tol = cfg.options.tolerances_for(points_size(poly.iter().map(|v| v.p)), off);
+    let poly_raws = poly_to_raws_open(&vec![poly.clone()], &tol);
+    let offset_arcs = offset_single(&poly_raws, off, cfg, false, &tol)?;
+    let chains = offset_chains(offset_arcs, &poly_raws, cfg.options.join, &tol);
+
+    if let Some(observer) = cfg.observer.as_mut() {
+        let arcs: Vec<Arcline> = chains.iter().map(|chain| chain.arcs.clone()).collect();
+        observer.reconnected(&arcs);
+        observer.output(&arcs);
+    }
+
+    let final_poly: Vec<Polyline> = chains.iter().map(chain_to_polyline).collect();
+    if final_poly.is_empty() {
+        return Err(OffsetError::Collapsed);
+    }
+    Ok(final_poly)
+}
+
+/// Computes the offset of an open Arcline and returns result as multiple open Arclines.
+///
+/// This function is similar to `offset_open_polyline` but operates on arclines.
+/// The arcs of each resulting Arcline are ordered along the input direction.
+/// If `cfg.options.reconnect` is not set, all offset arcs are returned unordered in one Arcline.
+///
+/// # Examples
+///
+/// ```rust
+/// use togo::prelude::*;
+/// use offroad::prelude::*;
+///
+/// let mut cfg = OffsetCfg::default();
+/// let arcline = vec![
+///     arcseg(point(0.0, 0.0), point(10.0, 0.0)),
+///     arcseg(point(10.0, 0.0), point(10.0, 10.0)),
+/// ];
+///
+/// let offset_arclines = offset_open_arcline(&arcline, 2.0, &mut cfg);
+/// assert_eq!(offset_arclines.len(), 1);
+/// assert_eq!(offset_arclines[0].len(), 3);
+/// ```
+pub fn offset_open_arcline(arcs: &Arcline, off: f64, cfg: &mut OffsetCfg) -> Vec<Arcline> {
+    try_offset_open_arcline(arcs, off, cfg).unwrap_or_default()
+}
+
+/// Computes the offset of an open Arcline, reporting failures as `OffsetError`.
+///
+/// Same as `offset_open_arcline`, but returns an `OffsetError` instead of an empty vector.
+///
+/// # Errors
+///
+/// See `try_offset_polyline_to_polyline`.
+pub fn try_offset_open_arcline(
+    arcs: &Arcline,
+    off: f64,
+    cfg: &mut OffsetCfg,
+) -> Result<Vec<Arcline>, OffsetError> {
+    check_arcline(arcs, off)?;
+    if let Some(observer) = cfg.observer.as_mut() {
+        observer.input(std::slice::from_ref(arcs));
+    }
+
+    let tol = cfg.options.tolerances_for(points_size(arcs.iter().flat_map(|a| [a.a, a.b])), off);
+    let poly_raws = arcs_to_raws_open(&vec![arcs.clone()], &tol);
+    let offset_arcs = offset_single(&poly_raws, off, cfg, false, &tol)?;
+
+    let mut final_arcs = Vec::new();
+    if cfg.options.reconnect {
+        final_arcs = offset_chains(offset_arcs, &poly_raws, cfg.options.join, &tol)
+            .into_iter()
+            .map(|chain| chain.arcs)
+            .collect();
+        if final_arcs.is_empty() {
+            return Err(OffsetError::Collapsed);
+        }
+        if let Some(observer) = cfg.observer.as_mut() {
+            observer.reconnected(&final_arcs);
+        }
+    } else {
+        final_arcs.push(offset_arcs);
+    }
+
+    if let Some(observer) = cfg.observer.as_mut() {
+        observer.output(&final_arcs);
+    }
+
+    Ok(final_arcs)
+}
+
+// Connects the pruned offset arcs into chains, oriented and ordered along the input.
+fn offset_chains(
+    mut offset_arcs: Vec<Arc>,
+    poly_raws: &[Vec<OffsetRaw>],
+    join: JoinStyle,
+    tol: &Tolerances,
+) -> Vec<ArcChain> {
+    merge_close_endpoints(&mut offset_arcs, tol.merge);
+    let mut chains = find_chains_with_tolerance(&offset_arcs, tol.vertex);
+
+    // Offset arcs keep the id of their input segment
+    let raws: HashMap<usize, (usize, &OffsetRaw)> = poly_raws
+        .iter()
+        .flatten()
+        .enumerate()
+        .map(|(i, raw)| (raw.arc.id, (i, raw)))
+        .collect();
+
+    for chain in chains.iter_mut() {
+        if chain_direction(chain, &raws) < 0 {
+            chain.reverse();
+        }
+    }
+    // Join arcs have no input segment and are ordered last
+    let first_segment = |chain: &ArcChain| {
+        chain
+            .arcs
+            .iter()
+            .filter_map(|arc| raws.get(&arc.id).map(|(i, _)| *i))
+            .min()
+            .unwrap_or(usize::MAX)
+    };
+    chains.sort_by_key(first_segment);
+    for chain in chains.iter_mut() {
+        merge_join_extensions(std::slice::from_mut(&mut chain.arcs), join, chain.closed, tol);
+    }
+    chains
+}
+
+// Positive if the chain mostly follows the direction of the input segments.
+fn chain_direction(chain: &ArcChain, raws: &HashMap<usize, (usize, &OffsetRaw)>) -> i64 {
+    let mut votes = 0;
+    let mut current = chain.start;
+    for arc in chain.arcs.iter() {
+        let forward = is_forward(arc, current);
+        let next = if forward { arc.b } else { arc.a };
+        if let Some((_, raw)) = raws.get(&arc.id) {
+            let same = if raw.arc.is_seg() && arc.is_seg() {
+                (next - current).dot(raw.arc.b - raw.arc.a) > 0.0
+            } else if raw.arc.is_arc() && arc.is_arc() {
+                // Arcs are CCW, negative bulge is traversed from b to a
+                forward == (raw.g >= 0.0)
+            } else {
+                // Arc collapsed into line, no information
+                current = next;
+                continue;
+            };
+            votes += if same { 1 } else { -1 };
+        }
+        current = next;
+    }
+    votes
+}
+
+fn is_forward(arc: &Arc, current: Point) -> bool {
+    (arc.a - current).norm() <= (arc.b - current).norm()
+}
+
+// Converts the chain to an open polyline, including the end vertex.
+fn chain_to_polyline(chain: &ArcChain) -> Polyline {
+    let mut pline = Vec::with_capacity(chain.arcs.len() + 1);
+    let mut current = chain.start;
+    for arc in chain.arcs.iter() {
+        let forward = is_forward(arc, current);
+        let bulge = if arc.is_seg() {
+            0.0
+        } else if forward {
+            bulge_from_arc(arc.a, arc.b, arc.c, arc.r)
+        } else {
+            -bulge_from_arc(arc.a, arc.b, arc.c, arc.r)
+        };
+        pline.push(pvertex(current, bulge));
+        current = if forward { arc.b } else { arc.a };
+    }
+    pline.push(pvertex(current, 0.0));
+    pline
+}
+
+#[cfg(test)]
+mod test_offset_open {
+    use super::*;
+
+    fn corner() -> Polyline {
+        vec![
+            pvertex(point(0.0, 0.0), 0.0),
+            pvertex(point(10.0, 0.0), 0.0),
+            pvertex(point(10.0, 10.0), 0.0),
+        ]
+    }
+
+    #[test]
+    fn test_open_convex_corner() {
+        let mut cfg = OffsetCfg::default();
+        let res = offset_open_polyline(&corner(), 2.0, &mut cfg);
+        assert_eq!(res.len(), 1);
+        let pline = &res[0];
+        assert_eq!(pline.len(), 4);
+        assert!(pline[0].p.close_enough(point(0.0, -2.0), 1e-10));
+        assert!(pline[1].p.close_enough(point(10.0, -2.0), 1e-10));
+        assert!(pline[1].b > 0.0);
+        assert!(pline[2].p.close_enough(point(12.0, 0.0), 1e-10));
+        assert!(pline[3].p.close_enough(point(12.0, 10.0), 1e-10));
+    }
+
+    #[test]
+    fn test_open_concave_corner() {
+        // Reversed path, the right side is inside the corner and is trimmed
+        let mut poly = corner();
+        poly.reverse();
+        let mut cfg = OffsetCfg::default();
+        let res = offset_open_polyline(&poly, 2.0, &mut cfg);
+        assert_eq!(res.len(), 1);
+        let pline = &res[0];
+        assert_eq!(pline.len(), 3);
+        assert!(pline[0].p.close_enough(point(8.0, 10.0), 1e-10));
+        assert!(pline[1].p.close_enough(point(8.0, 2.0), 1e-10));
+        assert!(pline[2].p.close_enough(point(0.0, 2.0), 1e-10));
+    }
+
+    #[test]
+    fn test_open_no_closing_segment() {
+        // Closed offset of the same points would contain the offset of (10, 10) -> (0, 0)
+        let mut cfg = OffsetCfg::default();
+        let res = offset_open_polyline(&corner(), 1.0, &mut cfg);
+        let closed = crate::offset::offset_polyline_to_polyline(&corner(), 1.0, &mut cfg);
+        assert_eq!(res.len(), 1);
+        assert_ne!(res[0].len(), closed[0].len());
+        assert_eq!(res[0].last().unwrap().b, 0.0);
+    }
+
+    #[test]
+    fn test_open_trimmed_at_self_intersection() {
+        // Narrow U turn, the right side of this path is inside the U
+        let poly = vec![
+            pvertex(point(3.0, 10.0), 0.0),
+            pvertex(point(3.0, 0.0), 0.0),
+            pvertex(point(0.0, 0.0), 0.0),
+            pvertex(point(0.0, 10.0), 0.0),
+        ];
+        let mut cfg = OffsetCfg::default();
+        let res = try_offset_open_polyline(&poly, 1.0, &mut cfg).unwrap();
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].len(), 4);
+        assert!(res[0][0].p.close_enough(point(2.0, 10.0), 1e-10));
+        assert!(res[0][1].p.close_enough(point(2.0, 1.0), 1e-10));
+        assert!(res[0][2].p.close_enough(point(1.0, 1.0), 1e-10));
+        assert!(res[0][3].p.close_enough(point(1.0, 10.0), 1e-10));
+        // The U is too narrow for this offset
+        let res = try_offset_open_polyline(&poly, 2.0, &mut cfg);
+        assert_eq!(res, Err(OffsetError::Collapsed));
+        // Outside of the U
+        let mut poly = poly;
+        poly.reverse();
+        let res = offset_open_polyline(&poly, 1.0, &mut cfg);
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].len(), 6);
+        assert!(res[0][0].p.close_enough(point(-1.0, 10.0), 1e-10));
+        assert!(res[0][5].p.close_enough(point(4.0, 10.0), 1e-10));
+    }
+
+    #[test]
+    fn test_open_arc_segment() {
+        // Half circle, CW, the right side is inside
+        let poly = vec![
+            pvertex(point(0.0, 0.0), -1.0),
+            pvertex(point(10.0, 0.0), 0.0),
+        ];
+        let mut cfg = OffsetCfg::default();
+        let res = offset_open_polyline(&poly, 1.0, &mut cfg);
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].len(), 2);
+        assert!(res[0][0].p.close_enough(point(1.0, 0.0), 1e-10));
+        assert!(res[0][0].b < 0.0);
+        assert!(res[0][1].p.close_enough(point(9.0, 0.0), 1e-10));
+    }
+
+    #[test]
+    fn test_open_arcline() {
+        let arcs = vec![
+            arcseg(point(0.0, 0.0), point(10.0, 0.0)),
+            arcseg(point(10.0, 0.0), point(10.0, 10.0)),
+        ];
+        let mut cfg = OffsetCfg::default();
+        let res = offset_open_arcline(&arcs, 2.0, &mut cfg);
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].len(), 3);
+        let res = try_offset_open_arcline(&Vec::new(), 2.0, &mut cfg);
+        assert!(matches!(res, Err(OffsetError::InvalidInput(_))));
+    }
+}
@@ -1,113 +1,235 @@
 #![allow(dead_code)]
 
+use std::collections::HashSet;
+
 use togo::prelude::*;
 use aabb::HilbertRTree;
 
-use crate::offsetraw::OffsetRaw;
-
-// Prune arcs that are close to any of the arcs in the polyline.
-const PRUNE_EPSILON: f64 = 1e-8;
+use crate::{
+    area::polyline_contains_point, budget::Budget, error::OffsetError,
+    offset_connect_raw::JoinCorner, offsetraw::OffsetRaw, tolerances::Tolerances,
+};
 
 // Set to true to use brute-force algorithm (for testing/comparison)
 const USE_BRUTE_FORCE: bool = false;
 
+// Valid input arcs of the prune stage, with the spatial index of their bounding boxes.
+// The boxes are not expanded by the offset distance, the queries are,
+// so the offsets of the same input at many distances share the index.
+pub struct PolyArcs {
+    arcs: Vec<Arc>,
+    index: HilbertRTree,
+    ends: Vec<FlatEnd>,
+}
+
+// Flat end of an open path, the buffer with butt or square caps (see offset_buffer).
+// The terminal segment covers the offsets past its end point only up to ext
+// in the direction dir, and within the offset distance across it.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct FlatEnd {
+    pub id: usize,
+    pub p: Point,
+    pub dir: Point,
+    pub ext: f64,
+}
+
+impl PolyArcs {
+    pub fn new(polyraws: &[Vec<OffsetRaw>], eps: f64) -> Self {
+        let arcs: Vec<Arc> = polyraws
+            .iter()
+            .flatten()
+            .map(|offset_raw| offset_raw.arc)
+            .filter(|arc| arc.is_valid(eps))
+            .collect();
+        let mut index = HilbertRTree::with_capacity(arcs.len());
+        for arc in arcs.iter() {
+            let (min_x, max_x, min_y, max_y) = arc_bounds(arc);
+            index.add(min_x, min_y, max_x, max_y);
+        }
+        index.build();
+        PolyArcs {
+            arcs,
+            index,
+            ends: Vec::new(),
+        }
+    }
+
+    pub(crate) fn with_flat_ends(mut self, ends: Vec<FlatEnd>) -> Self {
+        self.ends = ends;
+        self
+    }
+
+    // Whether the offset is closer than off to the input arc p when the offset is past
+    // a flat end of p, None when the distance to p decides.
+    // Offsets are split at the caps, so their middle point is on the same side of the end.
+    fn flat_end_covers(&self, p: &Arc, offset: &Arc, off: f64, eps: f64) -> Option<bool> {
+        if self.ends.is_empty() {
+            return None;
+        }
+        let mid = middle_point(offset);
+        let (_, closest) = dist_point_element(p, mid);
+        let end = self
+            .ends
+            .iter()
+            .find(|end| end.id == p.id && end.p.close_enough(closest, eps))?;
+        let v = mid - end.p;
+        let along = v.dot(end.dir);
+        if along <= eps {
+            return None;
+        }
+        Some(along < end.ext - eps && end.dir.perp(v).abs() < off - eps)
+    }
+
+    // True when the offset is closer than off - eps to the input arc p
+    fn covers(&self, p: &Arc, offset: &Arc, off: f64, eps: f64) -> bool {
+        match self.flat_end_covers(p, offset, off, eps) {
+            Some(covered) => covered,
+            None => distance_element_element(p, offset) < off - eps,
+        }
+    }
+
+    // Distance from the point to the closest arc, and the closest point on it
+    pub fn closest_point(&self, p: Point) -> Option<(f64, Point)> {
+        self.arcs
+            .iter()
+            .map(|arc| dist_point_element(arc, p))
+            .min_by(|d0, d1| d0.0.total_cmp(&d1.0))
+    }
+
+    // True when the arc is closer than dist to any of the arcs
+    pub fn closer_than(&self, arc: &Arc, dist: f64) -> bool {
+        let mut nearby_indices = Vec::new();
+        self.query_near(arc, dist, &mut nearby_indices);
+        nearby_indices
+            .iter()
+            .any(|&idx| distance_element_element(&self.arcs[idx], arc) < dist)
+    }
+
+    // Indices of the arcs with bounding boxes within dist of the bounding box of the arc
+    fn query_near(&self, arc: &Arc, dist: f64, res: &mut Vec<usize>) {
+        let (min_x, max_x, min_y, max_y) = arc_bounds_expanded(arc, dist);
+        res.clear();
+        self.index.query_intersecting(min_x, min_y, max_x, max_y, res);
+    }
+}
+
+// Prune arcs that are close to any of the arcs in the polyline.
+// The budget is checked for every offset.
 pub fn offset_prune_invalid(
-    polyraws: &Vec<Vec<OffsetRaw>>,
+    polyarcs: &PolyArcs,
     offsets: &mut Vec<Arc>,
     off: f64,
-) -> Vec<Arc> {
+    budget: &Budget,
+    tol: &Tolerances,
+) -> Result<Vec<Arc>, OffsetError> {
+    // Offsets on the left side (negative) are pruned the same way
+    let off = off.abs();
     if USE_BRUTE_FORCE {
-        offset_prune_invalid_brute_force(polyraws, offsets, off)
+        offset_prune_invalid_brute_force(polyarcs, offsets, off, budget, tol.prune)
     } else {
-        offset_prune_invalid_spatial(polyraws, offsets, off)
+        offset_prune_invalid_spatial(polyarcs, offsets, off, budget, tol.prune)
     }
 }
 
 fn offset_prune_invalid_spatial(
-    polyraws: &Vec<Vec<OffsetRaw>>,
+    polyarcs: &PolyArcs,
     offsets: &mut Vec<Arc>,
     off: f64,
-) -> Vec<Arc> {
+    budget: &Budget,
+    eps: f64,
+) -> Result<Vec<Arc>, OffsetError> {
     let mut valid = Vec::new();
-    let polyarcs: Vec<Arc> = polyraws
-        .iter()
-        .flatten()
-        .map(|offset_raw| offset_raw.arc.clone())
-        .filter(|arc| arc.is_valid(PRUNE_EPSILON))
-        .collect();
-
-    // Build spatial index containing only polyarcs (with expansion for search radius)
-    let mut spatial_index = HilbertRTree::with_capacity(polyarcs.len());
-    
-    // Add polyarcs to index with offset + epsilon expansion (done once)
-    let search_radius = off + PRUNE_EPSILON;
-    for arc in polyarcs.iter() {
-        let (min_x, max_x, min_y, max_y) = arc_bounds_expanded(arc, search_radius);
-        spatial_index.add(min_x, min_y, max_x, max_y);
-    }
-    
-    spatial_index.build();
-
-    while offsets.len() > 0 {
-        let offset = offsets.pop().unwrap();
+    let search_radius = off + eps;
+    let mut nearby_indices = Vec::new();
+    while let Some(offset) = offsets.pop() {
+        budget.check()?;
         valid.push(offset.clone());
 
         // Query nearby polyarcs using spatial index
-        let (offset_min_x, offset_max_x, offset_min_y, offset_max_y) =
-            arc_bounds(&offset);
-        let mut nearby_indices = Vec::new();
-        spatial_index.query_intersecting(
-            offset_min_x,
-            offset_min_y,
-            offset_max_x,
-            offset_max_y,
-            &mut nearby_indices,
-        );
+        polyarcs.query_near(&offset, search_radius, &mut nearby_indices);
 
         // Check only nearby polyarcs for actual distance
-        for idx in nearby_indices {
-            let p = &polyarcs[idx];
+        for &idx in nearby_indices.iter() {
+            let p = &polyarcs.arcs[idx];
             if p.id == offset.id {
                 continue; // skip self offsets
             }
-            let dist = distance_element_element(p, &offset);
-            if dist < off - PRUNE_EPSILON {
+            if polyarcs.covers(p, &offset, off, eps) {
                 valid.pop();
                 break;
             }
         }
     }
-    valid
+    Ok(valid)
 }
 
 fn offset_prune_invalid_brute_force(
-    polyraws: &Vec<Vec<OffsetRaw>>,
+    polyarcs: &PolyArcs,
     offsets: &mut Vec<Arc>,
     off: f64,
-) -> Vec<Arc> {
+    budget: &Budget,
+    eps: f64,
+) -> Result<Vec<Arc>, OffsetError> {
     let mut valid = Vec::new();
-    let polyarcs: Vec<Arc> = polyraws
-        .iter()
-        .flatten()
-        .map(|offset_raw| offset_raw.arc.clone())
-        .filter(|arc| arc.is_valid(PRUNE_EPSILON))
-        .collect();
-
-    while offsets.len() > 0 {
-        let offset = offsets.pop().unwrap();
+    while let Some(offset) = offsets.pop() {
+        budget.check()?;
         valid.push(offset.clone());
-        for p in polyarcs.iter() {
+        for p in polyarcs.arcs.iter() {
             if p.id == offset.id {
                 continue; // skip self offsets
             }
-            let dist = distance_element_element(&p, &offset);
-            if dist < off - PRUNE_EPSILON {
+            if polyarcs.covers(p, &offset, off, eps) {
                 valid.pop();
                 break;
             }
         }
     }
-    valid
+    Ok(valid)
+}
+
+#[doc(hidden)]
+// Prunes offsets closer to any polyline segment than the offset distance of that segment,
+// offs has the same layout as polyraws. The budget is checked for every offset.
+pub fn offset_prune_invalid_variable(
+    polyraws: &[Vec<OffsetRaw>],
+    offsets: &mut Vec<Arc>,
+    offs: &[Vec<f64>],
+    budget: &Budget,
+    tol: &Tolerances,
+) -> Result<Vec<Arc>, OffsetError> {
+    let eps = tol.prune;
+    let (polyarcs, polyoffs): (Vec<Arc>, Vec<f64>) = polyraws
+        .iter()
+        .flatten()
+        .zip(offs.iter().flatten())
+        .map(|(offset_raw, off)| (offset_raw.arc, off.abs()))
+        .filter(|(arc, _)| arc.is_valid(eps))
+        .unzip();
+
+    let mut spatial_index = HilbertRTree::with_capacity(polyarcs.len());
+    for (arc, off) in polyarcs.iter().zip(polyoffs.iter()) {
+        let (min_x, max_x, min_y, max_y) = arc_bounds_expanded(arc, off + eps);
+        spatial_index.add(min_x, min_y, max_x, max_y);
+    }
+    spatial_index.build();
+
+    let mut valid = Vec::new();
+    let mut nearby_indices = Vec::new();
+    while let Some(offset) = offsets.pop() {
+        budget.check()?;
+        let (min_x, max_x, min_y, max_y) = arc_bounds(&offset);
+        nearby_indices.clear();
+        spatial_index.query_intersecting(min_x, min_y, max_x, max_y, &mut nearby_indices);
+        let close_to_arc = nearby_indices.iter().any(|&idx| {
+            let p = &polyarcs[idx];
+            // skip self offsets
+            p.id != offset.id && distance_element_element(p, &offset) < polyoffs[idx] - eps
+        });
+        if !close_to_arc {
+            valid.push(offset);
+        }
+    }
+    Ok(valid)
 }
 
 /// Get bounding box of an arc
@@ -139,7 +261,197 @@ fn arc_bounds_expanded(arc: &Arc, expansion: f64) -> (f64, f64, f64, f64) {
     )
 }
 
-fn distance_element_element(seg0: &Arc, seg1: &Arc) -> f64 {
+/// Prunes the offsets of polylines with miter or bevel joins.
+///
+/// Offsets close to the polyline are pruned as in `offset_prune_invalid`, except:
+/// - offsets inside of a miter or extend polygon (corner point, offset ends and the point
+///   where the extended offsets meet) are pruned
+/// - behind a bevel line, the distance to the corner point is not checked
+/// - the round join arcs of bevel joins are always pruned
+///
+/// The offsets are split at all join lines and arcs, so every offset is either inside
+/// or outside of a corner polygon or a bevel, and it is tested in its middle point.
+/// The budget is checked for every offset.
+pub fn offset_prune_invalid_corners(
+    polyarcs: &PolyArcs,
+    offsets: &mut Vec<Arc>,
+    off: f64,
+    corners: &[JoinCorner],
+    budget: &Budget,
+    tol: &Tolerances,
+) -> Result<Vec<Arc>, OffsetError> {
+    let off = off.abs();
+    let eps = tol.prune;
+    let search_radius = off + eps;
+
+    // Corner polygons of miter and extend joins, and round arcs of bevel joins
+    let mut corners_index = HilbertRTree::with_capacity(corners.len());
+    for corner in corners.iter() {
+        let (min_x, max_x, min_y, max_y) = match &corner.fill {
+            Some(fill) => fill_bounds(fill),
+            None => arc_bounds(&corner.round),
+        };
+        corners_index.add(min_x, min_y, max_x, max_y);
+    }
+    corners_index.build();
+    let bevel_arcs: HashSet<usize> = corners
+        .iter()
+        .filter(|corner| corner.fill.is_none())
+        .map(|corner| corner.round.id)
+        .collect();
+
+    let mut valid = Vec::new();
+    let mut nearby_indices = Vec::new();
+    let mut behind = Vec::new();
+    while let Some(offset) = offsets.pop() {
+        budget.check()?;
+        if offset.is_arc() && bevel_arcs.contains(&offset.id) {
+            continue;
+        }
+        let mid = middle_point(&offset);
+
+        nearby_indices.clear();
+        corners_index.query_intersecting(mid.x, mid.y, mid.x, mid.y, &mut nearby_indices);
+        let mut in_fill = false;
+        behind.clear();
+        for &idx in nearby_indices.iter() {
+            let corner = &corners[idx];
+            match &corner.fill {
+                Some(fill) => in_fill |= inside_fill(fill, mid, eps),
+                None if behind_bevel(corner, mid, off, eps) => behind.push(corner.orig),
+                None => {}
+            }
+        }
+        if in_fill {
+            continue;
+        }
+
+        polyarcs.query_near(&offset, search_radius, &mut nearby_indices);
+        let close_to_arc = nearby_indices.iter().any(|&idx| {
+            let p = &polyarcs.arcs[idx];
+            if p.id == offset.id {
+                return false; // skip self offsets
+            }
+            if let Some(covered) = polyarcs.flat_end_covers(p, &offset, off, eps) {
+                return covered;
+            }
+            // Behind the bevel, the arcs meeting at the corner are not checked
+            // for the distance to the corner point
+            let at_corner = |q: Point| behind.iter().any(|c| c.close_enough(q, eps));
+            if at_corner(p.a) || at_corner(p.b) {
+                let (dist, closest) = dist_point_element(p, mid);
+                return dist < off - eps && !at_corner(closest);
+            }
+            distance_element_element(p, &offset) < off - eps
+        });
+        if close_to_arc {
+            continue;
+        }
+        valid.push(offset);
+    }
+    Ok(remove_duplicates(valid, tol.vertex))
+}
+
+// Point between the bevel line and the round join, or on the bevel line
+fn behind_bevel(corner: &JoinCorner, p: Point, off: f64, eps: f64) -> bool {
+    if (p - corner.orig).norm() > off + eps {
+        return false;
+    }
+    let a = corner.round.a;
+    let line = corner.round.b - a;
+    let len = line.norm();
+    if len < eps {
+        return false;
+    }
+    let side = |q: Point| line.perp(q - a) / len;
+    let round_side = side(middle_point(&corner.round));
+    side(p) * round_side.signum() > -eps
+}
+
+#[doc(hidden)]
+// Removes arcs that are the same as an other arc, they come from coincident offsets
+// (both sides of a path, or miter joins along an other offset).
+// Arcs with all points closer than the tolerance are the same.
+pub fn remove_duplicates(mut arcs: Vec<Arc>, tolerance: f64) -> Vec<Arc> {
+    let key = |arc: &Arc| arc.a.x.min(arc.b.x);
+    arcs.sort_by(|arc0, arc1| key(arc0).total_cmp(&key(arc1)));
+    let mut res: Vec<Arc> = Vec::with_capacity(arcs.len());
+    for arc in arcs {
+        let duplicate = res
+            .iter()
+            .rev()
+            .take_while(|prev| key(&arc) - key(prev) <= tolerance)
+            .any(|prev| is_same_arc(prev, &arc, tolerance));
+        if !duplicate {
+            res.push(arc);
+        }
+    }
+    res
+}
+
+fn is_same_arc(arc0: &Arc, arc1: &Arc, tolerance: f64) -> bool {
+    let close = |p0: Point, p1: Point| p0.close_enough(p1, tolerance);
+    if arc0.is_seg() && arc1.is_seg() {
+        (close(arc0.a, arc1.a) && close(arc0.b, arc1.b))
+            || (close(arc0.a, arc1.b) && close(arc0.b, arc1.a))
+    } else if arc0.is_arc() && arc1.is_arc() {
+        // Arcs are CCW, the end points are in the same order
+        close(arc0.a, arc1.a) && close(arc0.b, arc1.b) && close(arc0.c, arc1.c)
+    } else {
+        false
+    }
+}
+
+// Bounds of the corner polygon, arcs are bounded by their circle
+fn fill_bounds(fill: &Polyline) -> (f64, f64, f64, f64) {
+    let mut bounds = (f64::INFINITY, f64::NEG_INFINITY, f64::INFINITY, f64::NEG_INFINITY);
+    for (i, v) in fill.iter().enumerate() {
+        let next = fill[(i + 1) % fill.len()].p;
+        let (min_x, max_x, min_y, max_y) = arc_bounds(&arc_from_bulge(v.p, next, v.b));
+        bounds.0 = bounds.0.min(min_x);
+        bounds.1 = bounds.1.max(max_x);
+        bounds.2 = bounds.2.min(min_y);
+        bounds.3 = bounds.3.max(max_y);
+    }
+    bounds
+}
+
+// Middle point of the segment, or of the arc (arcs are CCW from a to b)
+fn middle_point(arc: &Arc) -> Point {
+    if arc.is_seg() {
+        return (arc.a + arc.b) * 0.5;
+    }
+    let start = (arc.a.y - arc.c.y).atan2(arc.a.x - arc.c.x);
+    let mut end = (arc.b.y - arc.c.y).atan2(arc.b.x - arc.c.x);
+    if end < start {
+        end += std::f64::consts::TAU;
+    }
+    let angle = (start + end) * 0.5;
+    arc.c + point(angle.cos(), angle.sin()) * arc.r
+}
+
+// Distance from the point to the segment or arc, and the closest point
+pub(crate) fn dist_point_element(element: &Arc, p: Point) -> (f64, Point) {
+    if element.is_seg() {
+        dist_point_segment(&p, &segment(element.a, element.b))
+    } else {
+        match dist_point_arc(&p, element) {
+            DistPointArcConfig::OnePoint(dist, closest) => (dist, closest),
+            DistPointArcConfig::Equidistant(dist, closest) => (dist, closest),
+        }
+    }
+}
+
+// Point strictly inside of the corner polygon, not on its boundary
+fn inside_fill(fill: &Polyline, p: Point, eps: f64) -> bool {
+    let on_boundary = fill.iter().enumerate().any(|(i, v)| {
+        let next = fill[(i + 1) % fill.len()].p;
+        v.p != next && dist_point_element(&arc_from_bulge(v.p, next, v.b), p).0 < eps
+    });
+    !on_boundary && polyline_contains_point(fill, p)
+}
+
+pub(crate) fn distance_element_element(seg0: &Arc, seg1: &Arc) -> f64 {
     let mut dist = std::f64::INFINITY;
     if seg0.is_seg() && seg1.is_seg() {
         dist = dist_segment_segment(&segment(seg0.a, seg0.b), &segment(seg1.a, seg1.b));
@@ -1,13 +1,16 @@
 #![allow(dead_code)]
 
 use togo::prelude::*;
-use crate::graph::{
-    merge_ends::merge_close_endpoints_default,
-    find_cycles::find_non_intersecting_cycles
+use crate::{
+    graph::{
+        find_cycles::find_non_intersecting_cycles_with_budget,
+        merge_ends::merge_close_endpoints,
+    },
+    budget::Budget,
+    error::OffsetError,
+    tolerances::Tolerances,
 };
 
-const EPS_CONNECT: f64 = 1e-7;
-
 /// Reconnects offset segments by merging close endpoints and finding non-intersecting cycles.
 /// 
 /// This function takes a collection of offset arcs and processes them to:
@@ -17,20 +20,27 @@ const EPS_CONNECT: f64 = 1e-7;
 /// 
 /// # Arguments
 /// * `arcs` - Input arcline containing offset arcs that may have disconnected endpoints
+/// * `budget` - Checked while finding the cycles
+/// * `tol` - Tolerances, `tol.merge` for merging the endpoints and `tol.vertex` for the cycles
 /// 
 /// # Returns
-/// Vector of Arclines, each representing a separate non-intersecting cycle
-pub fn offset_reconnect_arcs(arcs: Arcline) -> Vec<Arcline> {
+/// Vector of Arclines, each representing a separate non-intersecting cycle,
+/// or the error of the budget check
+pub fn offset_reconnect_arcs(
+    arcs: Arcline,
+    budget: &Budget,
+    tol: &Tolerances,
+) -> Result<Vec<Arcline>, OffsetError> {
     // Use the input arcs directly, no need to clone since we take ownership
     let mut arc_vec: Vec<Arc> = arcs;
     
     // Step 1: Merge close endpoints to connect arcs that should be connected
     // This fixes numerical precision issues from the offset algorithm
-    merge_close_endpoints_default(&mut arc_vec);
+    merge_close_endpoints(&mut arc_vec, tol.merge);
     
     // Step 2: Find non-intersecting cycles using our tangent-based algorithm
     // This separates the arcs into geometrically non-intersecting components
-    let cycles = find_non_intersecting_cycles(&arc_vec);
+    let cycles = find_non_intersecting_cycles_with_budget(&arc_vec, tol.vertex, budget)?;
     
     // Step 3: Each cycle is already a Vec<Arc> which is an Arcline
     let mut result = Vec::new();
@@ -40,7 +50,7 @@ pub fn offset_reconnect_arcs(arcs: Arcline) -> Vec<Arcline> {
         }
     }
     
-    result
+    Ok(result)
 }
 
 #[cfg(test)]
@@ -58,7 +68,7 @@ mod integration_tests {
         ];
 
         // Run the reconnection algorithm
-        let result = offset_reconnect_arcs(arcs);
+        let result = offset_reconnect_arcs(arcs, &Budget::default(), &Tolerances::default()).unwrap();
 
         // Should find 1 cycle (the merged triangle)
         assert_eq!(result.len(), 1, "Should find exactly one cycle");
@@ -80,7 +90,7 @@ mod integration_tests {
             arcseg(Point::new(0.0, 5.0), Point::new(0.0, 0.0)),
         ];
 
-        let result = offset_reconnect_arcs(arcs);
+        let result = offset_reconnect_arcs(arcs, &Budget::default(), &Tolerances::default()).unwrap();
 
         // Should find exactly 1 cycle (the square)
         assert_eq!(result.len(), 1, "Should find exactly one cycle");
@@ -110,7 +120,7 @@ mod integration_tests {
             arc(Point::new(0.0, -5.0 + 1e-9), Point::new(5.0, 0.0), center, radius),
         ];
 
-        let result = offset_reconnect_arcs(arcs);
+        let result = offset_reconnect_arcs(arcs, &Budget::default(), &Tolerances::default()).unwrap();
 
         // Should find exactly 1 cycle (the circle)
         assert_eq!(result.len(), 1, "Should find exactly one cycle");
@@ -142,7 +152,7 @@ mod integration_tests {
             arcseg(Point::new(-1e-9, 9.0), Point::new(0.0, 0.0)), // small gap to test merging
         ];
 
-        let result = offset_reconnect_arcs(arcs);
+        let result = offset_reconnect_arcs(arcs, &Budget::default(), &Tolerances::default()).unwrap();
 
         // Should find exactly 1 cycle (the mixed shape)
         assert_eq!(result.len(), 1, "Should find exactly one cycle");
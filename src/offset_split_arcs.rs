@@ -3,10 +3,9 @@
 
 use togo::prelude::*;
 
-use crate::offsetraw::OffsetRaw;
+use crate::{budget::Budget, error::OffsetError, offsetraw::OffsetRaw, tolerances::Tolerances};
 
 static ZERO: f64 = 0.0;
-const EPSILON: f64 = 1e-10;
 
 /// Get bounding circle for an arc: returns (center, radius_squared)
 /// Storing radius squared to avoid sqrt in distance calculations
@@ -25,19 +24,19 @@ fn bounding_circle_arc(arc: &Arc) -> (Point, f64) {
     }
 }
 
-/// Check if two bounding circles intersect or touch
+/// Check if two bounding circles intersect or come closer than eps
 /// Both r1_sq and r2_sq are radius squared to avoid sqrt
-fn circles_intersect(c1: Point, r1_sq: f64, c2: Point, r2_sq: f64) -> bool {
+fn circles_intersect(c1: Point, r1_sq: f64, c2: Point, r2_sq: f64, eps: f64) -> bool {
     let dx = c2.x - c1.x;
     let dy = c2.y - c1.y;
     let dist_sq = dx * dx + dy * dy;
     
     let r1 = r1_sq.sqrt();
     let r2 = r2_sq.sqrt();
-    let sum_r = r1 + r2;
+    let sum_r = r1 + r2 + eps;
     let sum_r_sq = sum_r * sum_r;
     
-    dist_sq <= sum_r_sq + EPSILON
+    dist_sq <= sum_r_sq
 }
 
 /// Get AABB (axis-aligned bounding box) for a segment: (min_x, max_x, min_y, max_y)
@@ -62,85 +61,85 @@ fn aabb_overlap(min_x0: f64, max_x0: f64, min_y0: f64, max_y0: f64,
     !(max_x0 < min_x1 || max_x1 < min_x0 || max_y0 < min_y1 || max_y1 < min_y0)
 }
 
-pub fn offset_split_arcs(row: &Vec<Vec<OffsetRaw>>, connect: &Vec<Vec<Arc>>) -> Vec<Arc> {
+/// Default limit of split steps in `offset_split_arcs`.
+pub const SPLIT_STEPS_LIMIT: usize = 100000;
+
+/// Splits the offsets and offset connections at their intersection points.
+///
+/// Every successful split of two parts counts as one step. When more than
+/// `budget.split_steps` splits are needed, `OffsetError::SplitIterationLimit` is returned.
+/// The budget is checked for every part, see `Budget::check`.
+/// Parts shorter than `tol.collapsed` are dropped.
+pub fn offset_split_arcs(
+    row: &Vec<Vec<OffsetRaw>>,
+    connect: &Vec<Vec<Arc>>,
+    budget: &Budget,
+    tol: &Tolerances,
+) -> Result<Vec<Arc>, OffsetError> {
     // Merge offsets and offset connections, filter singular arcs
     let mut parts: Vec<Arc> = row
         .iter()
         .flatten()
         .map(|offset_raw| offset_raw.arc.clone())
         .chain(connect.iter().flatten().cloned())
-        .filter(|arc| arc.is_valid(EPSILON))
+        .filter(|arc| arc.is_valid(tol.collapsed))
         .collect();
 
     let mut parts_final = Vec::new();
-    //let mut parts_final = Vec::new();
-    let steps = 100000; // TODO: make this configurable
-    //let mut splits_count = 0;
+    let mut splits_count = 0;
 
-    let mut kk = 0;
-    for k in 0..steps {
+    while let Some(part0) = parts.pop() {
+        budget.check()?;
+        if parts.is_empty() {
+            // No more parts to check against
+            parts_final.push(part0);
+            break;
+        }
         let mut j_current = usize::MAX;
-
-        while parts.len() > 0 {
-            let part0 = parts.pop().unwrap();
-            if parts.len() == 0 {
-                // No more parts to check against
-                parts_final.push(part0);
-                break;
-            }
-            for j in (0..parts.len()).rev() {
-                j_current = usize::MAX;
-                if part0.id == parts[j].id {
-                    // Skip parts comming from the same original arc
-                    continue;
-                }
-                // if part0.id == parts[j].id || part0.id == parts[j].id + ID_PADDING {
-                //     // Skip parts comming from the same original arc
-                //     continue;
-                // }
-
-                let part1 = parts[j].clone();
-
-                let (parts_new, _) = if part0.is_seg() && part1.is_seg() {
-                    split_line_line(&part0, &part1)
-                } else if part0.is_arc() && part1.is_arc() {
-                    split_arc_arc(&part0, &part1)
-                } else if part0.is_seg() && part1.is_arc() {
-                    split_segment_arc(&part0, &part1)
-                } else if part0.is_arc() && part1.is_seg() {
-                    split_segment_arc(&part1, &part0)
-                } else {
-                    (Vec::new(), 0)
-                };
-
-                if !parts_new.is_empty() {
-                    j_current = j;
-                    parts.extend(parts_new);
-                    break;
-                }
+        for j in (0..parts.len()).rev() {
+            if part0.id == parts[j].id {
+                // Skip parts comming from the same original arc
+                continue;
             }
-            // this part parts[i] does not intersect with any other part
-            if j_current == usize::MAX {
-                parts_final.push(part0);
+
+            let part1 = parts[j].clone();
+
+            let (parts_new, _) = if part0.is_seg() && part1.is_seg() {
+                split_line_line(&part0, &part1, tol.collapsed)
+            } else if part0.is_arc() && part1.is_arc() {
+                split_arc_arc(&part0, &part1, tol.collapsed)
+            } else if part0.is_seg() && part1.is_arc() {
+                split_segment_arc(&part0, &part1, tol.collapsed)
+            } else if part0.is_arc() && part1.is_seg() {
+                split_segment_arc(&part1, &part0, tol.collapsed)
             } else {
-                // remove the part1 from the parts
-                _ = parts.remove(j_current);
+                (Vec::new(), 0)
+            };
+
+            if !parts_new.is_empty() {
+                j_current = j;
+                parts.extend(parts_new.into_iter().filter(|arc| arc.is_valid(tol.collapsed)));
+                break;
             }
         }
-
-        if parts.is_empty() {
-            // No more splits, we are done
-            kk = k;
-            break;
+        // this part parts[i] does not intersect with any other part
+        if j_current == usize::MAX {
+            parts_final.push(part0);
+        } else {
+            // remove the part1 from the parts
+            _ = parts.remove(j_current);
+            splits_count += 1;
+            if splits_count > budget.split_steps {
+                return Err(OffsetError::SplitIterationLimit(budget.split_steps));
+            }
         }
     }
 
-    let _kkk = kk;
-    parts_final
+    Ok(parts_final)
 }
 
 // Split two lines at intersection point
-pub fn split_line_line(arc0: &Arc, arc1: &Arc) -> (Vec<Arc>, usize) {
+pub fn split_line_line(arc0: &Arc, arc1: &Arc, eps: f64) -> (Vec<Arc>, usize) {
     let mut res = Vec::new();
     
     // Quick AABB check before expensive segment intersection
@@ -172,10 +171,10 @@ pub fn split_line_line(arc0: &Arc, arc1: &Arc) -> (Vec<Arc>, usize) {
             line01.id(arc0.id);
             line10.id(arc1.id);
             line11.id(arc1.id);
-            check_and_push(&mut res, &line00);
-            check_and_push(&mut res, &line01);
-            check_and_push(&mut res, &line10);
-            check_and_push(&mut res, &line11);
+            check_and_push(&mut res, &line00, eps);
+            check_and_push(&mut res, &line01, eps);
+            check_and_push(&mut res, &line10, eps);
+            check_and_push(&mut res, &line11, eps);
             (res, 4)
         }
         SegmentSegmentConfig::TwoPoints(p0, p1, p2, p3) => {
@@ -186,15 +185,15 @@ pub fn split_line_line(arc0: &Arc, arc1: &Arc) -> (Vec<Arc>, usize) {
             line00.id(arc0.id);
             line01.id(arc0.id);
             line10.id(arc1.id);
-            check_and_push(&mut res, &line00);
-            check_and_push(&mut res, &line01);
-            check_and_push(&mut res, &line10);
+            check_and_push(&mut res, &line00, eps);
+            check_and_push(&mut res, &line01, eps);
+            check_and_push(&mut res, &line10, eps);
             (res, 3)
         }
     }
 }
 
-pub fn split_arc_arc(arc0: &Arc, arc1: &Arc) -> (Vec<Arc>, usize) {
+pub fn split_arc_arc(arc0: &Arc, arc1: &Arc, eps: f64) -> (Vec<Arc>, usize) {
     let mut res = Vec::new();
     
     // Quick AABB check before expensive arc intersection
@@ -227,10 +226,10 @@ pub fn split_arc_arc(arc0: &Arc, arc1: &Arc) -> (Vec<Arc>, usize) {
             arc01.id(arc0.id);
             arc10.id(arc1.id);
             arc11.id(arc1.id);
-            check_and_push(&mut res, &arc00);
-            check_and_push(&mut res, &arc01);
-            check_and_push(&mut res, &arc10);
-            check_and_push(&mut res, &arc11);
+            check_and_push(&mut res, &arc00, eps);
+            check_and_push(&mut res, &arc01, eps);
+            check_and_push(&mut res, &arc10, eps);
+            check_and_push(&mut res, &arc11, eps);
             (res, 4)
         }
         ArcArcConfig::NonCocircularTwoPoints(point0, point1) => {
@@ -256,12 +255,12 @@ pub fn split_arc_arc(arc0: &Arc, arc1: &Arc) -> (Vec<Arc>, usize) {
             arc11.id(arc1.id);
             arc12.id(arc1.id);
 
-            check_and_push(&mut res, &arc00);
-            check_and_push(&mut res, &arc01);
-            check_and_push(&mut res, &arc02);
-            check_and_push(&mut res, &arc10);
-            check_and_push(&mut res, &arc11);
-            check_and_push(&mut res, &arc12);
+            check_and_push(&mut res, &arc00, eps);
+            check_and_push(&mut res, &arc01, eps);
+            check_and_push(&mut res, &arc02, eps);
+            check_and_push(&mut res, &arc10, eps);
+            check_and_push(&mut res, &arc11, eps);
+            check_and_push(&mut res, &arc12, eps);
             (res, 6)
         }
         ArcArcConfig::CocircularOnePointOneArc0(_, _) => {
@@ -271,9 +270,9 @@ pub fn split_arc_arc(arc0: &Arc, arc1: &Arc) -> (Vec<Arc>, usize) {
             arc00.id(arc0.id);
             arc01.id(arc1.id);
             arc02.id(arc0.id);
-            check_and_push(&mut res, &arc00);
-            check_and_push(&mut res, &arc01);
-            check_and_push(&mut res, &arc02);
+            check_and_push(&mut res, &arc00, eps);
+            check_and_push(&mut res, &arc01, eps);
+            check_and_push(&mut res, &arc02, eps);
             (res, 3)
         }
         ArcArcConfig::CocircularOnePointOneArc1(_, _) => {
@@ -283,15 +282,15 @@ pub fn split_arc_arc(arc0: &Arc, arc1: &Arc) -> (Vec<Arc>, usize) {
             arc00.id(arc1.id);
             arc01.id(arc0.id);
             arc02.id(arc1.id);
-            check_and_push(&mut res, &arc00);
-            check_and_push(&mut res, &arc01);
-            check_and_push(&mut res, &arc02);
+            check_and_push(&mut res, &arc00, eps);
+            check_and_push(&mut res, &arc01, eps);
+            check_and_push(&mut res, &arc02, eps);
             (res, 3)
         }
         ArcArcConfig::CocircularOneArc0(_) => {
             let mut arc00 = arc(arc0.a, arc0.b, arc0.c, arc0.r);
             arc00.id(arc0.id);
-            check_and_push(&mut res, &arc00);
+            check_and_push(&mut res, &arc00, eps);
             (res, 1)
         }
         ArcArcConfig::CocircularOneArc1(_) => {
@@ -302,9 +301,9 @@ pub fn split_arc_arc(arc0: &Arc, arc1: &Arc) -> (Vec<Arc>, usize) {
             arc00.id(arc1.id);
             arc01.id(arc0.id);
             arc02.id(arc1.id);
-            check_and_push(&mut res, &arc00);
-            check_and_push(&mut res, &arc01);
-            check_and_push(&mut res, &arc02);
+            check_and_push(&mut res, &arc00, eps);
+            check_and_push(&mut res, &arc01, eps);
+            check_and_push(&mut res, &arc02, eps);
             (res, 3)
         }
         ArcArcConfig::CocircularOneArc2(_) => {
@@ -315,9 +314,9 @@ pub fn split_arc_arc(arc0: &Arc, arc1: &Arc) -> (Vec<Arc>, usize) {
             arc00.id(arc1.id);
             arc01.id(arc0.id);
             arc02.id(arc1.id);
-            check_and_push(&mut res, &arc00);
-            check_and_push(&mut res, &arc01);
-            check_and_push(&mut res, &arc02);
+            check_and_push(&mut res, &arc00, eps);
+            check_and_push(&mut res, &arc01, eps);
+            check_and_push(&mut res, &arc02, eps);
             (res, 3)
         }
         ArcArcConfig::CocircularOneArc3(_) => {
@@ -328,9 +327,9 @@ pub fn split_arc_arc(arc0: &Arc, arc1: &Arc) -> (Vec<Arc>, usize) {
             arc00.id(arc0.id);
             arc01.id(arc1.id);
             arc02.id(arc0.id);
-            check_and_push(&mut res, &arc00);
-            check_and_push(&mut res, &arc01);
-            check_and_push(&mut res, &arc02);
+            check_and_push(&mut res, &arc00, eps);
+            check_and_push(&mut res, &arc01, eps);
+            check_and_push(&mut res, &arc02, eps);
             (res, 3)
         }
         ArcArcConfig::CocircularOneArc4(_) => {
@@ -341,9 +340,9 @@ pub fn split_arc_arc(arc0: &Arc, arc1: &Arc) -> (Vec<Arc>, usize) {
             arc00.id(arc0.id);
             arc01.id(arc1.id);
             arc02.id(arc0.id);
-            check_and_push(&mut res, &arc00);
-            check_and_push(&mut res, &arc01);
-            check_and_push(&mut res, &arc02);
+            check_and_push(&mut res, &arc00, eps);
+            check_and_push(&mut res, &arc01, eps);
+            check_and_push(&mut res, &arc02, eps);
             (res, 3)
         }
         ArcArcConfig::CocircularTwoArcs(_, _) => {
@@ -353,15 +352,15 @@ pub fn split_arc_arc(arc0: &Arc, arc1: &Arc) -> (Vec<Arc>, usize) {
             let mut arc01 = arc(arc1.a, arc0.b, arc0.c, arc0.r);
             arc00.id(arc0.id);
             arc01.id(arc1.id);
-            check_and_push(&mut res, &arc00);
-            check_and_push(&mut res, &arc01);
+            check_and_push(&mut res, &arc00, eps);
+            check_and_push(&mut res, &arc01, eps);
             (res, 2)
         }
     }
 }
 
 // Split two lines at intersection point
-pub fn split_segment_arc(line0: &Arc, arc1: &Arc) -> (Vec<Arc>, usize) {
+pub fn split_segment_arc(line0: &Arc, arc1: &Arc, eps: f64) -> (Vec<Arc>, usize) {
     debug_assert!(line0.is_seg());
     debug_assert!(arc1.is_arc());
     let mut res = Vec::new();
@@ -394,10 +393,10 @@ pub fn split_segment_arc(line0: &Arc, arc1: &Arc) -> (Vec<Arc>, usize) {
             line01.id(line0.id);
             arc10.id(arc1.id);
             arc11.id(arc1.id);
-            check_and_push(&mut res, &line00);
-            check_and_push(&mut res, &line01);
-            check_and_push(&mut res, &arc10);
-            check_and_push(&mut res, &arc11);
+            check_and_push(&mut res, &line00, eps);
+            check_and_push(&mut res, &line01, eps);
+            check_and_push(&mut res, &arc10, eps);
+            check_and_push(&mut res, &arc11, eps);
             (res, 4)
         }
         SegmentArcConfig::TwoPoints(point0, point1, _, _) => {
@@ -418,20 +417,19 @@ pub fn split_segment_arc(line0: &Arc, arc1: &Arc) -> (Vec<Arc>, usize) {
             arc10.id(arc1.id);
             arc11.id(arc1.id);
             arc12.id(arc1.id);
-            check_and_push(&mut res, &line00);
-            check_and_push(&mut res, &line01);
-            check_and_push(&mut res, &line02);
-            check_and_push(&mut res, &arc10);
-            check_and_push(&mut res, &arc11);
-            check_and_push(&mut res, &arc12);
+            check_and_push(&mut res, &line00, eps);
+            check_and_push(&mut res, &line01, eps);
+            check_and_push(&mut res, &line02, eps);
+            check_and_push(&mut res, &arc10, eps);
+            check_and_push(&mut res, &arc11, eps);
+            check_and_push(&mut res, &arc12, eps);
             (res, 6)
         }
     }
 }
 
 // Check if the line-arc segments have 0.0 length
-fn check_and_push(res: &mut Vec<Arc>, seg: &Arc) {
-    let eps = 1e-10;
+fn check_and_push(res: &mut Vec<Arc>, seg: &Arc, eps: f64) {
     if seg.is_valid(eps) {
         res.push(seg.clone())
     }
@@ -445,6 +443,8 @@ mod test_offset_split_arcs {
     use togo::prelude::*;
     use super::*;
 
+    const EPSILON: f64 = crate::tolerances::EPS_COLLAPSED;
+
     fn show(arc0: &Arc, arc1: &Arc, arcs: &Vec<Arc>, svg: &mut SVG) {
         svg.arcsegment(&arc0, "grey");
         svg.arcsegment(&arc1, "grey");
@@ -461,7 +461,7 @@ mod test_offset_split_arcs {
         // let mut svg = svg(4.0, 6.0);
         let arc0 = arc(point(1.0, 1.0), point(0.0, 0.0), point(1.0, 0.0), 1.0);
         let arc1 = arc(point(1.0, 0.0), point(0.0, 1.0), point(0.0, 0.0), 1.0);
-        let (res, count) = split_arc_arc(&arc0, &arc1);
+        let (res, count) = split_arc_arc(&arc0, &arc1, EPSILON);
         //show(&arc0, &arc1, &res, &mut svg);
         assert_eq!(count, 4);
         let p = 0.8660254037844386; // cos(30 degrees)
@@ -481,7 +481,7 @@ mod test_offset_split_arcs {
         // let mut svg = svg(4.0, 6.0);
         let arc0 = arc(point(0.0, 0.0), point(1.0, 1.0), point(0.0, 1.0), 1.0);
         let arc1 = arc(point(0.0, 1.0), point(1.0, 0.0), point(1.0, 1.0), 1.0);
-        let (res, count) = split_arc_arc(&arc0, &arc1);
+        let (res, count) = split_arc_arc(&arc0, &arc1, EPSILON);
         //show(&arc0, &arc1, &res, &mut svg);
         assert_eq!(count, 4);
         let p = 1.0 - 0.8660254037844386;
@@ -501,17 +501,41 @@ mod test_offset_split_arcs {
         //let mut svg = svg(4.0, 6.0);
         let arc0 = arc(point(1.2, 2.2), point(2.2, 1.2), point(1.2, 1.2), 1.0);
         let arc1 = arc(point(-1.0, 0.0), point(0.0, 1.0), point(0.0, 0.0), 1.0);
-        let (_, count) = split_arc_arc(&arc0, &arc1);
+        let (_, count) = split_arc_arc(&arc0, &arc1, EPSILON);
         //show(&arc0, &arc1, &res, &mut svg);
         assert_eq!(count, 6);
     }
 
+    #[test]
+    fn test_split_steps_limit() {
+        let raw0 = OffsetRaw::new(arcseg(point(0.0, 0.0), point(10.0, 10.0)), point(0.0, 0.0), 0.0);
+        let raw1 = OffsetRaw::new(arcseg(point(0.0, 10.0), point(10.0, 0.0)), point(0.0, 0.0), 0.0);
+        let row = vec![vec![raw0, raw1]];
+        let tol = Tolerances::default();
+        let res = offset_split_arcs(&row, &Vec::new(), &Budget::default(), &tol).unwrap();
+        assert_eq!(res.len(), 4);
+        let budget = Budget { split_steps: 0, ..Budget::default() };
+        let res = offset_split_arcs(&row, &Vec::new(), &budget, &tol);
+        assert_eq!(res, Err(OffsetError::SplitIterationLimit(0)));
+    }
+
+    #[test]
+    fn test_split_cancelled() {
+        let raw0 = OffsetRaw::new(arcseg(point(0.0, 0.0), point(10.0, 10.0)), point(0.0, 0.0), 0.0);
+        let raw1 = OffsetRaw::new(arcseg(point(0.0, 10.0), point(10.0, 0.0)), point(0.0, 0.0), 0.0);
+        let row = vec![vec![raw0, raw1]];
+        let cancel = std::sync::atomic::AtomicBool::new(true);
+        let budget = Budget::default().with_cancel(&cancel);
+        let res = offset_split_arcs(&row, &Vec::new(), &budget, &Tolerances::default());
+        assert_eq!(res, Err(OffsetError::Cancelled));
+    }
+
     #[test]
     fn test_overlaping_lines() {
         let mut svg = svg(200.0, 100.0);
         let arc0 = arcseg(point(50.0, 50.0), point(150.0, 50.0));
         let arc1 = arcseg(point(100.0, 50.0), point(200.0, 50.0));
-        let (res, count) = split_line_line(&arc0, &arc1);
+        let (res, count) = split_line_line(&arc0, &arc1, EPSILON);
         show(&arc0, &arc1, &res, &mut svg);
         assert_eq!(count, 3);
     }
@@ -632,7 +656,7 @@ mod test_offset_split_arcs {
         let mut svg = svg(200.0, 300.0);
         let arc0 = arc(point(29.177446878757827, 250.0), point(-65.145657857171898, 211.46278163768008), point(15.0, 150.0), 101.0);
         let arc1 = arc(point(0.82255312124217461, 250.0), point(29.177446878757827, 250.0), point(15.0, 150.0), 101.0);
-        let (res, _) = split_arc_arc(&arc0, &arc1);
+        let (res, _) = split_arc_arc(&arc0, &arc1, EPSILON);
         show(&arc0, &arc1, &res, &mut svg);
         // assert_eq!(count, 4);
         // let p = 0.8660254037844386; // cos(30 degrees)
@@ -652,7 +676,7 @@ mod test_offset_split_arcs {
         let mut svg = svg(200.0, 300.0);
         let arc0 = arc(point(51.538461538461533, 246.30769230769232), point(-23.494939167562663, 105.0), point(100.0, 130.0), 126.0);
         let seg1 = arcseg(point(-25.599999999999994, -0.80000000000001137), point(-25.599999999999994, 150.80000000000001));
-        let (res, _) = split_segment_arc(&seg1, &arc0);
+        let (res, _) = split_segment_arc(&seg1, &arc0, EPSILON);
         show(&arc0, &seg1, &res, &mut svg);
         // assert_eq!(count, 4);
         // let p = 0.8660254037844386; // cos(30 degrees)
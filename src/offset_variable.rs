@@ -0,0 +1,245 @@
+//! Offsetting of closed Polylines with a separate offset distance for every segment.
+//!
+//! Used for example to leave different stock on different edges of a part.
+//! Where two segments with different distances meet, the offset follows
+//! an arc of the larger distance around the corner.
+
+use togo::prelude::*;
+
+use crate::{
+    error::OffsetError,
+    offset::{OffsetCfg, arcs_to_polylines, check_offset, check_polyline},
+    observer::polyline_arcs,
+    offset_arcs_raw::{offset_polyline_raw_variable, poly_to_raws, segment_offsets},
+    offset_connect_raw::offset_connect_raw_variable,
+    offset_prune_invalid::offset_prune_invalid_variable,
+    offset_reconnect_arcs::offset_reconnect_arcs,
+    offset_split_arcs::offset_split_arcs,
+    tolerances::points_size,
+};
+
+/// Computes the offset of a closed Polyline with a separate distance for every segment.
+///
+/// # Arguments
+///
+/// * `poly` - The input polyline, expected to be a closed shape.
+/// * `offs` - The offset distance of every segment, `offs[i]` is used for the segment
+///   from vertex `i` to vertex `i + 1`. Positive values offset to the "right" side of
+///   the polyline, negative values to the "left" side, all distances must have the same sign.
+/// * `cfg` - Configuration options controlling the offsetting behavior and observing the stages.
+///   Corners are always joined with arcs (`cfg.options.join` is not used), and `cfg.options.repair` is not used
+///   as it can change the number of segments.
+///
+/// # Returns
+///
+/// The offset polylines, as `offset_polyline_to_polyline` returns them.
+/// Invalid or degenerate cases produce an empty vector
+/// (use `try_offset_polyline_variable` to find out why).
+///
+/// # Examples
+///
+/// ```rust
+/// use togo::prelude::*;
+/// use offroad::prelude::*;
+///
+/// let mut cfg = OffsetCfg::default();
+/// // Clockwise square, offset to the right is inward
+/// let poly = vec![
+///     pvertex(point(0.0, 0.0), 0.0),
+///     pvertex(point(0.0, 10.0), 0.0),
+///     pvertex(point(10.0, 10.0), 0.0),
+///     pvertex(point(10.0, 0.0), 0.0),
+/// ];
+///
+/// // Leave 2 on the left side and 1 on the others
+/// let res = offset_polyline_variable(&poly, &[2.0, 1.0, 1.0, 1.0], &mut cfg);
+/// assert_eq!(res.len(), 1);
+/// assert!((polyline_signed_area(&res[0]).abs() - 56.0).abs() < 1e-9);
+/// ```
+pub fn offset_polyline_variable(poly: &Polyline, offs: &[f64], cfg: &mut OffsetCfg) -> Vec<Polyline> {
+    try_offset_polyline_variable(poly, offs, cfg).unwrap_or_default()
+}
+
+/// Computes the offset of a closed Polyline with a separate distance for every segment,
+/// reporting failures as `OffsetError`.
+///
+/// # Errors
+///
+/// `OffsetError::InvalidInput` when the number of distances is not the number of segments,
+/// or the distances are zero or of different signs,
+/// otherwise see `try_offset_polyline_to_polyline`.
+pub fn try_offset_polyline_variable(
+    poly: &Polyline,
+    offs: &[f64],
+    cfg: &mut OffsetCfg,
+) -> Result<Vec<Polyline>, OffsetError> {
+    check_offsets(poly, offs)?;
+    if let Some(observer) = cfg.observer.as_mut() {
+        observer.input(&[polyline_arcs(poly, true)]);
+    }
+
+    let max_off = offs.iter().fold(0.0, |max: f64, off| max.max(off.abs()));
+    let tol = cfg.options.tolerances_for(points_size(poly.iter().map(|v| v.p)), max_off);
+    let poly_raws = poly_to_raws(&vec![poly.clone()], &tol);
+    if poly_raws.iter().all(|raws| raws.is_empty()) {
+        return Err(OffsetError::InvalidInput(
+            "all segments are degenerate".to_string(),
+        ));
+    }
+    let raw_offs = vec![segment_offsets(poly, offs, &tol)];
+
+    let mut offset_raw = offset_polyline_raw_variable(&poly_raws, &raw_offs, &tol);
+    if let Some(observer) = cfg.observer.as_mut() {
+        observer.raw_offsets(&offset_raw);
+    }
+
+    let offset_connect = offset_connect_raw_variable(&mut offset_raw, &raw_offs, &tol);
+    if let Some(observer) = cfg.observer.as_mut() {
+        observer.connect(&offset_connect);
+    }
+
+    let mut offset_split = offset_split_arcs(&offset_raw, &offset_connect, &cfg.options.budget, &tol)?;
+    if let Some(observer) = cfg.observer.as_mut() {
+        observer.split(&offset_split);
+    }
+
+    let offset_prune =
+        offset_prune_invalid_variable(&poly_raws, &mut offset_split, &raw_offs, &cfg.options.budget, &tol)?;
+    if let Some(observer) = cfg.observer.as_mut() {
+        observer.pruned(&offset_prune);
+    }
+    if offset_prune.is_empty() {
+        return Err(OffsetError::Collapsed);
+    }
+
+    let reconnect_arcs = offset_reconnect_arcs(offset_prune, &cfg.options.budget, &tol)?;
+    if reconnect_arcs.is_empty() {
+        return Err(OffsetError::UnclosedResult);
+    }
+    if let Some(observer) = cfg.observer.as_mut() {
+        observer.reconnected(&reconnect_arcs);
+        observer.output(&reconnect_arcs);
+    }
+    let final_poly = arcs_to_polylines(&reconnect_arcs, &tol);
+    Ok(final_poly)
+}
+
+fn check_offsets(poly: &Polyline, offs: &[f64]) -> Result<(), OffsetError> {
+    if offs.len() != poly.len() {
+        return Err(OffsetError::InvalidInput(format!(
+            "{} offset distances for {} segments",
+            offs.len(),
+            poly.len()
+        )));
+    }
+    check_polyline(poly, 0.0)?;
+    for &off in offs.iter() {
+        check_offset(off)?;
+        if off == 0.0 || off.signum() != offs[0].signum() {
+            return Err(OffsetError::InvalidInput(
+                "offset distances must be non-zero and of the same sign".to_string(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_offset_variable {
+    use super::*;
+    use crate::{area::polyline_signed_area, offset::offset_polyline_to_polyline};
+
+    fn square_cw() -> Polyline {
+        vec![
+            pvertex(point(0.0, 0.0), 0.0),
+            pvertex(point(0.0, 10.0), 0.0),
+            pvertex(point(10.0, 10.0), 0.0),
+            pvertex(point(10.0, 0.0), 0.0),
+        ]
+    }
+
+    fn square_ccw() -> Polyline {
+        polyline_reverse(&square_cw())
+    }
+
+    #[test]
+    fn test_offset_variable_same_distances() {
+        let mut cfg = OffsetCfg::default();
+        let poly = crate::poly::pline_01()[0].clone();
+        for off in [2.0, 8.0, -3.0] {
+            let offs = vec![off; poly.len()];
+            let res = offset_polyline_variable(&poly, &offs, &mut cfg);
+            let expected = offset_polyline_to_polyline(&poly, off, &mut cfg);
+            assert_eq!(res.len(), expected.len());
+            let area = |polys: &Vec<Polyline>| -> f64 {
+                polys.iter().map(|p| polyline_signed_area(p).abs()).sum()
+            };
+            assert!((area(&res) - area(&expected)).abs() < 1e-6, "{}", off);
+        }
+    }
+
+    #[test]
+    fn test_offset_variable_inward() {
+        let mut cfg = OffsetCfg::default();
+        // Left side x = 2, other sides at 1
+        let res = offset_polyline_variable(&square_cw(), &[2.0, 1.0, 1.0, 1.0], &mut cfg);
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].len(), 4);
+        assert!(res[0].iter().any(|v| v.p.close_enough(point(2.0, 1.0), 1e-9)));
+        assert!(res[0].iter().any(|v| v.p.close_enough(point(2.0, 9.0), 1e-9)));
+    }
+
+    #[test]
+    fn test_offset_variable_outward() {
+        let mut cfg = OffsetCfg::default();
+        // Bottom side at 3, other sides at 1
+        let res = offset_polyline_variable(&square_ccw(), &[1.0, 1.0, 1.0, 3.0], &mut cfg);
+        assert_eq!(res.len(), 1);
+        let area = polyline_signed_area(&res[0]).abs();
+        // Corner arcs of radius 3 at the bottom end where they cross the side offsets,
+        // at (11, sqrt(8)) and (-1, sqrt(8))
+        let s = 8f64.sqrt();
+        let corner = 4.5 * (std::f64::consts::PI - (1.0 / 3.0f64).asin()) + s / 2.0;
+        let expected = 140.0 + 2.0 * (10.0 - s) + std::f64::consts::FRAC_PI_2 + 2.0 * corner;
+        assert!((area - expected).abs() < 1e-6, "{} {}", area, expected);
+    }
+
+    #[test]
+    fn test_offset_variable_collinear() {
+        let mut cfg = OffsetCfg::default();
+        // Left side split in two, different distances on the two halves
+        let poly = vec![
+            pvertex(point(0.0, 0.0), 0.0),
+            pvertex(point(0.0, 5.0), 0.0),
+            pvertex(point(0.0, 10.0), 0.0),
+            pvertex(point(10.0, 10.0), 0.0),
+            pvertex(point(10.0, 0.0), 0.0),
+        ];
+        let res = offset_polyline_variable(&poly, &[1.0, 2.0, 1.0, 1.0, 1.0], &mut cfg);
+        assert_eq!(res.len(), 1);
+        // Step from x = 2 to x = 1 along an arc of radius 2 around (0, 5)
+        assert!(res[0].iter().any(|v| v.p.close_enough(point(1.0, 5.0 - 3f64.sqrt()), 1e-9)));
+        assert!(res[0].iter().any(|v| v.p.close_enough(point(2.0, 5.0), 1e-9)));
+    }
+
+    #[test]
+    fn test_offset_variable_invalid() {
+        let mut cfg = OffsetCfg::default();
+        assert!(matches!(
+            try_offset_polyline_variable(&square_cw(), &[1.0, 1.0, 1.0], &mut cfg),
+            Err(OffsetError::InvalidInput(_))
+        ));
+        assert!(matches!(
+            try_offset_polyline_variable(&square_cw(), &[1.0, -1.0, 1.0, 1.0], &mut cfg),
+            Err(OffsetError::InvalidInput(_))
+        ));
+        assert!(matches!(
+            try_offset_polyline_variable(&square_cw(), &[1.0, 0.0, 1.0, 1.0], &mut cfg),
+            Err(OffsetError::InvalidInput(_))
+        ));
+        assert!(matches!(
+            try_offset_polyline_variable(&square_cw(), &[1.0, f64::NAN, 1.0, 1.0], &mut cfg),
+            Err(OffsetError::InvalidInput(_))
+        ));
+    }
+}
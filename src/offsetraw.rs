@@ -1,18 +1,19 @@
+//! Input segments and raw offsets used by the offsetting stages, see `OffsetPipeline`.
+
 #![allow(dead_code)]
 
 use std::fmt::Display;
 
 use togo::prelude::*;
 
-#[doc(hidden)]
-/// This is used in offsetting operations (offroad package).
+/// Input segment or its raw offset, before the offsets are connected and split.
 #[derive(Debug, PartialEq, Clone)]
 pub struct OffsetRaw {
-    /// The arc being offset.
+    /// The arc (or line segment) of the input or of its offset.
     pub arc: Arc,
-    /// The original point before offsetting.
+    /// The input vertex at the end of the segment, the corner joins are built around it.
     pub orig: Point,
-    /// The distance of the offset.
+    /// The bulge of the input segment, negative when the arc goes from `b` to `a`.
     pub g: f64,
 }
 
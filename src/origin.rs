@@ -0,0 +1,154 @@
+//! Provenance of the offset arcs.
+//!
+//! Every offset arc is made from one input segment: it is either a part of the offset
+//! of the segment, or a part of the corner join at one of its ends.
+//! `ArcOrigins` finds the origin of the arcs of an offset, so that per-edge attributes
+//! of the input (feed, layer, tabs) can be carried to the result.
+
+use togo::prelude::*;
+
+use crate::offsetraw::OffsetRaw;
+
+/// How an offset arc was made from its input segment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OriginKind {
+    /// Part of the offset of the segment
+    Offset,
+    /// Part of the corner join at an end of the segment
+    Join,
+}
+
+/// Input segment an offset arc was made from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ArcOrigin {
+    /// Index of the input shape, 0 for functions offsetting a single shape
+    pub contour: usize,
+    /// Index of the segment in the input shape, the segment from vertex `i` to vertex `i + 1`
+    /// of a Polyline, or the arc `i` of an Arcline
+    pub segment: usize,
+    /// Offset of the segment or corner join
+    pub kind: OriginKind,
+}
+
+/// Origins of the arcs of one offset, see `OffsetPipeline::origins`.
+///
+/// The input segments are numbered with the arc ids, the arcs of the offset
+/// keep the ids of the segment they were made from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ArcOrigins {
+    // Id of the first segment of every contour, and the total number of segments
+    starts: Vec<usize>,
+}
+
+impl ArcOrigins {
+    // Origins of contours with the given number of segments, degenerate ones included
+    pub(crate) fn new(counts: impl IntoIterator<Item = usize>) -> Self {
+        let mut starts = vec![0];
+        for count in counts {
+            starts.push(starts[starts.len() - 1] + count);
+        }
+        ArcOrigins { starts }
+    }
+
+    /// Returns the origin of an arc of the offset,
+    /// None for arcs that were not made by the offset.
+    pub fn get(&self, arc: &Arc) -> Option<ArcOrigin> {
+        let kind = if arc.id & 1 == 1 {
+            OriginKind::Join
+        } else {
+            OriginKind::Offset
+        };
+        let id = arc.id >> 1;
+        let total = *self.starts.last()?;
+        if id >= total {
+            return None;
+        }
+        let contour = self.starts.partition_point(|&start| start <= id) - 1;
+        Some(ArcOrigin {
+            contour,
+            segment: id - self.starts[contour],
+            kind,
+        })
+    }
+}
+
+// Arc ids tag the origin of the arcs in all stages: the input segment `k` and its
+// offsets have id 2k, the joins at its ends have id 2k + 1.
+// Arcs of the same segment have the same id, the split and prune stages use this
+// to skip the pairs of the same origin. The tags do not collide for any number of segments.
+pub(crate) fn offset_id(segment: usize) -> usize {
+    segment << 1
+}
+
+// Id of the joins at the ends of the segment with id `id`
+pub(crate) fn join_id(id: usize) -> usize {
+    id | 1
+}
+
+// Returns true when `join` is the id of the joins of the segment with id `id`
+pub(crate) fn is_join_of(join: usize, id: usize) -> bool {
+    join != id && join == join_id(id)
+}
+
+// Numbers the segments of all contours with consecutive ids,
+// the segments of every contour are numbered from 0 and shifted after the previous contours
+pub(crate) fn number_contours(raws: &mut [Vec<OffsetRaw>], counts: &[usize]) {
+    let mut start = 0;
+    for (contour, count) in raws.iter_mut().zip(counts.iter()) {
+        for raw in contour.iter_mut() {
+            raw.arc.id(raw.arc.id + offset_id(start));
+        }
+        start += count;
+    }
+}
+
+#[cfg(test)]
+mod test_origin {
+    use super::*;
+
+    fn with_id(id: usize) -> Arc {
+        let mut arc = arcseg(point(0.0, 0.0), point(1.0, 0.0));
+        arc.id(id);
+        arc
+    }
+
+    #[test]
+    fn test_get() {
+        let origins = ArcOrigins::new([4, 3]);
+        assert_eq!(
+            origins.get(&with_id(offset_id(2))),
+            Some(ArcOrigin { contour: 0, segment: 2, kind: OriginKind::Offset })
+        );
+        assert_eq!(
+            origins.get(&with_id(offset_id(4))),
+            Some(ArcOrigin { contour: 1, segment: 0, kind: OriginKind::Offset })
+        );
+        assert_eq!(
+            origins.get(&with_id(join_id(offset_id(6)))),
+            Some(ArcOrigin { contour: 1, segment: 2, kind: OriginKind::Join })
+        );
+        assert_eq!(origins.get(&with_id(offset_id(7))), None);
+        assert_eq!(ArcOrigins::default().get(&with_id(0)), None);
+    }
+
+    #[test]
+    fn test_ids_do_not_collide() {
+        // Many segments, the joins never get the id of another segment
+        for segment in [0, 1, 99_999, 100_000, usize::MAX >> 2] {
+            let id = offset_id(segment);
+            assert!(is_join_of(join_id(id), id));
+            assert!(!is_join_of(id, id));
+            assert!(!is_join_of(join_id(id), offset_id(segment + 1)));
+            assert_ne!(join_id(id), offset_id(segment + 1));
+        }
+        let origins = ArcOrigins::new([150_000, 50_000]);
+        assert_eq!(
+            origins.get(&with_id(join_id(offset_id(100_000)))),
+            Some(ArcOrigin { contour: 0, segment: 100_000, kind: OriginKind::Join })
+        );
+        assert_eq!(
+            origins.get(&with_id(offset_id(160_000))),
+            Some(ArcOrigin { contour: 1, segment: 10_000, kind: OriginKind::Offset })
+        );
+    }
+}
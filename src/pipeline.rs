@@ -0,0 +1,529 @@
+//! Stage-by-stage offsetting of closed Polylines and Arclines.
+//!
+//! `OffsetPipeline` runs the same stages as `offset_polyline_to_polyline`, one at a time,
+//! so that the intermediate results can be inspected or changed between the stages.
+
+use std::{cell::OnceCell, rc::Rc};
+
+use togo::prelude::*;
+
+use crate::{
+    canonical::canonical_polylines,
+    error::OffsetError,
+    observer::polyline_arcs,
+    offset::{JoinStyle, OffsetCfg, arcs_to_polylines, check_arcline, check_polyline},
+    offset_arcs_raw::{arcs_to_raws, offset_polyline_raw, poly_to_raws},
+    offset_connect_raw::{
+        merge_join_extensions, offset_connect_raw, offset_connect_raw_open, offset_join_corners,
+    },
+    offset_prune_invalid::{FlatEnd, PolyArcs, offset_prune_invalid, offset_prune_invalid_corners},
+    offset_reconnect_arcs::offset_reconnect_arcs,
+    offset_split_arcs::offset_split_arcs,
+    offsetraw::OffsetRaw,
+    origin::ArcOrigins,
+    tolerances::{Tolerances, points_size},
+};
+
+// Last stage that was run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Stage {
+    Input,
+    Raw,
+    Connect,
+    Split,
+    Prune,
+}
+
+// Input segments of the stages, and the data derived from them that does not depend
+// on the offset distance. The offsets of the same input at many distances share it.
+pub(crate) struct PipelineInput {
+    raws: Vec<Vec<OffsetRaw>>,
+    tol: Tolerances,
+    // Flat ends of open input, see offset_buffer
+    ends: Vec<FlatEnd>,
+    // Built by the first prune stage
+    polyarcs: OnceCell<PolyArcs>,
+}
+
+impl PipelineInput {
+    fn new(raws: Vec<Vec<OffsetRaw>>, tol: Tolerances) -> Self {
+        PipelineInput {
+            raws,
+            tol,
+            ends: Vec::new(),
+            polyarcs: OnceCell::new(),
+        }
+    }
+
+    fn polyarcs(&self) -> &PolyArcs {
+        self.polyarcs.get_or_init(|| {
+            PolyArcs::new(&self.raws, self.tol.prune).with_flat_ends(self.ends.clone())
+        })
+    }
+}
+
+/// Offsetting of one closed shape, one stage at a time.
+///
+/// The stages are run in order: `raw_offsets`, `connect`, `split`, `prune`,
+/// and the arcs are finally reconnected into loops with `reconnect` or `into_polylines`,
+/// or taken as they are with `into_arcs`.
+/// Calling a stage runs the stages before it that did not run yet.
+/// The result of the last stage can be read and changed with the `*_mut` accessors
+/// before the next stage runs.
+///
+/// `cfg` is used as in `offset_polyline_to_polyline`, except `cfg.options.repair`
+/// and `cfg.options.reconnect`, which are up to the caller.
+///
+/// # Examples
+///
+/// ```rust
+/// use togo::prelude::*;
+/// use offroad::prelude::*;
+///
+/// let mut cfg = OffsetCfg::default();
+/// // Clockwise square, offset to the right is inward
+/// let poly = vec![
+///     pvertex(point(0.0, 0.0), 0.0),
+///     pvertex(point(0.0, 10.0), 0.0),
+///     pvertex(point(10.0, 10.0), 0.0),
+///     pvertex(point(10.0, 0.0), 0.0),
+/// ];
+///
+/// let mut pipeline = OffsetPipeline::polyline(&poly, 1.0, &mut cfg)
+///     .unwrap()
+///     .split()
+///     .unwrap();
+/// // Own filter between split and prune
+/// pipeline.arcs_mut().retain(|arc| arc.a != arc.b);
+/// let res = pipeline.prune().unwrap().into_polylines().unwrap();
+/// assert_eq!(res.len(), 1);
+/// assert_eq!(res[0].len(), 4);
+/// ```
+pub struct OffsetPipeline<'c, 'a> {
+    cfg: &'c mut OffsetCfg<'a>,
+    off: f64,
+    closed: bool,
+    tol: Tolerances,
+    stage: Stage,
+    input: Rc<PipelineInput>,
+    origins: ArcOrigins,
+    raws: Vec<Vec<OffsetRaw>>,
+    connect: Vec<Arcline>,
+    arcs: Vec<Arc>,
+}
+
+impl<'c, 'a> OffsetPipeline<'c, 'a> {
+    /// Starts the offset of a closed Polyline, see `offset_polyline_to_polyline`.
+    ///
+    /// # Errors
+    ///
+    /// `OffsetError::InvalidInput` and `OffsetError::NonFiniteCoordinate` for invalid input,
+    /// see `try_offset_polyline_to_polyline`.
+    pub fn polyline(
+        poly: &Polyline,
+        off: f64,
+        cfg: &'c mut OffsetCfg<'a>,
+    ) -> Result<Self, OffsetError> {
+        let tol = cfg.options.tolerances_for(points_size(poly.iter().map(|v| v.p)), off);
+        Self::polyline_shared(poly, off, tol, cfg, &mut None)
+    }
+
+    // Starts the offset of a closed Polyline with the given tolerances, sharing the input
+    // with the offsets of the same Polyline at other distances.
+    // The input is created on the first call and left in shared.
+    pub(crate) fn polyline_shared(
+        poly: &Polyline,
+        off: f64,
+        tol: Tolerances,
+        cfg: &'c mut OffsetCfg<'a>,
+        shared: &mut Option<Rc<PipelineInput>>,
+    ) -> Result<Self, OffsetError> {
+        check_polyline(poly, off)?;
+        if let Some(observer) = cfg.observer.as_mut() {
+            observer.input(&[polyline_arcs(poly, true)]);
+        }
+        let input = match shared {
+            Some(input) => Rc::clone(input),
+            None => {
+                let raws = poly_to_raws(&vec![poly.clone()], &tol);
+                Rc::clone(shared.insert(Rc::new(PipelineInput::new(raws, tol))))
+            }
+        };
+        let mut pipeline = Self::from_input(input, off, cfg, true)?;
+        pipeline.origins = ArcOrigins::new([poly.len()]);
+        Ok(pipeline)
+    }
+
+    /// Starts the offset of a closed Arcline, see `offset_arcline_to_arcline`.
+    ///
+    /// # Errors
+    ///
+    /// See `OffsetPipeline::polyline`.
+    pub fn arcline(
+        arcs: &Arcline,
+        off: f64,
+        cfg: &'c mut OffsetCfg<'a>,
+    ) -> Result<Self, OffsetError> {
+        check_arcline(arcs, off)?;
+        if let Some(observer) = cfg.observer.as_mut() {
+            observer.input(std::slice::from_ref(arcs));
+        }
+        let tol = cfg.options.tolerances_for(points_size(arcs.iter().flat_map(|a| [a.a, a.b])), off);
+        let input = arcs_to_raws(&vec![arcs.clone()], &tol);
+        let mut pipeline = Self::from_raws(input, off, cfg, true, tol)?;
+        pipeline.origins = ArcOrigins::new([arcs.len()]);
+        Ok(pipeline)
+    }
+
+    // Starts the offset of input already converted to raws,
+    // open input is not connected from the last to the first segment.
+    pub(crate) fn from_raws(
+        input: Vec<Vec<OffsetRaw>>,
+        off: f64,
+        cfg: &'c mut OffsetCfg<'a>,
+        closed: bool,
+        tol: Tolerances,
+    ) -> Result<Self, OffsetError> {
+        Self::from_input(Rc::new(PipelineInput::new(input, tol)), off, cfg, closed)
+    }
+
+    // Starts the two-sided offset of an open path, input has the path in both directions.
+    // The terminal segments do not prune the offsets past the flat ends.
+    pub(crate) fn from_raws_flat_ends(
+        input: Vec<Vec<OffsetRaw>>,
+        off: f64,
+        cfg: &'c mut OffsetCfg<'a>,
+        ends: Vec<FlatEnd>,
+        tol: Tolerances,
+    ) -> Result<Self, OffsetError> {
+        let input = PipelineInput {
+            ends,
+            ..PipelineInput::new(input, tol)
+        };
+        Self::from_input(Rc::new(input), off, cfg, false)
+    }
+
+    fn from_input(
+        input: Rc<PipelineInput>,
+        off: f64,
+        cfg: &'c mut OffsetCfg<'a>,
+        closed: bool,
+    ) -> Result<Self, OffsetError> {
+        if input.raws.iter().all(|raws| raws.is_empty()) {
+            return Err(OffsetError::InvalidInput(
+                "all segments are degenerate".to_string(),
+            ));
+        }
+        Ok(OffsetPipeline {
+            cfg,
+            off,
+            closed,
+            tol: input.tol,
+            stage: Stage::Input,
+            input,
+            origins: ArcOrigins::default(),
+            raws: Vec::new(),
+            connect: Vec::new(),
+            arcs: Vec::new(),
+        })
+    }
+
+    /// Tolerances used by the stages, see `OffsetOptions::auto_tolerances`.
+    pub fn tolerances(&self) -> &Tolerances {
+        &self.tol
+    }
+
+    /// The input segments, without the degenerate ones.
+    pub fn input(&self) -> &[Vec<OffsetRaw>] {
+        &self.input.raws
+    }
+
+    /// Origins of the arcs of all stages, in the input shape.
+    ///
+    /// The arcs keep their origin through all stages, `ArcOrigins::get` finds it
+    /// for the arcs returned by `reconnect` and `into_arcs` as well.
+    /// The origins are only known for pipelines started with `polyline` or `arcline`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use togo::prelude::*;
+    /// use offroad::prelude::*;
+    ///
+    /// let mut cfg = OffsetCfg::default();
+    /// let poly = vec![
+    ///     pvertex(point(0.0, 0.0), 0.0),
+    ///     pvertex(point(0.0, 10.0), 0.0),
+    ///     pvertex(point(10.0, 10.0), 0.0),
+    ///     pvertex(point(10.0, 0.0), 0.0),
+    /// ];
+    ///
+    /// let pipeline = OffsetPipeline::polyline(&poly, 1.0, &mut cfg).unwrap();
+    /// let origins = pipeline.origins().clone();
+    /// let loops = pipeline.reconnect().unwrap();
+    /// for arc in loops[0].iter() {
+    ///     let origin = origins.get(arc).unwrap();
+    ///     assert_eq!(origin.kind, OriginKind::Offset);
+    ///     assert!(origin.segment < 4);
+    /// }
+    /// ```
+    pub fn origins(&self) -> &ArcOrigins {
+        &self.origins
+    }
+
+    /// Offsets every input segment separately.
+    pub fn raw_offsets(mut self) -> Self {
+        if self.stage >= Stage::Raw {
+            return self;
+        }
+        self.raws = offset_polyline_raw(&self.input.raws, self.off, &self.tol);
+        if let Some(observer) = self.cfg.observer.as_mut() {
+            observer.raw_offsets(&self.raws);
+        }
+        self.stage = Stage::Raw;
+        self
+    }
+
+    /// Connects the raw offsets with the corner joins of `cfg.options.join`.
+    pub fn connect(self) -> Self {
+        let mut pipeline = self.raw_offsets();
+        if pipeline.stage >= Stage::Connect {
+            return pipeline;
+        }
+        let (raws, off, join, tol) = (&pipeline.raws, pipeline.off, pipeline.cfg.options.join, &pipeline.tol);
+        pipeline.connect = if pipeline.closed {
+            offset_connect_raw(raws, off, join, tol)
+        } else {
+            offset_connect_raw_open(raws, off, join, tol)
+        };
+        if let Some(observer) = pipeline.cfg.observer.as_mut() {
+            observer.connect(&pipeline.connect);
+        }
+        pipeline.stage = Stage::Connect;
+        pipeline
+    }
+
+    /// Splits the raw offsets and the connections at their intersections.
+    ///
+    /// # Errors
+    ///
+    /// `OffsetError::SplitIterationLimit`, `OffsetError::Cancelled` and
+    /// `OffsetError::DeadlineExceeded` when `cfg.options.budget` stops the split.
+    pub fn split(self) -> Result<Self, OffsetError> {
+        let mut pipeline = self.connect();
+        if pipeline.stage >= Stage::Split {
+            return Ok(pipeline);
+        }
+        pipeline.arcs =
+            offset_split_arcs(&pipeline.raws, &pipeline.connect, &pipeline.cfg.options.budget, &pipeline.tol)?;
+        if let Some(observer) = pipeline.cfg.observer.as_mut() {
+            observer.split(&pipeline.arcs);
+        }
+        pipeline.stage = Stage::Split;
+        Ok(pipeline)
+    }
+
+    /// Removes the split arcs that are closer to the input than the offset distance.
+    ///
+    /// # Errors
+    ///
+    /// `OffsetError::Collapsed` when no arc is left, otherwise see `OffsetPipeline::split`.
+    pub fn prune(self) -> Result<Self, OffsetError> {
+        let mut pipeline = self.split()?;
+        if pipeline.stage >= Stage::Prune {
+            return Ok(pipeline);
+        }
+        let (off, join, budget, tol) =
+            (pipeline.off, pipeline.cfg.options.join, &pipeline.cfg.options.budget, &pipeline.tol);
+        let polyarcs = pipeline.input.polyarcs();
+        pipeline.arcs = if join == JoinStyle::Round {
+            offset_prune_invalid(polyarcs, &mut pipeline.arcs, off, budget, tol)?
+        } else {
+            let corners = offset_join_corners(&pipeline.raws, off, join, pipeline.closed, tol);
+            offset_prune_invalid_corners(polyarcs, &mut pipeline.arcs, off, &corners, budget, tol)?
+        };
+        if let Some(observer) = pipeline.cfg.observer.as_mut() {
+            observer.pruned(&pipeline.arcs);
+        }
+        if pipeline.arcs.is_empty() {
+            return Err(OffsetError::Collapsed);
+        }
+        pipeline.stage = Stage::Prune;
+        Ok(pipeline)
+    }
+
+    /// Reconnects the pruned arcs into closed loops.
+    ///
+    /// # Errors
+    ///
+    /// `OffsetError::UnclosedResult` when the arcs do not form closed loops,
+    /// otherwise see `OffsetPipeline::prune`.
+    pub fn reconnect(self) -> Result<Vec<Arcline>, OffsetError> {
+        let pipeline = self.prune()?;
+        let mut loops = offset_reconnect_arcs(pipeline.arcs, &pipeline.cfg.options.budget, &pipeline.tol)?;
+        if loops.is_empty() {
+            return Err(OffsetError::UnclosedResult);
+        }
+        if let Some(observer) = pipeline.cfg.observer.as_mut() {
+            observer.reconnected(&loops);
+        }
+        merge_join_extensions(&mut loops, pipeline.cfg.options.join, true, &pipeline.tol);
+        if let Some(observer) = pipeline.cfg.observer.as_mut() {
+            observer.output(&loops);
+        }
+        Ok(loops)
+    }
+
+    /// Reconnects the pruned arcs into closed loops and converts them to Polylines,
+    /// in canonical form when `cfg.options.canonical` is set.
+    ///
+    /// # Errors
+    ///
+    /// See `OffsetPipeline::reconnect`.
+    pub fn into_polylines(self) -> Result<Vec<Polyline>, OffsetError> {
+        let (tol, canonical) = (self.tol, self.cfg.options.canonical);
+        let loops = self.reconnect()?;
+        let polys = arcs_to_polylines(&loops, &tol);
+        if canonical {
+            Ok(canonical_polylines(polys))
+        } else {
+            Ok(polys)
+        }
+    }
+
+    /// Returns the arcs of the last stage that was run, unordered and not reconnected.
+    ///
+    /// Runs the stages up to `split` when it did not run yet.
+    ///
+    /// # Errors
+    ///
+    /// See `OffsetPipeline::split`.
+    pub fn into_arcs(self) -> Result<Vec<Arc>, OffsetError> {
+        Ok(self.split()?.arcs)
+    }
+
+    /// Raw offsets of the input segments, empty before `raw_offsets`.
+    pub fn raws(&self) -> &[Vec<OffsetRaw>] {
+        &self.raws
+    }
+
+    /// Raw offsets of the input segments, to change them before `connect`.
+    pub fn raws_mut(&mut self) -> &mut Vec<Vec<OffsetRaw>> {
+        &mut self.raws
+    }
+
+    /// Corner joins, empty before `connect`.
+    pub fn connect_arcs(&self) -> &[Arcline] {
+        &self.connect
+    }
+
+    /// Corner joins, to change them before `split`.
+    pub fn connect_arcs_mut(&mut self) -> &mut Vec<Arcline> {
+        &mut self.connect
+    }
+
+    /// Split arcs after `split`, or the remaining arcs after `prune`.
+    pub fn arcs(&self) -> &[Arc] {
+        &self.arcs
+    }
+
+    /// Split or pruned arcs, to change them before the next stage.
+    pub fn arcs_mut(&mut self) -> &mut Vec<Arc> {
+        &mut self.arcs
+    }
+}
+
+#[cfg(test)]
+mod test_pipeline {
+    use super::*;
+    use crate::offset::{OffsetOptions, offset_arcline_to_arcline, offset_polyline_to_polyline};
+
+    #[test]
+    fn test_pipeline_matches_offset() {
+        let poly = crate::poly::pline_01()[0].clone();
+        for off in [2.0, 8.0, -3.0] {
+            let mut cfg = OffsetCfg::default();
+            let expected = offset_polyline_to_polyline(&poly, off, &mut cfg);
+            let res = OffsetPipeline::polyline(&poly, off, &mut cfg)
+                .unwrap()
+                .into_polylines()
+                .unwrap();
+            assert_eq!(res, expected, "{}", off);
+        }
+    }
+
+    #[test]
+    fn test_pipeline_stages() {
+        let arcs = vec![
+            arcseg(point(0.0, 0.0), point(0.0, 10.0)),
+            arcseg(point(0.0, 10.0), point(10.0, 10.0)),
+            arcseg(point(10.0, 10.0), point(10.0, 0.0)),
+            arcseg(point(10.0, 0.0), point(0.0, 0.0)),
+        ];
+        let mut cfg = OffsetCfg::default();
+        let expected = offset_arcline_to_arcline(&arcs, 2.0, &mut cfg);
+
+        let pipeline = OffsetPipeline::arcline(&arcs, 2.0, &mut cfg).unwrap();
+        assert!(pipeline.raws().is_empty());
+        let pipeline = pipeline.raw_offsets();
+        assert_eq!(pipeline.raws()[0].len(), 4);
+        let pipeline = pipeline.connect();
+        assert_eq!(pipeline.connect_arcs().len(), 1);
+        let pipeline = pipeline.split().unwrap();
+        let split = pipeline.arcs().len();
+        let pipeline = pipeline.prune().unwrap();
+        assert!(pipeline.arcs().len() <= split);
+        assert_eq!(pipeline.reconnect().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_pipeline_without_reconnect() {
+        let poly = vec![
+            pvertex(point(0.0, 0.0), 0.0),
+            pvertex(point(0.0, 10.0), 0.0),
+            pvertex(point(10.0, 10.0), 0.0),
+            pvertex(point(10.0, 0.0), 0.0),
+        ];
+        let mut cfg = OffsetCfg::default();
+        let arcs = OffsetPipeline::polyline(&poly, 1.0, &mut cfg)
+            .unwrap()
+            .prune()
+            .unwrap()
+            .into_arcs()
+            .unwrap();
+        assert_eq!(arcs.len(), 4);
+    }
+
+    #[test]
+    fn test_pipeline_replace_arcs() {
+        let poly = vec![
+            pvertex(point(0.0, 0.0), 0.0),
+            pvertex(point(0.0, 10.0), 0.0),
+            pvertex(point(10.0, 10.0), 0.0),
+            pvertex(point(10.0, 0.0), 0.0),
+        ];
+        let mut cfg = OffsetCfg::default();
+        let mut pipeline = OffsetPipeline::polyline(&poly, 1.0, &mut cfg).unwrap().split().unwrap();
+        pipeline.arcs_mut().clear();
+        assert!(matches!(pipeline.prune(), Err(OffsetError::Collapsed)));
+
+        let res = OffsetPipeline::polyline(&Vec::new(), 1.0, &mut cfg);
+        assert!(matches!(res, Err(OffsetError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_pipeline_shared_input() {
+        let poly = crate::poly::pline_01()[0].clone();
+        let mut cfg = OffsetCfg::from(OffsetOptions {
+            auto_tolerances: true,
+            ..OffsetOptions::default()
+        });
+        let tol = cfg.options.tolerances_for(points_size(poly.iter().map(|v| v.p)), 400.0);
+        let mut shared = None;
+        for off in [1.0, 8.0, 400.0] {
+            let pipeline = OffsetPipeline::polyline_shared(&poly, off, tol, &mut cfg, &mut shared).unwrap();
+            assert!(Rc::ptr_eq(&pipeline.input, shared.as_ref().unwrap()));
+            assert_eq!(*pipeline.tolerances(), tol);
+        }
+    }
+}
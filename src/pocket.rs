@@ -0,0 +1,530 @@
+//! Pocketing toolpaths: concentric offsets of a region, for clearing it with a round tool.
+//!
+//! The tool center moves along inward offsets of the pocket boundary and of its islands,
+//! the first pass at the tool radius and every next pass one stepover further,
+//! until the region collapses. Where a branch or a neck of the region collapses more than
+//! the tool radius from the passes around it, one more pass cuts its middle: at the tool
+//! radius from the collapse distance, or from the next pass where the region goes on.
+//! Every pass is offset from the input region, so the distances do not drift.
+
+use togo::prelude::*;
+
+use crate::{
+    area::polyline_contains_point,
+    error::OffsetError,
+    observer::polyline_arcs,
+    offset::OffsetCfg,
+    offset_prune_invalid::dist_point_element,
+    region::{Region, try_offset_region},
+};
+
+// Precision of the collapse distance of the last pass, relative to the tool radius
+const LAST_PASS_PRECISION: f64 = 1e-3;
+
+/// One pass of a pocket: the region of the tool center at one distance from the walls.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PocketNode {
+    /// Loops of the tool center, the outer loop counter-clockwise and the island loops clockwise
+    pub region: Region,
+    /// Distance from the boundary and the islands, the tool radius plus `pass` stepovers,
+    /// or less for the last pass of a branch
+    pub offset: f64,
+    /// Number of the pass, 0 for the pass at the tool radius
+    pub pass: usize,
+    /// Index of the region of the previous pass containing this region
+    pub parent: Option<usize>,
+    /// Indices of the regions of the next pass inside of this region,
+    /// more than one where the region splits
+    pub children: Vec<usize>,
+}
+
+/// Concentric passes of a pocket, as a tree that follows the splits of the region.
+///
+/// The roots are the passes at the tool radius, one for every part of the pocket
+/// the tool fits in. Every pass is inside of its parent, at most one stepover further
+/// from the walls.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Pocket {
+    /// The passes, ordered by `pass`
+    pub nodes: Vec<PocketNode>,
+    /// Indices of the passes at the tool radius
+    pub roots: Vec<usize>,
+}
+
+impl Pocket {
+    /// Returns the passes in cutting order, from the center to the walls.
+    ///
+    /// Every pass comes after the passes inside of it, so every part of the pocket
+    /// is finished with the pass at exactly the tool radius.
+    pub fn cut_order(&self) -> Vec<usize> {
+        let mut order = Vec::with_capacity(self.nodes.len());
+        // Depth-first, a node is placed after all of its children
+        let mut stack: Vec<(usize, bool)> = self.roots.iter().rev().map(|&i| (i, false)).collect();
+        while let Some((i, visited)) = stack.pop() {
+            if visited {
+                order.push(i);
+                continue;
+            }
+            stack.push((i, true));
+            stack.extend(self.nodes[i].children.iter().rev().map(|&c| (c, false)));
+        }
+        order
+    }
+
+    /// Returns the chains of nested passes, in cutting order, each from the center to the walls.
+    ///
+    /// A chain goes on while its passes do not split, it ends at a pass with a split
+    /// region or at a pass at the tool radius. The passes of a chain without islands can be
+    /// linked into one path with `link_loops`.
+    pub fn chains(&self) -> Vec<Vec<usize>> {
+        let mut chains = Vec::new();
+        for i in self.cut_order() {
+            // Chains end at the roots and at the children of the splits
+            let top = match self.nodes[i].parent {
+                Some(parent) => self.nodes[parent].children.len() > 1,
+                None => true,
+            };
+            if !top {
+                continue;
+            }
+            let mut chain = vec![i];
+            let mut node = &self.nodes[i];
+            while let [child] = node.children[..] {
+                chain.push(child);
+                node = &self.nodes[child];
+            }
+            chain.reverse();
+            chains.push(chain);
+        }
+        chains
+    }
+}
+
+/// Computes the pocketing passes of a region, see `Pocket`.
+///
+/// The islands of the pocket are the holes of the region.
+/// Invalid input and tools that do not fit in the pocket produce an empty `Pocket`
+/// (use `try_pocket_region` to find out why).
+///
+/// # Examples
+///
+/// ```rust
+/// use togo::prelude::*;
+/// use offroad::prelude::*;
+///
+/// let mut cfg = OffsetCfg::default();
+/// let boundary = vec![
+///     pvertex(point(0.0, 0.0), 0.0),
+///     pvertex(point(40.0, 0.0), 0.0),
+///     pvertex(point(40.0, 20.0), 0.0),
+///     pvertex(point(0.0, 20.0), 0.0),
+/// ];
+/// let island = vec![
+///     pvertex(point(18.0, 8.0), 0.0),
+///     pvertex(point(22.0, 8.0), 0.0),
+///     pvertex(point(22.0, 12.0), 0.0),
+///     pvertex(point(18.0, 12.0), 0.0),
+/// ];
+/// let region = Region::new(boundary, vec![island]);
+///
+/// let pocket = pocket_region(&region, 1.0, 1.5, &mut cfg);
+/// // One ring around the island at the tool radius
+/// assert_eq!(pocket.roots.len(), 1);
+/// assert_eq!(pocket.nodes[0].offset, 1.0);
+/// assert_eq!(pocket.nodes[0].region.holes.len(), 1);
+/// // The ring splits at the sides of the island
+/// assert!(pocket.nodes.iter().any(|node| node.children.len() == 2));
+/// // The pass at the tool radius is the last one
+/// assert_eq!(pocket.cut_order().last(), Some(&0));
+/// ```
+pub fn pocket_region(
+    region: &Region,
+    tool_radius: f64,
+    stepover: f64,
+    cfg: &mut OffsetCfg,
+) -> Pocket {
+    try_pocket_region(region, tool_radius, stepover, cfg).unwrap_or_default()
+}
+
+/// Computes the pocketing passes of a region, reporting failures as `OffsetError`.
+///
+/// # Errors
+///
+/// - `OffsetError::InvalidInput` when the tool radius or the stepover is not positive
+///   and finite, or the stepover is larger than the tool diameter, which leaves material
+///   between the passes
+/// - `OffsetError::Collapsed` when the tool does not fit in the pocket
+/// - otherwise see `try_offset_region`
+///
+/// Stepovers larger than the tool radius times `sqrt(2)` can leave material between
+/// the passes at their sharp corners.
+pub fn try_pocket_region(
+    region: &Region,
+    tool_radius: f64,
+    stepover: f64,
+    cfg: &mut OffsetCfg,
+) -> Result<Pocket, OffsetError> {
+    check_tool(tool_radius, stepover)?;
+    let mut pocket = Pocket::default();
+    // Passes of the previous distance, the parents of the next ones
+    let mut prev: Vec<usize> = Vec::new();
+    for pass in 0.. {
+        let offset = tool_radius + pass as f64 * stepover;
+        let regions = match try_offset_region(region, -offset, cfg) {
+            Ok(regions) => regions,
+            Err(OffsetError::Collapsed) if pass > 0 => Vec::new(),
+            Err(err) => return Err(err),
+        };
+        let mut next = Vec::with_capacity(regions.len());
+        for region in regions {
+            let parent = prev
+                .iter()
+                .copied()
+                .find(|&j| first_inside(&region, &pocket.nodes[j].region));
+            next.push(add_node(&mut pocket, region, offset, pass, parent));
+        }
+        // Branches and necks that collapse before this pass, their middle can be left uncut
+        for &j in prev.iter() {
+            add_last_passes(&mut pocket, j, region, offset, tool_radius, cfg)?;
+        }
+        if next.is_empty() {
+            break;
+        }
+        prev = next;
+    }
+    Ok(pocket)
+}
+
+fn add_node(
+    pocket: &mut Pocket,
+    region: Region,
+    offset: f64,
+    pass: usize,
+    parent: Option<usize>,
+) -> usize {
+    let i = pocket.nodes.len();
+    match parent {
+        Some(j) => pocket.nodes[j].children.push(i),
+        None => pocket.roots.push(i),
+    }
+    pocket.nodes.push(PocketNode {
+        region,
+        offset,
+        pass,
+        parent,
+        children: Vec::new(),
+    });
+    i
+}
+
+// Adds the last passes of the parts of node j that the passes of the node and of its children
+// do not cut. The parts are the regions at the tool radius from the pass of the node.
+// A part with no child region inside collapses before the next pass: the collapse distance
+// is found by bisection between the offset of the part and the offset of the next pass,
+// and the last pass is at the tool radius from it. A part with child regions that is not
+// within the tool radius of their passes (a branch or a neck that collapses before the
+// next pass) gets a last pass at the tool radius from the next pass. Such parts are found
+// at the middle of their edges, the sharp corners of the parts are left to the stepover.
+fn add_last_passes(
+    pocket: &mut Pocket,
+    j: usize,
+    input: &Region,
+    next_offset: f64,
+    tool_radius: f64,
+    cfg: &mut OffsetCfg,
+) -> Result<(), OffsetError> {
+    let node = &pocket.nodes[j];
+    let part_offset = node.offset + tool_radius;
+    if part_offset >= next_offset {
+        return Ok(());
+    }
+    let (node_region, pass) = (node.region.clone(), node.pass + 1);
+    let children: Vec<&Region> = node.children.iter().map(|&c| &pocket.nodes[c].region).collect();
+    // Passes of the children, they cut their regions and the tool radius around them
+    let passes: Vec<Arc> = children
+        .iter()
+        .flat_map(|child| std::iter::once(&child.outer).chain(child.holes.iter()))
+        .flat_map(|poly| polyline_arcs(poly, true))
+        .collect();
+    let reach = tool_radius * (1.0 + LAST_PASS_PRECISION);
+    let is_cut = |p: Point| {
+        children.iter().any(|child| region_contains_point(child, p))
+            || passes.iter().any(|arc| dist_point_element(arc, p).0 <= reach)
+    };
+
+    let mut last: Vec<(f64, Region)> = Vec::new();
+    for part in regions_inside(input, part_offset, &node_region, cfg)? {
+        let offset = if children.iter().any(|child| first_inside(child, &part)) {
+            let cut = std::iter::once(&part.outer)
+                .chain(part.holes.iter())
+                .flat_map(middle_points)
+                .all(is_cut);
+            if cut {
+                continue;
+            }
+            next_offset - tool_radius
+        } else {
+            let (mut lo, mut hi) = (part_offset, next_offset);
+            while hi - lo > tool_radius * LAST_PASS_PRECISION {
+                let mid = 0.5 * (lo + hi);
+                if regions_inside(input, mid, &part, cfg)?.is_empty() {
+                    hi = mid;
+                } else {
+                    lo = mid;
+                }
+            }
+            // The part collapses between lo and hi, at the tool radius from the pass of the node
+            if lo <= part_offset {
+                continue;
+            }
+            hi - tool_radius
+        };
+        for region in regions_inside(input, offset, &node_region, cfg)? {
+            if first_inside(&part, &region) && !last.iter().any(|(o, r)| *o == offset && *r == region) {
+                last.push((offset, region));
+            }
+        }
+    }
+    for (offset, region) in last {
+        add_node(pocket, region, offset, pass, Some(j));
+    }
+    Ok(())
+}
+
+// Regions of the input offset inward by offset, inside of the region within
+fn regions_inside(
+    input: &Region,
+    offset: f64,
+    within: &Region,
+    cfg: &mut OffsetCfg,
+) -> Result<Vec<Region>, OffsetError> {
+    let regions = match try_offset_region(input, -offset, cfg) {
+        Ok(regions) => regions,
+        Err(OffsetError::Collapsed) => Vec::new(),
+        Err(err) => return Err(err),
+    };
+    Ok(regions.into_iter().filter(|region| first_inside(region, within)).collect())
+}
+
+// Middle points of the edges of the closed Polyline
+fn middle_points(poly: &Polyline) -> impl Iterator<Item = Point> + '_ {
+    poly.iter().enumerate().map(|(i, v)| {
+        let next = poly[(i + 1) % poly.len()].p;
+        let d = next - v.p;
+        // Positive bulges are CCW arcs, on the right of the chord
+        (v.p + next) * 0.5 + point(d.y, -d.x) * (0.5 * v.b)
+    })
+}
+
+// The first point of the outer loop of region is inside of other
+fn first_inside(region: &Region, other: &Region) -> bool {
+    region
+        .outer
+        .first()
+        .is_some_and(|v| region_contains_point(other, v.p))
+}
+
+fn check_tool(tool_radius: f64, stepover: f64) -> Result<(), OffsetError> {
+    if !(tool_radius.is_finite() && tool_radius > 0.0) {
+        return Err(OffsetError::InvalidInput(format!(
+            "tool radius {} is not positive",
+            tool_radius
+        )));
+    }
+    if !(stepover.is_finite() && stepover > 0.0) {
+        return Err(OffsetError::InvalidInput(format!(
+            "stepover {} is not positive",
+            stepover
+        )));
+    }
+    if stepover > 2.0 * tool_radius {
+        return Err(OffsetError::InvalidInput(format!(
+            "stepover {} is larger than the tool diameter {}",
+            stepover,
+            2.0 * tool_radius
+        )));
+    }
+    Ok(())
+}
+
+// Point inside of the outer loop and outside of the holes
+fn region_contains_point(region: &Region, p: Point) -> bool {
+    polyline_contains_point(&region.outer, p)
+        && !region
+            .holes
+            .iter()
+            .any(|hole| polyline_contains_point(hole, p))
+}
+
+#[cfg(test)]
+mod test_pocket {
+    use super::*;
+
+    fn rect(x0: f64, y0: f64, x1: f64, y1: f64) -> Polyline {
+        vec![
+            pvertex(point(x0, y0), 0.0),
+            pvertex(point(x1, y0), 0.0),
+            pvertex(point(x1, y1), 0.0),
+            pvertex(point(x0, y1), 0.0),
+        ]
+    }
+
+    #[test]
+    fn test_pocket_rect() {
+        let mut cfg = OffsetCfg::default();
+        let region = Region::new(rect(0.0, 0.0, 20.0, 10.0), Vec::new());
+        let pocket = try_pocket_region(&region, 1.0, 1.5, &mut cfg).unwrap();
+        // Passes at 1.0, 2.5 and 4.0, the region collapses at 5.0
+        assert_eq!(pocket.roots, vec![0]);
+        let offsets: Vec<f64> = pocket.nodes.iter().map(|node| node.offset).collect();
+        assert_eq!(offsets, vec![1.0, 2.5, 4.0]);
+        for (i, node) in pocket.nodes.iter().enumerate() {
+            assert_eq!(node.pass, i);
+            assert_eq!(node.parent, i.checked_sub(1));
+        }
+        // The first pass is at exactly the tool radius from the walls
+        let outer = &pocket.nodes[0].region.outer;
+        assert!(outer.iter().all(|v| v.p.x >= 1.0 && v.p.x <= 19.0));
+        assert!(outer.iter().any(|v| v.p.x == 1.0) && outer.iter().any(|v| v.p.y == 9.0));
+        assert_eq!(pocket.cut_order(), vec![2, 1, 0]);
+        assert_eq!(pocket.chains(), vec![vec![2, 1, 0]]);
+    }
+
+    #[test]
+    fn test_pocket_split() {
+        let mut cfg = OffsetCfg::default();
+        let region = Region::new(
+            rect(0.0, 0.0, 40.0, 20.0),
+            vec![rect(18.0, 8.0, 22.0, 12.0)],
+        );
+        let pocket = try_pocket_region(&region, 1.0, 1.5, &mut cfg).unwrap();
+        assert_eq!(pocket.roots, vec![0]);
+        assert_eq!(pocket.nodes[0].region.holes.len(), 1);
+        let split = pocket
+            .nodes
+            .iter()
+            .position(|node| node.children.len() == 2)
+            .unwrap();
+        // Left and right of the island, the branches go on separately
+        let children = &pocket.nodes[split].children;
+        for &c in children.iter() {
+            assert!(pocket.nodes[c].region.holes.is_empty());
+            assert_eq!(pocket.nodes[c].pass, pocket.nodes[split].pass + 1);
+        }
+        let chains = pocket.chains();
+        assert_eq!(chains.len(), 3);
+        assert_eq!(
+            chains.iter().map(|chain| chain.len()).sum::<usize>(),
+            pocket.nodes.len()
+        );
+        assert_eq!(*chains[2].last().unwrap(), 0);
+        assert_eq!(chains[2][0], split);
+        let order = pocket.cut_order();
+        assert_eq!(order.len(), pocket.nodes.len());
+        let position = |i: usize| order.iter().position(|&j| j == i).unwrap();
+        for (i, node) in pocket.nodes.iter().enumerate() {
+            assert!(node.children.iter().all(|&c| position(c) < position(i)));
+        }
+    }
+
+    // Every point of the window at least the tool radius from the walls is cut by some pass
+    fn assert_covered(region: &Region, pocket: &Pocket, tool_radius: f64, min: Point, max: Point) {
+        let loops = |region: &Region| -> Vec<Arcline> {
+            std::iter::once(&region.outer)
+                .chain(region.holes.iter())
+                .map(|poly| polyline_arcs(poly, true))
+                .collect()
+        };
+        let dist = |loops: &[Arcline], p: Point| {
+            loops
+                .iter()
+                .flatten()
+                .map(|arc| dist_point_element(arc, p).0)
+                .fold(f64::INFINITY, f64::min)
+        };
+        let walls = loops(region);
+        let passes: Vec<Arcline> = pocket
+            .nodes
+            .iter()
+            .flat_map(|node| loops(&node.region))
+            .collect();
+        let step = 0.1;
+        for i in 0..=((max.x - min.x) / step) as usize {
+            for j in 0..=((max.y - min.y) / step) as usize {
+                let p = point(min.x + i as f64 * step, min.y + j as f64 * step);
+                if !region_contains_point(region, p) || dist(&walls, p) < tool_radius {
+                    continue;
+                }
+                assert!(dist(&passes, p) <= tool_radius + 1e-6, "{:?} is not cut", p);
+            }
+        }
+    }
+
+    #[test]
+    fn test_pocket_last_pass() {
+        let mut cfg = OffsetCfg::default();
+        // The stepover is larger than the tool radius, the middle is cut by a last pass
+        let region = Region::new(rect(0.0, 0.0, 20.0, 9.8), Vec::new());
+        let pocket = try_pocket_region(&region, 1.0, 2.0, &mut cfg).unwrap();
+        let offsets: Vec<f64> = pocket.nodes.iter().map(|node| node.offset).collect();
+        assert_eq!(offsets.len(), 3);
+        assert_eq!(offsets[..2], [1.0, 3.0]);
+        assert!((offsets[2] - 3.9).abs() < 1e-2);
+        assert_eq!(pocket.nodes[2].parent, Some(1));
+        assert_covered(&region, &pocket, 1.0, point(3.0, 3.0), point(17.0, 6.8));
+
+        // Stepovers up to the tool radius times sqrt(2) also cut the corners
+        let region = Region::new(rect(0.0, 0.0, 20.0, 10.0), Vec::new());
+        let pocket = try_pocket_region(&region, 1.0, 1.4, &mut cfg).unwrap();
+        assert_eq!(pocket.nodes.len(), 4);
+        assert_covered(&region, &pocket, 1.0, point(0.0, 0.0), point(20.0, 10.0));
+
+        // Branches around an island end at different passes
+        let region = Region::new(
+            rect(0.0, 0.0, 40.0, 20.0),
+            vec![rect(14.0, 6.0, 22.0, 12.0)],
+        );
+        let pocket = try_pocket_region(&region, 1.0, 1.4, &mut cfg).unwrap();
+        assert_covered(&region, &pocket, 1.0, point(0.0, 0.0), point(40.0, 20.0));
+    }
+
+    #[test]
+    fn test_pocket_corridor() {
+        let mut cfg = OffsetCfg::default();
+        // Two squares joined by a corridor, the corridor collapses between two passes
+        // that go on in both squares
+        let boundary = [
+            (0.0, 0.0), (30.0, 0.0), (30.0, 10.0), (40.0, 10.0), (40.0, 0.0), (70.0, 0.0),
+            (70.0, 30.0), (40.0, 30.0), (40.0, 20.0), (30.0, 20.0), (30.0, 30.0), (0.0, 30.0),
+        ];
+        let boundary = boundary.iter().map(|&(x, y)| pvertex(point(x, y), 0.0)).collect();
+        let region = Region::new(boundary, Vec::new());
+        let pocket = try_pocket_region(&region, 1.0, 1.4, &mut cfg).unwrap();
+        // The middle of the corridor is cut by a last pass at the tool radius from the pass at 5.2
+        let last: Vec<&PocketNode> = pocket
+            .nodes
+            .iter()
+            .filter(|node| (node.offset - 4.2).abs() < 1e-9)
+            .collect();
+        assert_eq!(last.len(), 1);
+        assert_eq!(pocket.nodes[last[0].parent.unwrap()].children.len(), 3);
+        assert_covered(&region, &pocket, 1.0, point(0.0, 0.0), point(70.0, 30.0));
+    }
+
+    #[test]
+    fn test_pocket_errors() {
+        let mut cfg = OffsetCfg::default();
+        let region = Region::new(rect(0.0, 0.0, 20.0, 10.0), Vec::new());
+        for (tool_radius, stepover) in [(0.0, 1.0), (1.0, f64::NAN), (1.0, 2.5)] {
+            let res = try_pocket_region(&region, tool_radius, stepover, &mut cfg);
+            assert!(matches!(res, Err(OffsetError::InvalidInput(_))));
+        }
+        // The tool does not fit
+        let res = try_pocket_region(&region, 6.0, 1.0, &mut cfg);
+        assert_eq!(res, Err(OffsetError::Collapsed));
+        assert_eq!(
+            pocket_region(&region, 6.0, 1.0, &mut cfg),
+            Pocket::default()
+        );
+    }
+}
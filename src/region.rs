@@ -0,0 +1,404 @@
+//! Offsetting of regions with holes and of sets of shapes.
+//!
+//! All loops are offset together in one pass, so that
+//! holes growing into each other or into the outer boundary merge or split the region,
+//! and offsets of separate shapes merge where they overlap.
+
+use togo::prelude::*;
+
+use crate::{
+    area::polyline_signed_area,
+    contour_tree::ContourTree,
+    error::OffsetError,
+    observer::polyline_arcs,
+    offset::{OffsetCfg, arcs_to_polylines, check_polyline, offset_single},
+    offset_arcs_raw::poly_to_raws,
+    offset_connect_raw::merge_join_extensions,
+    offset_reconnect_arcs::offset_reconnect_arcs,
+    tolerances::points_size,
+};
+
+/// A closed region: an outer boundary with holes (islands) inside.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Region {
+    /// The outer boundary
+    pub outer: Polyline,
+    /// The holes inside of the outer boundary
+    pub holes: Vec<Polyline>,
+}
+
+impl Region {
+    /// Creates a new region from the outer boundary and the holes.
+    pub fn new(outer: Polyline, holes: Vec<Polyline>) -> Self {
+        Region { outer, holes }
+    }
+}
+
+/// Computes the offset of a Region and returns the resulting Regions.
+///
+/// Positive `off` grows the region (the outer boundary moves outward and the holes shrink),
+/// negative `off` shrinks it.
+/// The orientation of the input loops does not matter.
+///
+/// # Returns
+///
+/// The regions left after the offset, each with its own holes.
+/// Outer boundaries are counter-clockwise and holes are clockwise.
+/// Invalid or degenerate cases produce an empty vector
+/// (use `try_offset_region` to find out why).
+///
+/// # Examples
+///
+/// ```rust
+/// use togo::prelude::*;
+/// use offroad::prelude::*;
+///
+/// let mut cfg = OffsetCfg::default();
+/// let outer = vec![
+///     pvertex(point(0.0, 0.0), 0.0),
+///     pvertex(point(20.0, 0.0), 0.0),
+///     pvertex(point(20.0, 20.0), 0.0),
+///     pvertex(point(0.0, 20.0), 0.0),
+/// ];
+/// let hole = vec![
+///     pvertex(point(8.0, 8.0), 0.0),
+///     pvertex(point(12.0, 8.0), 0.0),
+///     pvertex(point(12.0, 12.0), 0.0),
+///     pvertex(point(8.0, 12.0), 0.0),
+/// ];
+/// let region = Region::new(outer, vec![hole]);
+///
+/// let res = offset_region(&region, -1.0, &mut cfg);
+/// assert_eq!(res.len(), 1);
+/// assert_eq!(res[0].holes.len(), 1);
+///
+/// // The hole closes
+/// let res = offset_region(&region, 3.0, &mut cfg);
+/// assert_eq!(res.len(), 1);
+/// assert!(res[0].holes.is_empty());
+/// ```
+pub fn offset_region(region: &Region, off: f64, cfg: &mut OffsetCfg) -> Vec<Region> {
+    try_offset_region(region, off, cfg).unwrap_or_default()
+}
+
+/// Computes the offset of a Region, reporting failures as `OffsetError`.
+///
+/// # Errors
+///
+/// `OffsetError::InvalidInput` when the outer boundary or a hole encloses no area,
+/// otherwise see `try_offset_polyline_to_polyline`.
+pub fn try_offset_region(
+    region: &Region,
+    off: f64,
+    cfg: &mut OffsetCfg,
+) -> Result<Vec<Region>, OffsetError> {
+    Ok(try_offset_region_tree(region, off, cfg)?.regions())
+}
+
+/// Computes the offset of a Region and returns the containment tree of the resulting loops.
+///
+/// Same as `try_offset_region`, with the nesting of the loops kept: islands left inside
+/// of the holes of the result are children of the holes.
+///
+/// # Errors
+///
+/// See `try_offset_region`.
+pub fn try_offset_region_tree(
+    region: &Region,
+    off: f64,
+    cfg: &mut OffsetCfg,
+) -> Result<ContourTree, OffsetError> {
+    check_polyline(&region.outer, off)?;
+    for hole in region.holes.iter() {
+        check_polyline(hole, off)?;
+    }
+
+    // Offsets go to the right side, it is outside of counter-clockwise loops
+    let grow = off >= 0.0;
+    let mut loops = Vec::with_capacity(1 + region.holes.len());
+    loops.push(orient_loop(&region.outer, grow)?);
+    for hole in region.holes.iter() {
+        loops.push(orient_loop(hole, !grow)?);
+    }
+
+    let final_poly = offset_loops(&loops, off.abs(), cfg)?;
+    Ok(ContourTree::new(final_poly))
+}
+
+/// Computes the offset of a set of closed Polylines and returns the union of the offsets.
+///
+/// Every polyline is treated as a separate shape, independently of its orientation.
+/// Positive `off` grows the shapes and the offsets merge where they overlap
+/// (for example part spacing in nesting), negative `off` shrinks the shapes.
+///
+/// # Returns
+///
+/// The outlines of the union, counter-clockwise, and the gaps enclosed by
+/// the union, clockwise.
+/// Invalid or degenerate cases produce an empty vector
+/// (use `try_offset_polylines` to find out why).
+///
+/// # Examples
+///
+/// ```rust
+/// use togo::prelude::*;
+/// use offroad::prelude::*;
+///
+/// let mut cfg = OffsetCfg::default();
+/// let square = |x: f64| vec![
+///     pvertex(point(x, 0.0), 0.0),
+///     pvertex(point(x + 10.0, 0.0), 0.0),
+///     pvertex(point(x + 10.0, 10.0), 0.0),
+///     pvertex(point(x, 10.0), 0.0),
+/// ];
+/// let parts = vec![square(0.0), square(12.0)];
+///
+/// assert_eq!(offset_polylines(&parts, 0.5, &mut cfg).len(), 2);
+/// // Offsets overlap in the gap between the squares
+/// assert_eq!(offset_polylines(&parts, 1.5, &mut cfg).len(), 1);
+/// ```
+pub fn offset_polylines(polys: &[Polyline], off: f64, cfg: &mut OffsetCfg) -> Vec<Polyline> {
+    try_offset_polylines(polys, off, cfg).unwrap_or_default()
+}
+
+/// Computes the offset of a set of closed Polylines, reporting failures as `OffsetError`.
+///
+/// # Errors
+///
+/// `OffsetError::InvalidInput` when there are no polylines or a polyline encloses no area,
+/// otherwise see `try_offset_polyline_to_polyline`.
+pub fn try_offset_polylines(
+    polys: &[Polyline],
+    off: f64,
+    cfg: &mut OffsetCfg,
+) -> Result<Vec<Polyline>, OffsetError> {
+    if polys.is_empty() {
+        return Err(OffsetError::InvalidInput("no polylines".to_string()));
+    }
+    let grow = off >= 0.0;
+    let mut loops = Vec::with_capacity(polys.len());
+    for poly in polys.iter() {
+        check_polyline(poly, off)?;
+        loops.push(orient_loop(poly, grow)?);
+    }
+
+    let final_poly = offset_loops(&loops, off.abs(), cfg)?;
+    let mut res = Vec::with_capacity(final_poly.len());
+    for region in ContourTree::new(final_poly).regions() {
+        res.push(region.outer);
+        res.extend(region.holes);
+    }
+    Ok(res)
+}
+
+// Offsets all loops together to the right side, pruning checks every loop
+fn offset_loops(
+    loops: &Vec<Polyline>,
+    off: f64,
+    cfg: &mut OffsetCfg,
+) -> Result<Vec<Polyline>, OffsetError> {
+    if let Some(observer) = cfg.observer.as_mut() {
+        let arcs: Vec<Arcline> = loops.iter().map(|poly| polyline_arcs(poly, true)).collect();
+        observer.input(&arcs);
+    }
+    let tol = cfg.options.tolerances_for(points_size(loops.iter().flatten().map(|v| v.p)), off);
+    let poly_raws = poly_to_raws(loops, &tol);
+    let offset_arcs = offset_single(&poly_raws, off, cfg, true, &tol)?;
+    let mut reconnect_arcs = offset_reconnect_arcs(offset_arcs, &cfg.options.budget, &tol)?;
+    if reconnect_arcs.is_empty() {
+        return Err(OffsetError::UnclosedResult);
+    }
+    if let Some(observer) = cfg.observer.as_mut() {
+        observer.reconnected(&reconnect_arcs);
+    }
+    merge_join_extensions(&mut reconnect_arcs, cfg.options.join, true, &tol);
+    if let Some(observer) = cfg.observer.as_mut() {
+        observer.output(&reconnect_arcs);
+    }
+
+    let final_poly = arcs_to_polylines(&reconnect_arcs, &tol);
+    Ok(final_poly)
+}
+
+// Returns the loop in counter-clockwise (ccw = true) or clockwise orientation
+fn orient_loop(poly: &Polyline, ccw: bool) -> Result<Polyline, OffsetError> {
+    let area = polyline_signed_area(poly);
+    if area == 0.0 {
+        return Err(OffsetError::InvalidInput(
+            "region loop encloses no area".to_string(),
+        ));
+    }
+    if (area > 0.0) == ccw {
+        Ok(poly.clone())
+    } else {
+        Ok(polyline_reverse(poly))
+    }
+}
+
+#[cfg(test)]
+mod test_region {
+    use super::*;
+
+    fn rect(x0: f64, y0: f64, x1: f64, y1: f64) -> Polyline {
+        vec![
+            pvertex(point(x0, y0), 0.0),
+            pvertex(point(x1, y0), 0.0),
+            pvertex(point(x1, y1), 0.0),
+            pvertex(point(x0, y1), 0.0),
+        ]
+    }
+
+    fn area(poly: &Polyline) -> f64 {
+        polyline_signed_area(poly)
+    }
+
+    #[test]
+    fn test_offset_region_shrink_keeps_hole() {
+        let mut cfg = OffsetCfg::default();
+        let region = Region::new(rect(0.0, 0.0, 20.0, 20.0), vec![rect(8.0, 8.0, 12.0, 12.0)]);
+        let res = offset_region(&region, -1.0, &mut cfg);
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].holes.len(), 1);
+        assert!((area(&res[0].outer) - 18.0 * 18.0).abs() < 1e-9);
+        // Hole grown by 1.0 with round corners, clockwise
+        let hole_area = 16.0 + 16.0 + std::f64::consts::PI;
+        assert!((area(&res[0].holes[0]) + hole_area).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_offset_region_grow_closes_hole() {
+        let mut cfg = OffsetCfg::default();
+        // Input orientation does not matter
+        let region = Region::new(
+            polyline_reverse(&rect(0.0, 0.0, 20.0, 20.0)),
+            vec![polyline_reverse(&rect(8.0, 8.0, 12.0, 12.0))],
+        );
+        let res = offset_region(&region, 1.0, &mut cfg);
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].holes.len(), 1);
+        assert!((area(&res[0].holes[0]) + 4.0).abs() < 1e-9);
+
+        let res = offset_region(&region, 3.0, &mut cfg);
+        assert_eq!(res.len(), 1);
+        assert!(res[0].holes.is_empty());
+        assert!(area(&res[0].outer) > 0.0);
+    }
+
+    #[test]
+    fn test_offset_region_hole_merges_with_outer() {
+        let mut cfg = OffsetCfg::default();
+        let region = Region::new(rect(0.0, 0.0, 20.0, 20.0), vec![rect(2.0, 2.0, 6.0, 6.0)]);
+        let res = offset_region(&region, -1.5, &mut cfg);
+        assert_eq!(res.len(), 1);
+        assert!(res[0].holes.is_empty());
+    }
+
+    #[test]
+    fn test_offset_region_tree() {
+        let mut cfg = OffsetCfg::default();
+        let region = Region::new(rect(0.0, 0.0, 20.0, 20.0), vec![rect(8.0, 8.0, 12.0, 12.0)]);
+        let tree = try_offset_region_tree(&region, -1.0, &mut cfg).unwrap();
+        assert_eq!(tree.nodes.len(), 2);
+        assert_eq!(tree.roots.len(), 1);
+        let outer = &tree.nodes[tree.roots[0]];
+        assert_eq!(outer.children.len(), 1);
+        assert!((outer.signed_area.abs() - 18.0 * 18.0).abs() < 1e-9);
+        let hole = &tree.nodes[outer.children[0]];
+        assert!(hole.is_hole());
+        assert_eq!(hole.parent, Some(tree.roots[0]));
+    }
+
+    #[test]
+    fn test_offset_region_hole_splits_region() {
+        let mut cfg = OffsetCfg::default();
+        let region = Region::new(rect(0.0, 0.0, 30.0, 10.0), vec![rect(13.0, 3.0, 17.0, 7.0)]);
+        let res = offset_region(&region, -2.5, &mut cfg);
+        assert_eq!(res.len(), 2);
+        for r in res.iter() {
+            assert!(r.holes.is_empty());
+            assert!(area(&r.outer) > 0.0);
+        }
+    }
+
+    #[test]
+    fn test_offset_region_two_holes_merge() {
+        let mut cfg = OffsetCfg::default();
+        let region = Region::new(
+            rect(0.0, 0.0, 30.0, 20.0),
+            vec![rect(5.0, 5.0, 12.0, 15.0), rect(14.0, 5.0, 21.0, 15.0)],
+        );
+        let res = offset_region(&region, -0.5, &mut cfg);
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].holes.len(), 2);
+        let res = offset_region(&region, -1.5, &mut cfg);
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].holes.len(), 1);
+    }
+
+    #[test]
+    fn test_offset_region_invalid() {
+        let mut cfg = OffsetCfg::default();
+        let flat = vec![pvertex(point(0.0, 0.0), 0.0), pvertex(point(10.0, 0.0), 0.0)];
+        let region = Region::new(rect(0.0, 0.0, 20.0, 20.0), vec![flat]);
+        let res = try_offset_region(&region, 1.0, &mut cfg);
+        assert!(matches!(res, Err(OffsetError::InvalidInput(_))));
+        let region = Region::new(rect(0.0, 0.0, 20.0, 20.0), vec![]);
+        let res = try_offset_region(&region, f64::NAN, &mut cfg);
+        assert!(matches!(res, Err(OffsetError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_offset_polylines_merge() {
+        let mut cfg = OffsetCfg::default();
+        let parts = vec![rect(0.0, 0.0, 10.0, 10.0), polyline_reverse(&rect(12.0, 0.0, 22.0, 10.0))];
+        let res = offset_polylines(&parts, 0.5, &mut cfg);
+        assert_eq!(res.len(), 2);
+        let res = offset_polylines(&parts, 1.5, &mut cfg);
+        assert_eq!(res.len(), 1);
+        assert!(area(&res[0]) > 0.0);
+        // Union of two 13x13 rounded squares, the overlap is 1.0 wide
+        // with a lens of two corner circles (r = 1.5, 2.0 apart)
+        let rounded = 13.0 * 13.0 - (4.0 - std::f64::consts::PI) * 1.5 * 1.5;
+        let lens = 2.0 * 1.5 * 1.5 * (1.0f64 / 1.5).acos() - 5.0f64.sqrt();
+        let overlap = 1.0 * 10.0 + lens;
+        let expected = 2.0 * rounded - overlap;
+        assert!((area(&res[0]) - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_offset_polylines_enclosed_gap() {
+        // 3x3 grid of squares without the center one
+        let mut cfg = OffsetCfg::default();
+        let mut parts = Vec::new();
+        for i in 0..3 {
+            for j in 0..3 {
+                if i == 1 && j == 1 {
+                    continue;
+                }
+                let x = i as f64 * 12.0;
+                let y = j as f64 * 12.0;
+                parts.push(rect(x, y, x + 10.0, y + 10.0));
+            }
+        }
+        let res = offset_polylines(&parts, 1.5, &mut cfg);
+        assert_eq!(res.len(), 2);
+        assert!(area(&res[0]) > 0.0);
+        // The center gap is 14x14 with the gaps between the squares,
+        // it shrinks to 11x11 with the corners of the squares around
+        assert!(area(&res[1]) < -121.0);
+        assert!(area(&res[1]) > -122.0);
+    }
+
+    #[test]
+    fn test_offset_polylines_shrink() {
+        let mut cfg = OffsetCfg::default();
+        let parts = vec![rect(0.0, 0.0, 10.0, 10.0), rect(12.0, 0.0, 22.0, 10.0)];
+        let res = offset_polylines(&parts, -1.0, &mut cfg);
+        assert_eq!(res.len(), 2);
+        for poly in res.iter() {
+            assert!((area(poly) - 64.0).abs() < 1e-9);
+        }
+        let res = try_offset_polylines(&[], 1.0, &mut cfg);
+        assert!(matches!(res, Err(OffsetError::InvalidInput(_))));
+    }
+}
@@ -0,0 +1,461 @@
+//! Linking of nested loops into one continuous toolpath.
+//!
+//! The passes of a pocket are nested loops, one stepover apart. Instead of lifting the tool
+//! to move from one loop to the next, the linked path goes on to the next loop with a short
+//! line or arc to its closest point, and cuts all loops without lifting the tool.
+//!
+//! Every link is checked against the walls of the pocket, the outer boundary and
+//! the islands: it must not come closer to the walls than its two ends do. The ends are on
+//! the passes, so a link that passes the check keeps the tool in the material the passes remove.
+
+use togo::prelude::*;
+
+use crate::{
+    area::{arcline_forward, arcline_signed_area_with_tolerance, polyline_contains_point},
+    error::OffsetError,
+    leads::tangent,
+    offset::check_polyline,
+    offset_arcs_raw::poly_to_raws,
+    offset_prune_invalid::{PolyArcs, dist_point_element},
+    region::Region,
+    tolerances::Tolerances,
+};
+
+/// Shape of the links between the loops.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LinkStyle {
+    /// Line to the closest point of the next loop
+    #[default]
+    Line,
+    /// Arc to the closest point of the next loop, tangent to the next loop there.
+    /// Where the arc would be longer than a half circle, or it does not pass the check
+    /// against the walls, the link is a line.
+    Arc,
+}
+
+/// Links a chain of nested loops into one continuous path, see `try_link_loops`.
+///
+/// Invalid input and links that leave the pocket produce an empty path
+/// (use `try_link_loops` to find out why).
+pub fn link_loops(
+    loops: &[Arcline],
+    style: LinkStyle,
+    source: &Region,
+    tol: &Tolerances,
+) -> Arcline {
+    try_link_loops(loops, style, source, tol).unwrap_or_default()
+}
+
+/// Links a chain of nested loops into one continuous path, reporting failures as `OffsetError`.
+///
+/// The loops are cut in the given order, every loop inside of the previous one or
+/// around it, as the loops of `offset_arcline_to_arcline` at growing distances,
+/// or the passes of a chain of `Pocket::chains`.
+/// The first loop is cut from its first point. Every next loop is entered at its point
+/// closest to the end of the previous loop, with a link of the given style, and is cut
+/// all the way around back to the entry point. All loops are cut in the direction of
+/// the first loop.
+///
+/// The `source` is the region the loops are offsets of, the pocket with its islands.
+/// A link must not come closer to its walls than the ends of the link, by more than
+/// `tol.prune`.
+///
+/// The path has the arcs of the loops, split at the entry points, and the links,
+/// in the order they are cut. As in the loops of `offset_arcline_to_arcline`, the arcs are
+/// counter-clockwise and an arc that is cut clockwise goes from `b` to `a`,
+/// every arc continues from the previous one (see `arcline_to_gcode`).
+///
+/// # Errors
+///
+/// - `OffsetError::InvalidInput` when a loop is empty, or two following loops are not nested
+/// - `OffsetError::LinkCollision` when a link comes closer to the walls than its ends
+/// - otherwise see `try_offset_region` for the errors of the source
+///
+/// # Examples
+///
+/// ```rust
+/// use togo::prelude::*;
+/// use offroad::prelude::*;
+///
+/// let mut cfg = OffsetCfg::default();
+/// // Counter-clockwise square, offset to the left is inward
+/// let source = vec![
+///     pvertex(point(0.0, 0.0), 0.0),
+///     pvertex(point(20.0, 0.0), 0.0),
+///     pvertex(point(20.0, 20.0), 0.0),
+///     pvertex(point(0.0, 20.0), 0.0),
+/// ];
+/// let square = vec![
+///     arcseg(point(0.0, 0.0), point(20.0, 0.0)),
+///     arcseg(point(20.0, 0.0), point(20.0, 20.0)),
+///     arcseg(point(20.0, 20.0), point(0.0, 20.0)),
+///     arcseg(point(0.0, 20.0), point(0.0, 0.0)),
+/// ];
+/// let loops: Vec<Arcline> = [1.0, 3.0, 5.0]
+///     .iter()
+///     .map(|&off| offset_arcline_to_arcline(&square, -off, &mut cfg).remove(0))
+///     .collect();
+///
+/// let source = Region::new(source, Vec::new());
+/// let tol = Tolerances::default();
+/// let path = try_link_loops(&loops, LinkStyle::Line, &source, &tol).unwrap();
+/// // The loops and a link between every two of them
+/// assert_eq!(path.len(), 4 + 4 + 4 + 2);
+/// // The loops start at a corner, the links go from corner to corner
+/// let link = 8.0_f64.sqrt();
+/// let links = path.iter().filter(|arc| ((arc.b - arc.a).norm() - link).abs() < 1e-9);
+/// assert_eq!(links.count(), 2);
+/// ```
+pub fn try_link_loops(
+    loops: &[Arcline],
+    style: LinkStyle,
+    source: &Region,
+    tol: &Tolerances,
+) -> Result<Arcline, OffsetError> {
+    let Some(first) = loops.first() else {
+        return Ok(Vec::new());
+    };
+    check_polyline(&source.outer, 0.0)?;
+    for hole in source.holes.iter() {
+        check_polyline(hole, 0.0)?;
+    }
+    let walls: Vec<Polyline> = std::iter::once(&source.outer)
+        .chain(source.holes.iter())
+        .cloned()
+        .collect();
+    let walls = PolyArcs::new(&poly_to_raws(&walls, tol), tol.prune);
+
+    let eps = tol.vertex;
+    let ccw = arcline_signed_area_with_tolerance(first, eps) > 0.0;
+    let mut path = Vec::new();
+    let mut prev: Option<Polyline> = None;
+    let mut end = point(0.0, 0.0);
+    for (k, arcs) in loops.iter().enumerate() {
+        if arcs.is_empty() {
+            return Err(OffsetError::InvalidInput(format!("loop {} is empty", k)));
+        }
+        let arcs = if (arcline_signed_area_with_tolerance(arcs, eps) > 0.0) == ccw {
+            arcs.clone()
+        } else {
+            arcline_reverse(arcs)
+        };
+        let forward = arcline_forward(&arcs, true, eps);
+        let poly = loop_polyline(&arcs, &forward);
+        if let Some(prev) = prev.as_ref()
+            && !polyline_contains_point(prev, poly[0].p)
+            && !polyline_contains_point(&poly, prev[0].p)
+        {
+            return Err(OffsetError::InvalidInput(format!(
+                "loops {} and {} are not nested",
+                k - 1,
+                k
+            )));
+        }
+
+        // Entry point of the loop, the start of the first loop
+        let (i, entry) = if k == 0 {
+            (0, poly[0].p)
+        } else {
+            closest_point(&arcs, end)
+        };
+        if k > 0 && !end.close_enough(entry, eps) {
+            let t = tangent(&arcs[i], forward[i], entry);
+            path.push(link(end, entry, t, style, &walls, tol).ok_or(OffsetError::LinkCollision)?);
+        }
+        path.extend(loop_from(&arcs, &forward, i, entry, eps));
+
+        end = entry;
+        prev = Some(poly);
+    }
+    Ok(path)
+}
+
+// Link from the end of a loop to the entry point of the next loop, that goes on
+// in the direction t. None when the link comes closer to the walls than its ends.
+fn link(
+    end: Point,
+    entry: Point,
+    t: Point,
+    style: LinkStyle,
+    walls: &PolyArcs,
+    tol: &Tolerances,
+) -> Option<Arc> {
+    let wall_dist = |p: Point| {
+        walls
+            .closest_point(p)
+            .map_or(f64::INFINITY, |(dist, _)| dist)
+    };
+    let min_dist = wall_dist(end).min(wall_dist(entry)) - tol.prune;
+    let clear = |arc: &Arc| !walls.closer_than(arc, min_dist);
+    if style == LinkStyle::Arc
+        && let Some(arc) = tangent_arc(end, entry, t, tol.vertex)
+        && clear(&arc)
+    {
+        return Some(arc);
+    }
+    let line = arcseg(end, entry);
+    clear(&line).then_some(line)
+}
+
+// Arc from p0 to p1 that arrives at p1 going in the direction t, at most a half circle.
+// None when p0 is behind p1, or closer than eps to the line of t
+fn tangent_arc(p0: Point, p1: Point, t: Point, eps: f64) -> Option<Arc> {
+    let d = p0 - p1;
+    if d.dot(t) > 0.0 {
+        return None;
+    }
+    // Center on the normal of t at p1, signed radius, positive on the left of t
+    let n = point(-t.y, t.x);
+    let nd = n.dot(d);
+    if nd.abs() < eps {
+        return None;
+    }
+    let r = d.dot(d) / (2.0 * nd);
+    let c = p1 + n * r;
+    // Turning left, the arc is counter-clockwise from p0 to p1
+    if r > 0.0 {
+        Some(arc(p0, p1, c, r))
+    } else {
+        Some(arc(p1, p0, c, -r))
+    }
+}
+
+// The loop as a Polyline, the arcs in their traversal direction
+pub(crate) fn loop_polyline(arcs: &Arcline, forward: &[bool]) -> Polyline {
+    arcs.iter()
+        .zip(forward.iter())
+        .map(|(arc, &forward)| {
+            let bulge = if arc.is_seg() {
+                0.0
+            } else {
+                bulge_from_arc(arc.a, arc.b, arc.c, arc.r)
+            };
+            if forward {
+                pvertex(arc.a, bulge)
+            } else {
+                pvertex(arc.b, -bulge)
+            }
+        })
+        .collect()
+}
+
+// The loop cut from the point on its arc i all the way around, back to the point.
+// Parts of the arc shorter than eps are left out
+pub(crate) fn loop_from(arcs: &Arcline, forward: &[bool], i: usize, p: Point, eps: f64) -> Arcline {
+    let (head, tail) = split_arc(&arcs[i], p, eps);
+    let (first_part, last_part) = if forward[i] {
+        (tail, head)
+    } else {
+        (head, tail)
+    };
+    let mut res = Vec::with_capacity(arcs.len() + 1);
+    res.extend(first_part);
+    res.extend_from_slice(&arcs[i + 1..]);
+    res.extend_from_slice(&arcs[..i]);
+    res.extend(last_part);
+    res
+}
+
+// Index of the arc closest to the point, and the closest point on it
+pub(crate) fn closest_point(arcs: &Arcline, p: Point) -> (usize, Point) {
+    let mut best = (0, p, f64::INFINITY);
+    for (i, arc) in arcs.iter().enumerate() {
+        let (dist, closest) = dist_point_element(arc, p);
+        if dist < best.2 {
+            best = (i, closest, dist);
+        }
+    }
+    (best.0, best.1)
+}
+
+// Parts of the arc from a to the point and from the point to b, none when empty
+fn split_arc(arc: &Arc, p: Point, eps: f64) -> (Option<Arc>, Option<Arc>) {
+    let part = |a: Point, b: Point| {
+        if a.close_enough(b, eps) {
+            None
+        } else if arc.is_seg() {
+            Some(arcseg(a, b))
+        } else {
+            Some(togo::prelude::arc(a, b, arc.c, arc.r))
+        }
+    };
+    if p.close_enough(arc.a, eps) {
+        return (None, Some(*arc));
+    }
+    if p.close_enough(arc.b, eps) {
+        return (Some(*arc), None);
+    }
+    (part(arc.a, p), part(p, arc.b))
+}
+
+#[cfg(test)]
+mod test_spiral {
+    use super::*;
+    use crate::{
+        observer::polyline_arcs,
+        offset::{OffsetCfg, offset_arcline_to_arcline},
+        pocket::try_pocket_region,
+    };
+
+    fn rect(x0: f64, y0: f64, x1: f64, y1: f64) -> Polyline {
+        vec![
+            pvertex(point(x0, y0), 0.0),
+            pvertex(point(x1, y0), 0.0),
+            pvertex(point(x1, y1), 0.0),
+            pvertex(point(x0, y1), 0.0),
+        ]
+    }
+
+    // Counter-clockwise rounded shape, the offsets have arcs
+    fn rounded() -> Polyline {
+        vec![
+            pvertex(point(0.0, 0.0), 0.0),
+            pvertex(point(30.0, 0.0), 0.5),
+            pvertex(point(30.0, 20.0), 0.0),
+            pvertex(point(0.0, 20.0), 0.0),
+        ]
+    }
+
+    // Offsets of the rounded shape inside-out, as a pocket is cut
+    fn rounded_loops() -> Vec<Arcline> {
+        let mut cfg = OffsetCfg::default();
+        let shape = polyline_arcs(&rounded(), true);
+        [7.0, 5.0, 3.0, 1.0]
+            .iter()
+            .map(|&off| offset_arcline_to_arcline(&shape, -off, &mut cfg).remove(0))
+            .collect()
+    }
+
+    // Start and end points of the path arcs, in the cutting order
+    fn path_points(path: &Arcline) -> Vec<(Point, Point)> {
+        path.iter()
+            .zip(arcline_forward(path, false, Tolerances::default().vertex))
+            .map(|(arc, forward)| {
+                if forward {
+                    (arc.a, arc.b)
+                } else {
+                    (arc.b, arc.a)
+                }
+            })
+            .collect()
+    }
+
+    fn assert_continuous(path: &Arcline) {
+        let points = path_points(path);
+        for pair in points.windows(2) {
+            assert!(pair[0].1.close_enough(pair[1].0, 1e-9), "{:?}", pair);
+        }
+    }
+
+    fn length(arcs: &Arcline) -> f64 {
+        arcs.iter()
+            .map(|arc| {
+                if arc.is_seg() {
+                    return (arc.b - arc.a).norm();
+                }
+                let angle = |p: Point| (p.y - arc.c.y).atan2(p.x - arc.c.x);
+                arc.r * (angle(arc.b) - angle(arc.a)).rem_euclid(std::f64::consts::TAU)
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_link_offsets() {
+        let loops = rounded_loops();
+        let source = Region::new(rounded(), Vec::new());
+        let path =
+            try_link_loops(&loops, LinkStyle::Line, &source, &Tolerances::default()).unwrap();
+        assert_continuous(&path);
+        let points = path_points(&path);
+        assert_eq!(points[0].0, path_points(&loops[0])[0].0);
+        // Every loop is cut whole, with three links one stepover long
+        let expected: f64 = loops.iter().map(length).sum::<f64>() + 3.0 * 2.0;
+        assert!((length(&path) - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_link_arcs() {
+        let loops = rounded_loops();
+        let source = Region::new(rounded(), Vec::new());
+        let tol = Tolerances::default();
+        let lines = try_link_loops(&loops, LinkStyle::Line, &source, &tol).unwrap();
+        let path = try_link_loops(&loops, LinkStyle::Arc, &source, &tol).unwrap();
+        assert_continuous(&path);
+        assert_eq!(path.len(), lines.len());
+        // The links that became arcs go on tangent to the loop they enter
+        let forward = arcline_forward(&path, false, tol.vertex);
+        let points = path_points(&path);
+        let mut arcs = 0;
+        for i in 0..path.len() - 1 {
+            if path[i] == lines[i] {
+                continue;
+            }
+            assert!(path[i].is_arc() && lines[i].is_seg());
+            let p = points[i].1;
+            let t0 = tangent(&path[i], forward[i], p);
+            let t1 = tangent(&path[i + 1], forward[i + 1], p);
+            assert!(t0.close_enough(t1, 1e-9), "{:?} {:?}", t0, t1);
+            arcs += 1;
+        }
+        assert!(arcs > 0);
+    }
+
+    #[test]
+    fn test_link_collision() {
+        // The link from the inner square down to the outer one crosses an island
+        let loops = vec![
+            polyline_arcs(&rect(8.0, 6.0, 12.0, 10.0), true),
+            polyline_arcs(&rect(2.0, 2.0, 18.0, 18.0), true),
+        ];
+        let tol = Tolerances::default();
+        let source = Region::new(rect(0.0, 0.0, 20.0, 20.0), Vec::new());
+        let path = try_link_loops(&loops, LinkStyle::Line, &source, &tol).unwrap();
+        assert_eq!(path[4], arcseg(point(8.0, 6.0), point(8.0, 2.0)));
+        let source = Region::new(rect(0.0, 0.0, 20.0, 20.0), vec![rect(7.0, 3.0, 9.0, 5.0)]);
+        for style in [LinkStyle::Line, LinkStyle::Arc] {
+            let res = try_link_loops(&loops, style, &source, &tol);
+            assert_eq!(res, Err(OffsetError::LinkCollision));
+            assert!(link_loops(&loops, style, &source, &tol).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_link_pocket_chain() {
+        let mut cfg = OffsetCfg::default();
+        let region = Region::new(rect(0.0, 0.0, 40.0, 20.0), Vec::new());
+        let pocket = try_pocket_region(&region, 2.0, 3.0, &mut cfg).unwrap();
+        let chains = pocket.chains();
+        assert_eq!(chains.len(), 1);
+        let loops: Vec<Arcline> = chains[0]
+            .iter()
+            .map(|&i| polyline_arcs(&pocket.nodes[i].region.outer, true))
+            .collect();
+        let path = link_loops(&loops, LinkStyle::Arc, &region, &Tolerances::default());
+        assert_continuous(&path);
+        // The path ends on the pass at the tool radius
+        let last = path_points(&path).last().unwrap().1;
+        let outer = loops.last().unwrap();
+        assert!(
+            outer
+                .iter()
+                .any(|arc| dist_point_element(arc, last).0 < 1e-9)
+        );
+    }
+
+    #[test]
+    fn test_link_errors() {
+        let square = |x: f64| polyline_arcs(&rect(x, 0.0, x + 10.0, 10.0), true);
+        let source = Region::new(rect(-10.0, -10.0, 40.0, 20.0), Vec::new());
+        let tol = Tolerances::default();
+        let res = try_link_loops(&[square(0.0), square(20.0)], LinkStyle::Line, &source, &tol);
+        assert!(matches!(res, Err(OffsetError::InvalidInput(_))));
+        let res = try_link_loops(&[square(0.0), Vec::new()], LinkStyle::Line, &source, &tol);
+        assert!(matches!(res, Err(OffsetError::InvalidInput(_))));
+        assert!(link_loops(&[], LinkStyle::Line, &source, &tol).is_empty());
+        // One loop is cut as it is
+        assert_eq!(
+            link_loops(&[square(0.0)], LinkStyle::Line, &source, &tol),
+            square(0.0)
+        );
+    }
+}
@@ -0,0 +1,117 @@
+//! Numeric tolerances of the offsetting stages.
+//!
+//! The default values are tuned for input coordinates around `REFERENCE_SIZE`.
+//! For much smaller or larger input, set `OffsetOptions::auto_tolerances` to scale
+//! them with the size of the input, or set `OffsetOptions::tolerances` directly.
+
+use togo::prelude::*;
+
+use crate::graph::merge_ends::MERGE_TOLERANCE;
+
+/// Size of the input (the largest side of its bounding box) the default tolerances are tuned for.
+pub const REFERENCE_SIZE: f64 = 100.0;
+
+// Default of Tolerances::collapsed, also used by the input validation
+pub(crate) const EPS_COLLAPSED: f64 = 1e-10;
+
+// Default of Tolerances::vertex, also used by the functions without tolerances
+pub(crate) const EPS_VERTEX: f64 = 1e-8;
+
+/// Tolerances used by the offsetting stages.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tolerances {
+    /// Input segments, offsets and split parts shorter than this are degenerate
+    pub collapsed: f64,
+    /// Matching of offset ends when connecting them with corner joins
+    pub connect: f64,
+    /// Offset parts closer than the offset distance minus this to the input are invalid
+    pub prune: f64,
+    /// Offset ends closer than this are merged before reconnecting
+    pub merge: f64,
+    /// Arc ends closer than this are the same vertex when finding cycles and chains
+    pub vertex: f64,
+    /// Arc ends closer than this are connected when converting to polylines
+    pub output: f64,
+}
+
+impl Default for Tolerances {
+    fn default() -> Self {
+        Tolerances {
+            collapsed: EPS_COLLAPSED,
+            connect: 1e-8,
+            prune: 1e-8,
+            merge: MERGE_TOLERANCE,
+            vertex: EPS_VERTEX,
+            output: 1e-10,
+        }
+    }
+}
+
+impl Tolerances {
+    /// Returns all tolerances multiplied by `factor`.
+    pub fn scaled(&self, factor: f64) -> Self {
+        Tolerances {
+            collapsed: self.collapsed * factor,
+            connect: self.connect * factor,
+            prune: self.prune * factor,
+            merge: self.merge * factor,
+            vertex: self.vertex * factor,
+            output: self.output * factor,
+        }
+    }
+
+    /// Returns the tolerances adjusted from `REFERENCE_SIZE` to input of the given size.
+    ///
+    /// Sizes that are zero or not finite leave the tolerances unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use offroad::prelude::*;
+    ///
+    /// // Micron scale input, 0.1 wide
+    /// let tol = Tolerances::default().scaled_to(0.1);
+    /// assert!((tol.prune - 1e-11).abs() < 1e-20);
+    /// ```
+    pub fn scaled_to(&self, size: f64) -> Self {
+        if size.is_finite() && size > 0.0 {
+            self.scaled(size / REFERENCE_SIZE)
+        } else {
+            *self
+        }
+    }
+}
+
+// Largest side of the bounding box of the points
+pub(crate) fn points_size(points: impl IntoIterator<Item = Point>) -> f64 {
+    let mut min = point(f64::INFINITY, f64::INFINITY);
+    let mut max = point(f64::NEG_INFINITY, f64::NEG_INFINITY);
+    for p in points {
+        min = point(min.x.min(p.x), min.y.min(p.y));
+        max = point(max.x.max(p.x), max.y.max(p.y));
+    }
+    (max.x - min.x).max(max.y - min.y)
+}
+
+#[cfg(test)]
+mod test_tolerances {
+    use super::*;
+
+    #[test]
+    fn test_scaled_to() {
+        let tol = Tolerances::default();
+        assert_eq!(tol.scaled_to(REFERENCE_SIZE), tol);
+        assert_eq!(tol.scaled_to(0.0), tol);
+        assert_eq!(tol.scaled_to(f64::NAN), tol);
+        let big = tol.scaled_to(REFERENCE_SIZE * 1000.0);
+        assert!((big.vertex - tol.vertex * 1000.0).abs() < 1e-18);
+        assert!((big.collapsed - tol.collapsed * 1000.0).abs() < 1e-18);
+    }
+
+    #[test]
+    fn test_points_size() {
+        let size = points_size([point(1.0, 2.0), point(4.0, -3.0), point(2.0, 0.0)]);
+        assert_eq!(size, 5.0);
+        assert!(points_size([]) < 0.0);
+    }
+}
@@ -0,0 +1,488 @@
+//! Input validation and repair for Polyline and Arcline before offsetting.
+//!
+//! The offsetting pipeline silently skips degenerate segments. The functions in this
+//! module report such problems as structured issues, and optionally repair them.
+
+use togo::prelude::*;
+
+use crate::tolerances::EPS_COLLAPSED;
+
+/// Bulges with larger magnitude are reported as invalid (bulge 1e5 is already an almost full circle).
+pub const MAX_BULGE: f64 = 1e5;
+
+/// Default tolerance for `repair_polyline` and `repair_arcline`.
+pub const REPAIR_TOLERANCE: f64 = 1e-6;
+
+/// A problem found in the input Polyline or Arcline.
+///
+/// Segment `i` of a Polyline goes from vertex `i` to vertex `i + 1`
+/// (the last segment closes the shape back to vertex 0).
+/// Segment `i` of an Arcline is the arc `i`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationIssue {
+    /// The input has less than 2 segments.
+    TooFewSegments {
+        /// Number of segments in the input
+        count: usize,
+    },
+    /// The segment has a NaN or infinite coordinate.
+    NonFiniteCoordinate {
+        /// Index of the segment
+        segment: usize,
+    },
+    /// The segment has coincident end points (a repeated vertex).
+    DuplicateVertex {
+        /// Index of the segment
+        segment: usize,
+    },
+    /// The last vertex of the Polyline repeats the first one,
+    /// so the closing segment has zero length.
+    RepeatedClosingVertex {
+        /// Index of the closing segment
+        segment: usize,
+    },
+    /// The bulge is NaN, infinite, or larger than `MAX_BULGE` in magnitude.
+    InvalidBulge {
+        /// Index of the segment
+        segment: usize,
+        /// The offending bulge value
+        bulge: f64,
+    },
+    /// The segment is degenerate (for example an inconsistent arc) and would be
+    /// skipped by the offsetting.
+    CollapsedSegment {
+        /// Index of the segment
+        segment: usize,
+    },
+    /// The end of the segment is not connected to the next segment.
+    Gap {
+        /// Index of the segment
+        segment: usize,
+        /// Distance between the closest end points of the segment and the next one
+        distance: f64,
+    },
+}
+
+/// Validates a closed Polyline before offsetting.
+///
+/// Vertices closer than `Tolerances::default().collapsed` are repeated,
+/// see `validate_polyline_with_tolerance` for input of other sizes.
+///
+/// # Arguments
+///
+/// * `poly` - The input polyline, expected to be a closed shape.
+///
+/// # Returns
+///
+/// A list of issues, ordered by segment index. Empty if the polyline is valid.
+///
+/// # Examples
+///
+/// ```rust
+/// use togo::prelude::*;
+/// use offroad::prelude::*;
+///
+/// let poly = vec![
+///     pvertex(point(0.0, 0.0), 0.0),
+///     pvertex(point(10.0, 0.0), 0.0),
+///     pvertex(point(10.0, 10.0), 0.0),
+///     pvertex(point(0.0, 0.0), 0.0), // repeats the first vertex
+/// ];
+/// let issues = validate_polyline(&poly);
+/// assert_eq!(issues, vec![ValidationIssue::RepeatedClosingVertex { segment: 3 }]);
+/// ```
+pub fn validate_polyline(poly: &Polyline) -> Vec<ValidationIssue> {
+    validate_polyline_with_tolerance(poly, EPS_COLLAPSED)
+}
+
+/// Same as `validate_polyline`, vertices closer than `tolerance` are repeated
+/// and segments shorter than it are collapsed.
+pub fn validate_polyline_with_tolerance(poly: &Polyline, tolerance: f64) -> Vec<ValidationIssue> {
+    let mut issues = Vec::new();
+    let n = poly.len();
+    if n < 2 {
+        issues.push(ValidationIssue::TooFewSegments { count: n });
+        return issues;
+    }
+    for i in 0..n {
+        let v0 = poly[i];
+        let v1 = poly[(i + 1) % n];
+        if !is_finite_point(v0.p) || !is_finite_point(v1.p) {
+            issues.push(ValidationIssue::NonFiniteCoordinate { segment: i });
+            continue;
+        }
+        if !v0.b.is_finite() || v0.b.abs() > MAX_BULGE {
+            issues.push(ValidationIssue::InvalidBulge {
+                segment: i,
+                bulge: v0.b,
+            });
+            continue;
+        }
+        if v0.p.close_enough(v1.p, tolerance) {
+            if i == n - 1 {
+                issues.push(ValidationIssue::RepeatedClosingVertex { segment: i });
+            } else {
+                issues.push(ValidationIssue::DuplicateVertex { segment: i });
+            }
+            continue;
+        }
+        let seg = arc_from_bulge(v0.p, v1.p, v0.b);
+        if !seg.is_valid(tolerance) {
+            issues.push(ValidationIssue::CollapsedSegment { segment: i });
+        }
+    }
+    issues
+}
+
+/// Validates a closed Arcline before offsetting.
+///
+/// Arc ends farther apart than `Tolerances::default().collapsed` are gaps,
+/// see `validate_arcline_with_tolerance` for input of other sizes.
+///
+/// # Arguments
+///
+/// * `arcs` - The input arcline, expected to be a closed shape.
+///
+/// # Returns
+///
+/// A list of issues, ordered by arc index. Empty if the arcline is valid.
+///
+/// # Examples
+///
+/// ```rust
+/// use togo::prelude::*;
+/// use offroad::prelude::*;
+///
+/// let arcs = vec![
+///     arcseg(point(0.0, 0.0), point(10.0, 0.0)),
+///     arcseg(point(10.0, 0.0), point(10.0, 10.0)),
+///     arcseg(point(10.0, 10.5), point(0.0, 0.0)), // does not start at (10, 10)
+/// ];
+/// let issues = validate_arcline(&arcs);
+/// assert_eq!(issues, vec![ValidationIssue::Gap { segment: 1, distance: 0.5 }]);
+/// ```
+pub fn validate_arcline(arcs: &Arcline) -> Vec<ValidationIssue> {
+    validate_arcline_with_tolerance(arcs, EPS_COLLAPSED)
+}
+
+/// Same as `validate_arcline`, arc ends farther apart than `tolerance` are gaps
+/// and arcs shorter than it are collapsed.
+///
+/// # Examples
+///
+/// ```rust
+/// use togo::prelude::*;
+/// use offroad::prelude::*;
+///
+/// // Triangle 100 km wide, the ends of the sides are 1e-9 apart
+/// let arcs = vec![
+///     arcseg(point(0.0, 0.0), point(1e5, 0.0)),
+///     arcseg(point(1e5, 1e-9), point(0.0, 1e5)),
+///     arcseg(point(0.0, 1e5), point(0.0, 0.0)),
+/// ];
+/// assert_eq!(validate_arcline(&arcs).len(), 1);
+/// let tol = Tolerances::default().scaled_to(1e5);
+/// assert!(validate_arcline_with_tolerance(&arcs, tol.collapsed).is_empty());
+/// ```
+pub fn validate_arcline_with_tolerance(arcs: &Arcline, tolerance: f64) -> Vec<ValidationIssue> {
+    let mut issues = Vec::new();
+    let n = arcs.len();
+    if n < 2 {
+        issues.push(ValidationIssue::TooFewSegments { count: n });
+        return issues;
+    }
+    for i in 0..n {
+        let seg = arcs[i];
+        if !is_finite_arc(&seg) {
+            issues.push(ValidationIssue::NonFiniteCoordinate { segment: i });
+            continue;
+        }
+        if seg.a.close_enough(seg.b, tolerance) {
+            issues.push(ValidationIssue::DuplicateVertex { segment: i });
+            continue;
+        }
+        if !seg.is_valid(tolerance) {
+            issues.push(ValidationIssue::CollapsedSegment { segment: i });
+            continue;
+        }
+        let next = arcs[(i + 1) % n];
+        if !is_finite_arc(&next) {
+            continue;
+        }
+        let (distance, _, _) = closest_ends(&seg, &next);
+        if distance > tolerance {
+            issues.push(ValidationIssue::Gap {
+                segment: i,
+                distance,
+            });
+        }
+    }
+    issues
+}
+
+/// Repairs a closed Polyline so that it passes `validate_polyline`.
+///
+/// - vertices with NaN or infinite coordinates are removed
+/// - NaN or infinite bulges are replaced with 0 (line segment)
+/// - bulges larger than `MAX_BULGE` are clamped
+/// - repeated vertices (closer than `tol`), including a repeated closing vertex, are removed
+///
+/// # Arguments
+///
+/// * `poly` - The input polyline, expected to be a closed shape.
+/// * `tol` - Distance below which two consecutive vertices are considered the same.
+///
+/// # Returns
+///
+/// The repaired polyline.
+pub fn repair_polyline(poly: &Polyline, tol: f64) -> Polyline {
+    let mut res: Polyline = poly
+        .iter()
+        .filter(|v| is_finite_point(v.p))
+        .map(|v| pvertex(v.p, normalize_bulge(v.b)))
+        .collect();
+
+    // Segment i is degenerate when vertex i and i + 1 coincide,
+    // remove vertex i (and its bulge) and keep the next one.
+    let mut i = 0;
+    while res.len() > 1 && i < res.len() {
+        let next = (i + 1) % res.len();
+        if res[i].p.close_enough(res[next].p, tol) {
+            _ = res.remove(i);
+        } else {
+            i += 1;
+        }
+    }
+    res
+}
+
+/// Repairs a closed Arcline so that it passes `validate_arcline`.
+///
+/// - arcs with NaN or infinite coordinates are removed
+/// - arcs with end points closer than `tol` are removed
+/// - gaps between consecutive arcs smaller than `tol` are closed,
+///   by moving both end points to their middle
+/// - arcs are made consistent (center and radius adjusted to the new end points)
+///
+/// # Arguments
+///
+/// * `arcs` - The input arcline, expected to be a closed shape.
+/// * `tol` - Largest gap that is closed, and smallest arc that is kept.
+///
+/// # Returns
+///
+/// The repaired arcline.
+pub fn repair_arcline(arcs: &Arcline, tol: f64) -> Arcline {
+    let mut res: Arcline = arcs
+        .iter()
+        .filter(|arc| is_finite_arc(arc) && !arc.a.close_enough(arc.b, tol))
+        .copied()
+        .collect();
+
+    let n = res.len();
+    if n < 2 {
+        return res;
+    }
+    for i in 0..n {
+        let next_i = (i + 1) % n;
+        let (distance, end0, end1) = closest_ends(&res[i], &res[next_i]);
+        if distance == 0.0 || distance > tol {
+            continue;
+        }
+        let (p0, p1) = (arc_end(&res[i], end0), arc_end(&res[next_i], end1));
+        let mid = point((p0.x + p1.x) / 2.0, (p0.y + p1.y) / 2.0);
+        set_arc_end(&mut res[i], end0, mid);
+        set_arc_end(&mut res[next_i], end1, mid);
+    }
+    for arc in res.iter_mut() {
+        let id = arc.id;
+        arc.make_consistent();
+        arc.id(id);
+    }
+    res
+}
+
+fn normalize_bulge(bulge: f64) -> f64 {
+    if !bulge.is_finite() {
+        0.0
+    } else {
+        bulge.clamp(-MAX_BULGE, MAX_BULGE)
+    }
+}
+
+fn is_finite_point(p: Point) -> bool {
+    p.x.is_finite() && p.y.is_finite()
+}
+
+fn is_finite_arc(arc: &Arc) -> bool {
+    // Line segments have infinite center and radius
+    is_finite_point(arc.a)
+        && is_finite_point(arc.b)
+        && (arc.is_seg() || (is_finite_point(arc.c) && arc.r.is_finite()))
+}
+
+// Returns the distance between the closest end points of two arcs,
+// and which end (false - a, true - b) of each arc it is.
+fn closest_ends(arc0: &Arc, arc1: &Arc) -> (f64, bool, bool) {
+    let mut best = (f64::INFINITY, false, false);
+    for end0 in [false, true] {
+        for end1 in [false, true] {
+            let dist = (arc_end(arc0, end0) - arc_end(arc1, end1)).norm();
+            if dist < best.0 {
+                best = (dist, end0, end1);
+            }
+        }
+    }
+    best
+}
+
+fn arc_end(arc: &Arc, end: bool) -> Point {
+    if end { arc.b } else { arc.a }
+}
+
+fn set_arc_end(arc: &mut Arc, end: bool, p: Point) {
+    if end {
+        arc.b = p;
+    } else {
+        arc.a = p;
+    }
+}
+
+#[cfg(test)]
+mod test_validate {
+    use super::*;
+
+    fn square() -> Polyline {
+        vec![
+            pvertex(point(0.0, 0.0), 0.0),
+            pvertex(point(10.0, 0.0), 0.0),
+            pvertex(point(10.0, 10.0), 0.0),
+            pvertex(point(0.0, 10.0), 0.0),
+        ]
+    }
+
+    #[test]
+    fn test_validate_polyline_valid() {
+        assert!(validate_polyline(&square()).is_empty());
+        assert!(validate_polyline(&crate::poly::pline_01()[0]).is_empty());
+    }
+
+    #[test]
+    fn test_validate_polyline_too_few() {
+        let poly = vec![pvertex(point(0.0, 0.0), 0.0)];
+        assert_eq!(
+            validate_polyline(&poly),
+            vec![ValidationIssue::TooFewSegments { count: 1 }]
+        );
+    }
+
+    #[test]
+    fn test_validate_polyline_pline_02_closing_vertex() {
+        let poly = crate::poly::pline_02();
+        assert_eq!(
+            validate_polyline(&poly),
+            vec![ValidationIssue::RepeatedClosingVertex { segment: poly.len() - 1 }]
+        );
+    }
+
+    #[test]
+    fn test_validate_polyline_issues() {
+        let mut poly = square();
+        poly.insert(1, pvertex(point(0.0, 0.0), 0.0));
+        poly[2].b = 1e9;
+        poly[4].p.x = f64::INFINITY;
+        assert_eq!(
+            validate_polyline(&poly),
+            vec![
+                ValidationIssue::DuplicateVertex { segment: 0 },
+                ValidationIssue::InvalidBulge {
+                    segment: 2,
+                    bulge: 1e9
+                },
+                ValidationIssue::NonFiniteCoordinate { segment: 3 },
+                ValidationIssue::NonFiniteCoordinate { segment: 4 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_polyline_nan_bulge() {
+        let mut poly = square();
+        poly[1].b = f64::NAN;
+        let issues = validate_polyline(&poly);
+        assert_eq!(issues.len(), 1);
+        assert!(matches!(
+            issues[0],
+            ValidationIssue::InvalidBulge { segment: 1, bulge } if bulge.is_nan()
+        ));
+    }
+
+    #[test]
+    fn test_repair_polyline() {
+        let mut poly = square();
+        poly.insert(1, pvertex(point(0.0, 0.0), 0.0));
+        poly.push(pvertex(point(0.0, 0.0), 0.0));
+        poly[2].b = f64::NAN;
+        poly[3].b = 1e9;
+        let repaired = repair_polyline(&poly, REPAIR_TOLERANCE);
+        assert_eq!(repaired.len(), 4);
+        assert_eq!(repaired[1].b, 0.0);
+        assert_eq!(repaired[2].b, MAX_BULGE);
+        assert!(validate_polyline(&repaired).is_empty());
+    }
+
+    #[test]
+    fn test_repair_pline_02() {
+        let poly = crate::poly::pline_02();
+        let repaired = repair_polyline(&poly, REPAIR_TOLERANCE);
+        assert_eq!(repaired.len(), poly.len() - 1);
+        assert!(validate_polyline(&repaired).is_empty());
+    }
+
+    #[test]
+    fn test_validate_arcline() {
+        let arcs = vec![
+            arcseg(point(0.0, 0.0), point(10.0, 0.0)),
+            arc_from_bulge(point(10.0, 0.0), point(10.0, 10.0), 0.5),
+            arcseg(point(10.0, 10.0), point(0.0, 0.0)),
+        ];
+        assert!(validate_arcline(&arcs).is_empty());
+
+        let arcs = vec![
+            arcseg(point(0.0, 0.0), point(10.0, 0.0)),
+            arcseg(point(10.0, 0.0), point(10.0, 0.0)),
+            arcseg(point(10.0, 0.0), point(10.0, 10.0)),
+            arcseg(point(10.0, 10.0), point(f64::NAN, 0.0)),
+        ];
+        assert_eq!(
+            validate_arcline(&arcs),
+            vec![
+                ValidationIssue::DuplicateVertex { segment: 1 },
+                ValidationIssue::NonFiniteCoordinate { segment: 3 },
+            ]
+        );
+
+        let arcs = vec![
+            arcseg(point(0.0, 0.0), point(10.0, 0.0)),
+            arcseg(point(10.0, 1e-7), point(10.0, 10.0)),
+            arcseg(point(10.0, 10.0), point(0.0, 0.0)),
+        ];
+        let issues = validate_arcline(&arcs);
+        assert_eq!(issues.len(), 1);
+        assert!(matches!(issues[0], ValidationIssue::Gap { segment: 0, distance } if (distance - 1e-7).abs() < 1e-12));
+    }
+
+    #[test]
+    fn test_repair_arcline() {
+        let arcs = vec![
+            arcseg(point(0.0, 0.0), point(10.0, 0.0)),
+            arcseg(point(10.0, 0.0), point(10.0, 1e-8)),
+            arc_from_bulge(point(10.0, 2e-7), point(10.0, 10.0), 0.5),
+            arcseg(point(10.0, 10.0), point(0.0, 1e-7)),
+        ];
+        assert!(!validate_arcline(&arcs).is_empty());
+        let repaired = repair_arcline(&arcs, REPAIR_TOLERANCE);
+        assert_eq!(repaired.len(), 3);
+        assert!(validate_arcline(&repaired).is_empty());
+    }
+}